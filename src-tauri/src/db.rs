@@ -1,5 +1,8 @@
-use rusqlite::{Connection, params};
-use std::path::PathBuf;
+use crate::parser::content_hash;
+use rusqlite::{Connection, OpenFlags, Row, params, params_from_iter};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Mutex;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -12,11 +15,54 @@ pub enum DbError {
     Lock,
     #[error("Not found: {0}")]
     NotFound(String),
+    #[error("Cannot create data directory {}: {source}", path.display())]
+    DataDir { path: PathBuf, source: std::io::Error },
+    #[error("Cannot open database {}: {source}", path.display())]
+    Open { path: PathBuf, source: rusqlite::Error },
+    #[cfg(feature = "sqlcipher")]
+    #[error("Cannot unlock database {}: wrong passphrase, or it isn't encrypted", path.display())]
+    WrongPassphrase { path: PathBuf },
+    #[cfg(not(feature = "sqlcipher"))]
+    #[error("A database passphrase was given but this build has no encryption support (enable the `sqlcipher` feature)")]
+    EncryptionUnsupported,
 }
 
 pub type DbResult<T> = Result<T, DbError>;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// How vectors are stored in the `embedding` column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddingEncoding {
+    /// Four bytes per component, lossless
+    F32,
+    /// A f32 scale followed by one signed byte per component; about 4x smaller
+    Int8,
+}
+
+impl EmbeddingEncoding {
+    pub fn parse(name: &str) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "int8" | "i8" => EmbeddingEncoding::Int8,
+            _ => EmbeddingEncoding::F32,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EmbeddingEncoding::F32 => "f32",
+            EmbeddingEncoding::Int8 => "int8",
+        }
+    }
+
+    /// Number of components in a stored vector of `bytes` bytes
+    fn dimension(self, bytes: usize) -> usize {
+        match self {
+            EmbeddingEncoding::F32 => bytes / 4,
+            EmbeddingEncoding::Int8 => bytes.saturating_sub(4),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Artifact {
     pub id: String,
@@ -24,6 +70,21 @@ pub struct Artifact {
     pub last_modified: i64,
     pub content_hash: String,
     pub indexed_at: i64,
+    pub title: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Always included in chat context
+    #[serde(default)]
+    pub pinned: bool,
+    /// Root of the vault the note was synced from; `None` for other sources
+    #[serde(default)]
+    pub vault: Option<String>,
+    /// Outline collection the document belongs to; `None` for other sources
+    #[serde(default)]
+    pub collection_id: Option<String>,
+    /// Display name of that collection
+    #[serde(default)]
+    pub collection: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,52 +103,394 @@ pub struct ChatMessage {
     pub role: String,
     pub content: String,
     pub timestamp: i64,
+    /// Notes an assistant answer was given as context
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<SourceCitation>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A note cited by an answer, saved with the message so links survive a reload
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceCitation {
+    pub artifact_id: String,
+    pub path: String,
+    /// The note's title, or its path when it has none
+    pub title: String,
+    /// The best-matching chunk from the note
+    pub chunk: String,
+    pub similarity: f32,
+}
+
+/// How often an artifact's chunks were used as chat context
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceUsage {
+    pub artifact: Artifact,
+    pub retrievals: usize,
+}
+
+/// A chunk cited by rated answers, with how those answers were received
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoisyChunk {
+    pub artifact: Artifact,
+    pub chunk_index: i32,
+    pub content: String,
+    /// Answers that used the chunk as context, rated or not
+    pub retrievals: usize,
+    pub upvotes: usize,
+    pub downvotes: usize,
+}
+
+/// One artifact with metadata derived from its stored chunks
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactDetails {
+    pub artifact: Artifact,
+    pub chunk_count: usize,
+}
+
+/// Result of pruning the embedding cache
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachePruneReport {
+    /// Cached vectors no current chunk uses that were deleted
+    pub removed_entries: usize,
+    pub reclaimed_bytes: u64,
+    /// Nothing was pruned because a sync was running
+    pub skipped: bool,
+}
+
+/// Result of checking (and optionally repairing) the database
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityReport {
+    /// Whether `PRAGMA integrity_check` reported "ok"
+    pub integrity_ok: bool,
+    pub integrity_errors: Vec<String>,
+    /// Embeddings whose artifact no longer exists
+    pub orphaned_embeddings: usize,
+    /// Embeddings with an empty or misaligned vector blob
+    pub invalid_embeddings: usize,
+    /// Extra rows for an artifact chunk that was stored more than once
+    pub duplicate_chunks: usize,
+    /// Whether the problems above were deleted
+    pub repaired: bool,
+}
+
+/// Artifacts with identical content collapsed into the one indexed last
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
+pub struct ArtifactMerge {
+    pub kept_path: String,
+    pub removed_paths: Vec<String>,
+    /// Embeddings deleted along with the removed artifacts
+    pub removed_embeddings: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 pub struct Settings {
-    pub vault_path: String,
+    /// Folders indexed as vaults
+    pub vault_paths: Vec<String>,
+    /// Chat backend; only `ollama` is supported
+    pub llm_provider: String,
     pub ollama_endpoint: String,
+    /// Server for the embedding model; `ollama_endpoint` when empty
+    pub embedding_endpoint: String,
     pub ollama_model: String,
     pub embedding_model: String,
     pub outline_api_key: String,
     pub outline_base_url: String,
+    /// Notes with fewer plain-text words than this are not indexed
+    pub min_word_count: usize,
+    /// Snap chunk overlaps to sentence boundaries
+    pub snap_chunk_overlap: bool,
+    /// How notes are chunked: `words` or `tokens`
+    pub chunk_strategy: String,
+    /// Largest chunk, in tokens, with the `tokens` strategy
+    pub chunk_max_tokens: usize,
+    /// Prepended to chunks before embedding (e.g. `search_document: `)
+    pub embedding_document_prefix: String,
+    /// Prepended to queries before embedding (e.g. `search_query: `)
+    pub embedding_query_prefix: String,
+    /// Embedding request body: `prompt` (Ollama), `input` or `input_array`
+    pub embedding_request_format: String,
+    /// Embedding response shape: `embedding` (Ollama), `embeddings` or `data`
+    pub embedding_response_format: String,
+    /// Text sent for embedding is cut to this many bytes, for servers that
+    /// reject longer input (0 = no limit)
+    pub embedding_max_input_bytes: usize,
+    /// Files larger than this many megabytes are skipped (0 = no limit)
+    pub max_file_size_mb: u64,
+    /// Follow symlinks while scanning the vault
+    pub follow_symlinks: bool,
+    /// Index link URLs alongside their text
+    pub include_link_urls: bool,
+    /// Index image sources alongside their alt text
+    pub include_image_sources: bool,
+    /// Lowercase frontmatter tags when indexing
+    pub lowercase_tags: bool,
+    /// Index local text files a note links to as part of the note
+    pub index_attachments: bool,
+    /// Retrieval score multipliers keyed by `#tag` or vault path prefix
+    pub retrieval_boosts: HashMap<String, f32>,
+    /// One of `cosine`, `dot_product` or `euclidean`
+    pub similarity_metric: String,
+    /// Minimum score (maximum distance for Euclidean); the metric's default when unset
+    pub similarity_threshold: Option<f32>,
+    /// Floor each expanded query's results must pass before merging; the threshold when unset
+    pub subquery_similarity_floor: Option<f32>,
+    /// Retrieved chunks more cosine-similar than this to a better one are dropped; 1.0 disables
+    pub near_duplicate_threshold: f32,
+    /// Collapse retrieved chunks of notes with the same content hash, whatever
+    /// source they came from, keeping the one from the preferred source
+    pub cross_source_dedup: bool,
+    /// Path prefixes (e.g. a vault path or `outline://`) in order of preference
+    /// for cross-source dedup; unlisted sources come last
+    pub source_priority: Vec<String>,
+    /// Answer only from retrieved notes, flagging weakly grounded answers
+    pub grounded_answers_only: bool,
+    /// Keep a local log of which notes were used to answer each question
+    pub track_retrieval: bool,
+    /// Cap on generated tokens (Ollama's `num_predict`); unlimited when unset
+    pub max_tokens: Option<u32>,
+    /// Context window requested from the model (Ollama's `num_ctx`), in
+    /// tokens; retrieved chunks are fitted into half of it
+    pub context_window_tokens: u32,
+    /// Generation stops before any of these sequences
+    pub stop_sequences: Vec<String>,
+    /// Sampling seed for reproducible answers; random when unset. Answers only
+    /// repeat while the model and its temperature stay the same
+    pub seed: Option<u32>,
+    /// Fall back to typo-tolerant keyword matching when semantic results are weak
+    pub fuzzy_keyword_fallback: bool,
+    /// Minimum trigram keyword score (0-1) for a fallback match
+    pub fuzzy_match_threshold: f32,
+    /// Number of recent searches to cache in memory (0 disables the cache)
+    pub search_cache_size: usize,
+    /// Sources keyed by path prefix (e.g. `outline://`); `false` hides them
+    /// from retrieval without deleting their data. Unlisted sources are enabled.
+    pub enabled_sources: HashMap<String, bool>,
+    /// How new embeddings are stored: `f32` (exact) or `int8` (quantized, ~4x smaller)
+    pub embedding_encoding: String,
+    /// A watched file is reindexed once it has gone this long without changes
+    pub reindex_debounce_ms: u64,
+    /// Language answers are requested in: `off`, `auto` (the question's) or a language name
+    pub response_language: String,
+    /// Treat note paths differing only in case as the same note. Defaults to
+    /// on where the filesystem ignores case (macOS, Windows)
+    pub case_insensitive_paths: bool,
+    /// Use earlier chat messages as context; off answers each question on its own
+    pub use_chat_context: bool,
+    /// Hidden directories scanned despite the leading dot, by name (e.g. `.notes`)
+    pub allowed_hidden_dirs: Vec<String>,
+    /// Folder in each vault where deleted notes go; notes in it are treated as deleted
+    pub trash_folder: String,
+    /// Skip sync progress events for unchanged files, reporting only files being embedded
+    pub quiet_unchanged_files: bool,
+    /// Alternative queries the LLM may add to each question; 0 disables expansion
+    pub max_query_expansions: usize,
+    /// Skip expansion while fewer chunks than this are indexed, where one
+    /// search already scans everything quickly
+    pub expansion_min_embeddings: usize,
 }
 
+/// Whether the platform's default filesystem ignores path case
+const CASE_INSENSITIVE_FILESYSTEM: bool = cfg!(any(target_os = "macos", target_os = "windows"));
+
 impl Default for Settings {
     fn default() -> Self {
         Self {
-            vault_path: String::new(),
+            vault_paths: Vec::new(),
+            llm_provider: "ollama".to_string(),
             ollama_endpoint: "http://localhost:11434".to_string(),
+            embedding_endpoint: String::new(),
             ollama_model: "llama3.2".to_string(),
             embedding_model: "nomic-embed-text".to_string(),
             outline_api_key: String::new(),
             outline_base_url: "https://app.getoutline.com/api".to_string(),
+            min_word_count: 3,
+            snap_chunk_overlap: true,
+            chunk_strategy: "words".to_string(),
+            chunk_max_tokens: 512,
+            embedding_document_prefix: String::new(),
+            embedding_query_prefix: String::new(),
+            embedding_request_format: "prompt".to_string(),
+            embedding_response_format: "embedding".to_string(),
+            embedding_max_input_bytes: 8192,
+            max_file_size_mb: 5,
+            follow_symlinks: false,
+            include_link_urls: false,
+            include_image_sources: false,
+            lowercase_tags: false,
+            index_attachments: false,
+            retrieval_boosts: HashMap::new(),
+            similarity_metric: "cosine".to_string(),
+            similarity_threshold: None,
+            subquery_similarity_floor: None,
+            near_duplicate_threshold: 0.97,
+            cross_source_dedup: false,
+            source_priority: Vec::new(),
+            grounded_answers_only: false,
+            track_retrieval: false,
+            max_tokens: None,
+            context_window_tokens: 8192,
+            seed: None,
+            stop_sequences: Vec::new(),
+            fuzzy_keyword_fallback: false,
+            fuzzy_match_threshold: 0.5,
+            search_cache_size: 0,
+            enabled_sources: HashMap::new(),
+            embedding_encoding: "f32".to_string(),
+            reindex_debounce_ms: 2000,
+            response_language: "off".to_string(),
+            case_insensitive_paths: CASE_INSENSITIVE_FILESYSTEM,
+            use_chat_context: true,
+            allowed_hidden_dirs: Vec::new(),
+            trash_folder: ".trash".to_string(),
+            quiet_unchanged_files: false,
+            max_query_expansions: 3,
+            expansion_min_embeddings: 200,
         }
     }
 }
 
+/// Artifact columns in the order expected by `row_to_artifact`; tags are
+/// aggregated from `artifact_tags` as a JSON array
+const ARTIFACT_COLUMNS: &str = "id, path, last_modified, content_hash, indexed_at, title,
+    (SELECT json_group_array(tag) FROM artifact_tags WHERE artifact_id = artifacts.id), pinned, vault,
+    collection_id, collection";
+
+fn row_to_artifact(row: &rusqlite::Row) -> rusqlite::Result<Artifact> {
+    let tags_json: Option<String> = row.get(6)?;
+    Ok(Artifact {
+        id: row.get(0)?,
+        path: row.get(1)?,
+        last_modified: row.get(2)?,
+        content_hash: row.get(3)?,
+        indexed_at: row.get(4)?,
+        title: row.get(5)?,
+        tags: tags_json
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
+        pinned: row.get(7)?,
+        vault: row.get(8)?,
+        collection_id: row.get(9)?,
+        collection: row.get(10)?,
+    })
+}
+
+/// Key an SQLCipher connection. The key is only checked on first read, so
+/// read the schema to turn a wrong passphrase into a clear error.
+#[cfg(feature = "sqlcipher")]
+fn unlock(conn: &Connection, passphrase: &str, path: &Path) -> DbResult<()> {
+    conn.pragma_update(None, "key", passphrase)?;
+    match conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0)) {
+        Ok(_) => Ok(()),
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == rusqlite::ErrorCode::NotADatabase => {
+            Err(DbError::WrongPassphrase { path: path.to_path_buf() })
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+fn unlock(_conn: &Connection, _passphrase: &str, _path: &Path) -> DbResult<()> {
+    Err(DbError::EncryptionUnsupported)
+}
+
+/// Add a column to an existing table when an older database lacks it
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> DbResult<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(|r| r.ok())
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
 pub struct Database {
     conn: Mutex<Connection>,
+    /// Bumped whenever stored embeddings change, so caches can tell they're stale
+    embeddings_version: AtomicU64,
+    /// Encoding used for newly inserted embeddings; existing rows keep theirs
+    embedding_encoding: Mutex<EmbeddingEncoding>,
+    /// Match artifact paths ignoring (ASCII) case
+    case_insensitive_paths: AtomicBool,
 }
 
 impl Database {
     pub fn new(app_data_dir: PathBuf) -> DbResult<Self> {
-        std::fs::create_dir_all(&app_data_dir).ok();
+        Self::with_passphrase(app_data_dir, None)
+    }
+
+    /// Open the database, unlocking it with `passphrase` when built with the
+    /// `sqlcipher` feature. A new database is encrypted with the passphrase.
+    pub fn with_passphrase(app_data_dir: PathBuf, passphrase: Option<&str>) -> DbResult<Self> {
+        std::fs::create_dir_all(&app_data_dir).map_err(|source| DbError::DataDir {
+            path: app_data_dir.clone(),
+            source,
+        })?;
         let db_path = app_data_dir.join("metabrain.db");
-        let conn = Connection::open(db_path)?;
-        
-        let db = Self {
-            conn: Mutex::new(conn),
-        };
+        let conn = Connection::open(&db_path).map_err(|source| DbError::Open { path: db_path.clone(), source })?;
+        if let Some(passphrase) = passphrase {
+            unlock(&conn, passphrase, &db_path)?;
+        }
         
+        let db = Self::from_connection(conn);
         db.initialize()?;
+        db.apply_stored_settings()?;
+        Ok(db)
+    }
+
+    /// Open an existing database without write access, for external tools
+    /// that read a database the app may have open. An encrypted database is
+    /// unlocked with `passphrase` as in `with_passphrase`. The schema is
+    /// assumed to exist and every write fails with SQLite's read-only error.
+    #[allow(dead_code, reason = "entry point for read-only tooling; the app opens the database with `new`")]
+    pub fn open_read_only(app_data_dir: &Path, passphrase: Option<&str>) -> DbResult<Self> {
+        let db_path = app_data_dir.join("metabrain.db");
+        let conn = Connection::open_with_flags(
+            &db_path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .map_err(|source| DbError::Open { path: db_path.clone(), source })?;
+        if let Some(passphrase) = passphrase {
+            unlock(&conn, passphrase, &db_path)?;
+        }
+
+        let db = Self::from_connection(conn);
+        db.apply_stored_settings()?;
         Ok(db)
     }
 
+    fn from_connection(conn: Connection) -> Self {
+        Self {
+            conn: Mutex::new(conn),
+            embeddings_version: AtomicU64::new(0),
+            embedding_encoding: Mutex::new(EmbeddingEncoding::F32),
+            case_insensitive_paths: AtomicBool::new(CASE_INSENSITIVE_FILESYSTEM),
+        }
+    }
+
+    /// Load the stored settings that change how rows are written and matched
+    fn apply_stored_settings(&self) -> DbResult<()> {
+        let settings = self.get_settings()?;
+        self.set_embedding_encoding(EmbeddingEncoding::parse(&settings.embedding_encoding))?;
+        self.case_insensitive_paths.store(settings.case_insensitive_paths, Ordering::SeqCst);
+        Ok(())
+    }
+
     fn initialize(&self) -> DbResult<()> {
         let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+
+        // Enforce ON DELETE CASCADE for embeddings and tags
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
         
         // Create artifacts table
         conn.execute(
@@ -96,11 +499,37 @@ impl Database {
                 path TEXT NOT NULL UNIQUE,
                 last_modified INTEGER NOT NULL,
                 content_hash TEXT NOT NULL,
-                indexed_at INTEGER NOT NULL
+                indexed_at INTEGER NOT NULL,
+                title TEXT,
+                pinned INTEGER NOT NULL DEFAULT 0,
+                vault TEXT,
+                collection_id TEXT,
+                collection TEXT
+            )",
+            [],
+        )?;
+        add_column_if_missing(&conn, "artifacts", "title", "TEXT")?;
+        add_column_if_missing(&conn, "artifacts", "pinned", "INTEGER NOT NULL DEFAULT 0")?;
+        add_column_if_missing(&conn, "artifacts", "vault", "TEXT")?;
+        add_column_if_missing(&conn, "artifacts", "collection_id", "TEXT")?;
+        add_column_if_missing(&conn, "artifacts", "collection", "TEXT")?;
+
+        // Create artifact_tags table
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS artifact_tags (
+                artifact_id TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (artifact_id, tag),
+                FOREIGN KEY (artifact_id) REFERENCES artifacts(id) ON DELETE CASCADE
             )",
             [],
         )?;
 
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_artifact_tags_tag ON artifact_tags(tag)",
+            [],
+        )?;
+
         // Create embeddings table
         conn.execute(
             "CREATE TABLE IF NOT EXISTS embeddings (
@@ -109,24 +538,115 @@ impl Database {
                 chunk_index INTEGER NOT NULL,
                 content TEXT NOT NULL,
                 embedding BLOB NOT NULL,
+                chunk_hash TEXT,
+                encoding TEXT NOT NULL DEFAULT 'f32',
                 FOREIGN KEY (artifact_id) REFERENCES artifacts(id) ON DELETE CASCADE
             )",
             [],
         )?;
 
+        add_column_if_missing(&conn, "embeddings", "chunk_hash", "TEXT")?;
+        add_column_if_missing(&conn, "embeddings", "encoding", "TEXT NOT NULL DEFAULT 'f32'")?;
+
         // Create index on artifact_id for faster lookups
         conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_embeddings_artifact_id ON embeddings(artifact_id)",
             [],
         )?;
 
+        // Create embedding_cache table (vectors by chunk content, reused across notes)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS embedding_cache (
+                content_hash TEXT NOT NULL,
+                model TEXT NOT NULL,
+                embedding BLOB NOT NULL,
+                encoding TEXT NOT NULL DEFAULT 'f32',
+                PRIMARY KEY (content_hash, model)
+            )",
+            [],
+        )?;
+
         // Create chat_messages table
         conn.execute(
             "CREATE TABLE IF NOT EXISTS chat_messages (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 role TEXT NOT NULL,
                 content TEXT NOT NULL,
-                timestamp INTEGER NOT NULL
+                timestamp INTEGER NOT NULL,
+                sources TEXT
+            )",
+            [],
+        )?;
+        add_column_if_missing(&conn, "chat_messages", "sources", "TEXT")?;
+
+        // Create model_dimensions table (embedding model -> vector dimension seen)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS model_dimensions (
+                model TEXT PRIMARY KEY,
+                dimension INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // Create retrieval_log table (artifacts used as context, per query)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS retrieval_log (
+                query_id TEXT NOT NULL,
+                artifact_id TEXT NOT NULL,
+                retrieved_at INTEGER NOT NULL,
+                FOREIGN KEY (artifact_id) REFERENCES artifacts(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_retrieval_log_artifact ON retrieval_log(artifact_id)",
+            [],
+        )?;
+
+        // Create answer_chunks table (chunks given as context to each answer)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS answer_chunks (
+                message_id INTEGER NOT NULL,
+                artifact_id TEXT NOT NULL,
+                chunk_index INTEGER NOT NULL,
+                PRIMARY KEY (message_id, artifact_id, chunk_index),
+                FOREIGN KEY (message_id) REFERENCES chat_messages(id) ON DELETE CASCADE,
+                FOREIGN KEY (artifact_id) REFERENCES artifacts(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        // Create message_feedback table (thumbs up/down on assistant answers)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS message_feedback (
+                message_id INTEGER PRIMARY KEY,
+                helpful INTEGER NOT NULL,
+                rated_at INTEGER NOT NULL,
+                FOREIGN KEY (message_id) REFERENCES chat_messages(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        // Create artifact_summaries table (saved on-demand note summaries)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS artifact_summaries (
+                artifact_id TEXT PRIMARY KEY,
+                content_hash TEXT NOT NULL,
+                summary TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                FOREIGN KEY (artifact_id) REFERENCES artifacts(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        // Create sync_checkpoints table (files finished by a sync that hasn't completed)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS sync_checkpoints (
+                vault_path TEXT NOT NULL,
+                path TEXT NOT NULL,
+                content_hash TEXT NOT NULL,
+                PRIMARY KEY (vault_path, path)
             )",
             [],
         )?;
@@ -148,39 +668,101 @@ impl Database {
     pub fn upsert_artifact(&self, artifact: &Artifact) -> DbResult<()> {
         let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
         conn.execute(
-            "INSERT INTO artifacts (id, path, last_modified, content_hash, indexed_at)
-             VALUES (?1, ?2, ?3, ?4, ?5)
+            "INSERT INTO artifacts (id, path, last_modified, content_hash, indexed_at, title, vault,
+                collection_id, collection)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
              ON CONFLICT(id) DO UPDATE SET
                 path = excluded.path,
                 last_modified = excluded.last_modified,
                 content_hash = excluded.content_hash,
-                indexed_at = excluded.indexed_at",
+                indexed_at = excluded.indexed_at,
+                title = excluded.title,
+                vault = excluded.vault,
+                collection_id = excluded.collection_id,
+                collection = excluded.collection",
             params![
                 artifact.id,
                 artifact.path,
                 artifact.last_modified,
                 artifact.content_hash,
-                artifact.indexed_at
+                artifact.indexed_at,
+                artifact.title,
+                artifact.vault,
+                artifact.collection_id,
+                artifact.collection
             ],
         )?;
+
+        // Replace the artifact's tags
+        conn.execute("DELETE FROM artifact_tags WHERE artifact_id = ?1", [&artifact.id])?;
+        for tag in &artifact.tags {
+            let tag = tag.trim().trim_start_matches('#');
+            if tag.is_empty() {
+                continue;
+            }
+            conn.execute(
+                "INSERT OR IGNORE INTO artifact_tags (artifact_id, tag) VALUES (?1, ?2)",
+                params![artifact.id, tag],
+            )?;
+        }
         Ok(())
     }
 
+    pub fn get_artifact_by_id(&self, id: &str) -> DbResult<Option<Artifact>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut stmt = conn.prepare(
+            &format!("SELECT {} FROM artifacts WHERE id = ?1", ARTIFACT_COLUMNS)
+        )?;
+
+        match stmt.query_row([id], row_to_artifact) {
+            Ok(artifact) => Ok(Some(artifact)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(DbError::Sqlite(e)),
+        }
+    }
+
+    /// An artifact and its chunk count, or `NotFound` for an unknown id
+    pub fn get_artifact_details(&self, id: &str) -> DbResult<ArtifactDetails> {
+        let artifact = self.get_artifact_by_id(id)?.ok_or_else(|| DbError::NotFound(id.to_string()))?;
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let chunk_count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM embeddings WHERE artifact_id = ?1",
+            [id],
+            |row| row.get(0),
+        )?;
+
+        Ok(ArtifactDetails { artifact, chunk_count: chunk_count as usize })
+    }
+
+    /// Artifacts indexed or modified after `since` (unix seconds) with their
+    /// chunk counts, most recent change first
+    pub fn get_artifacts_changed_since(&self, since: i64) -> DbResult<Vec<ArtifactDetails>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, (SELECT COUNT(*) FROM embeddings WHERE embeddings.artifact_id = artifacts.id)
+             FROM artifacts
+             WHERE indexed_at > ?1 OR last_modified > ?1
+             ORDER BY MAX(indexed_at, last_modified) DESC, path",
+            ARTIFACT_COLUMNS
+        ))?;
+
+        let artifacts = stmt.query_map([since], |row| {
+            Ok(ArtifactDetails {
+                artifact: row_to_artifact(row)?,
+                chunk_count: row.get::<_, i64>(11)? as usize,
+            })
+        })?.filter_map(|r| r.ok()).collect();
+
+        Ok(artifacts)
+    }
+
     pub fn get_artifact_by_path(&self, path: &str) -> DbResult<Option<Artifact>> {
         let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
         let mut stmt = conn.prepare(
-            "SELECT id, path, last_modified, content_hash, indexed_at FROM artifacts WHERE path = ?1"
+            &format!("SELECT {} FROM artifacts WHERE {}", ARTIFACT_COLUMNS, self.path_matches())
         )?;
         
-        let result = stmt.query_row([path], |row| {
-            Ok(Artifact {
-                id: row.get(0)?,
-                path: row.get(1)?,
-                last_modified: row.get(2)?,
-                content_hash: row.get(3)?,
-                indexed_at: row.get(4)?,
-            })
-        });
+        let result = stmt.query_row([path], row_to_artifact);
 
         match result {
             Ok(artifact) => Ok(Some(artifact)),
@@ -189,28 +771,72 @@ impl Database {
         }
     }
 
+    pub fn get_artifacts_by_hash(&self, content_hash: &str) -> DbResult<Vec<Artifact>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut stmt = conn.prepare(
+            &format!("SELECT {} FROM artifacts WHERE content_hash = ?1", ARTIFACT_COLUMNS)
+        )?;
+        
+        let artifacts = stmt
+            .query_map([content_hash], row_to_artifact)?
+            .filter_map(|r| r.ok())
+            .collect();
+        
+        Ok(artifacts)
+    }
+
+    /// Artifacts with no stored embeddings, e.g. left by an interrupted sync
+    pub fn get_artifacts_without_embeddings(&self) -> DbResult<Vec<Artifact>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM artifacts
+             WHERE NOT EXISTS (SELECT 1 FROM embeddings WHERE embeddings.artifact_id = artifacts.id)
+             ORDER BY path",
+            ARTIFACT_COLUMNS
+        ))?;
+
+        let artifacts = stmt
+            .query_map([], row_to_artifact)?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(artifacts)
+    }
+
     pub fn get_all_artifacts(&self) -> DbResult<Vec<Artifact>> {
         let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
         let mut stmt = conn.prepare(
-            "SELECT id, path, last_modified, content_hash, indexed_at FROM artifacts"
+            &format!("SELECT {} FROM artifacts", ARTIFACT_COLUMNS)
         )?;
         
-        let artifacts = stmt.query_map([], |row| {
-            Ok(Artifact {
-                id: row.get(0)?,
-                path: row.get(1)?,
-                last_modified: row.get(2)?,
-                content_hash: row.get(3)?,
-                indexed_at: row.get(4)?,
-            })
-        })?.filter_map(|r| r.ok()).collect();
+        let artifacts = stmt
+            .query_map([], row_to_artifact)?
+            .filter_map(|r| r.ok())
+            .collect();
         
         Ok(artifacts)
     }
 
+    /// Pin or unpin an artifact; re-indexing keeps the flag
+    pub fn set_artifact_pinned(&self, id: &str, pinned: bool) -> DbResult<()> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let updated = conn.execute("UPDATE artifacts SET pinned = ?1 WHERE id = ?2", params![pinned, id])?;
+        if updated == 0 {
+            return Err(DbError::NotFound(id.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn count_artifacts(&self) -> DbResult<usize> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM artifacts", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
     pub fn delete_artifact(&self, id: &str) -> DbResult<()> {
         let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
         conn.execute("DELETE FROM artifacts WHERE id = ?1", [id])?;
+        self.embeddings_changed();
         Ok(())
     }
 
@@ -218,92 +844,611 @@ impl Database {
         let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
         // First delete embeddings
         conn.execute(
-            "DELETE FROM embeddings WHERE artifact_id IN (SELECT id FROM artifacts WHERE path = ?1)",
+            &format!(
+                "DELETE FROM embeddings WHERE artifact_id IN (SELECT id FROM artifacts WHERE {})",
+                self.path_matches()
+            ),
             [path],
         )?;
         // Then delete artifact
-        conn.execute("DELETE FROM artifacts WHERE path = ?1", [path])?;
+        conn.execute(&format!("DELETE FROM artifacts WHERE {}", self.path_matches()), [path])?;
+        self.embeddings_changed();
         Ok(())
     }
 
+    /// Delete every artifact whose path starts with `prefix` (e.g. `outline://`),
+    /// cascading to their embeddings. Returns the number of artifacts removed.
+    pub fn delete_artifacts_by_prefix(&self, prefix: &str) -> DbResult<usize> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let removed = conn.execute(
+            "DELETE FROM artifacts WHERE substr(path, 1, length(?1)) = ?1",
+            [prefix],
+        )?;
+        self.embeddings_changed();
+        Ok(removed)
+    }
+
+    /// Collapse artifacts sharing a content hash (e.g. a moved file indexed
+    /// under both paths) into the most recently indexed one
+    pub fn deduplicate_artifacts(&self) -> DbResult<Vec<ArtifactMerge>> {
+        let mut groups: HashMap<String, Vec<Artifact>> = HashMap::new();
+        for artifact in self.get_all_artifacts()? {
+            if !artifact.content_hash.is_empty() {
+                groups.entry(artifact.content_hash.clone()).or_default().push(artifact);
+            }
+        }
+
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut merges = Vec::new();
+
+        for mut group in groups.into_values().filter(|g| g.len() > 1) {
+            group.sort_by(|a, b| b.indexed_at.cmp(&a.indexed_at).then_with(|| a.path.cmp(&b.path)));
+            let kept = group.remove(0);
+
+            let mut removed_embeddings = 0;
+            for duplicate in &group {
+                let count: i64 = conn.query_row(
+                    "SELECT COUNT(*) FROM embeddings WHERE artifact_id = ?1",
+                    [&duplicate.id],
+                    |row| row.get(0),
+                )?;
+                removed_embeddings += count as usize;
+                conn.execute("DELETE FROM artifacts WHERE id = ?1", [&duplicate.id])?;
+            }
+
+            // Don't lose a pin that was set on one of the copies
+            if !kept.pinned && group.iter().any(|a| a.pinned) {
+                conn.execute("UPDATE artifacts SET pinned = 1 WHERE id = ?1", [&kept.id])?;
+            }
+
+            merges.push(ArtifactMerge {
+                kept_path: kept.path,
+                removed_paths: group.into_iter().map(|a| a.path).collect(),
+                removed_embeddings,
+            });
+        }
+
+        if !merges.is_empty() {
+            self.embeddings_changed();
+        }
+        Ok(merges)
+    }
+
     // === Embedding Methods ===
 
+    /// Changes whenever embeddings are inserted or deleted
+    pub fn embeddings_version(&self) -> u64 {
+        self.embeddings_version.load(Ordering::SeqCst)
+    }
+
+    /// SQL condition matching the `path` column against `?1`
+    fn path_matches(&self) -> &'static str {
+        if self.case_insensitive_paths.load(Ordering::SeqCst) {
+            "path = ?1 COLLATE NOCASE"
+        } else {
+            "path = ?1"
+        }
+    }
+
+    fn embeddings_changed(&self) {
+        self.embeddings_version.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn set_embedding_encoding(&self, encoding: EmbeddingEncoding) -> DbResult<()> {
+        *self.embedding_encoding.lock().map_err(|_| DbError::Lock)? = encoding;
+        Ok(())
+    }
+
+    /// Store an embedding, replacing the row with the same id if one exists
+    /// so a retried index doesn't fail on duplicate keys. Re-indexing a note
+    /// still deletes its old embeddings first: an upsert can't drop the
+    /// chunks past the end of a note that got shorter.
     pub fn insert_embedding(&self, embedding: &Embedding) -> DbResult<()> {
+        let encoding = *self.embedding_encoding.lock().map_err(|_| DbError::Lock)?;
         let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
-        let embedding_bytes = embedding_to_bytes(&embedding.embedding);
+        let embedding_bytes = embedding_to_bytes(&embedding.embedding, encoding);
         
         conn.execute(
-            "INSERT INTO embeddings (id, artifact_id, chunk_index, content, embedding)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
+            "INSERT INTO embeddings (id, artifact_id, chunk_index, content, embedding, chunk_hash, encoding)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(id) DO UPDATE SET
+                artifact_id = excluded.artifact_id,
+                chunk_index = excluded.chunk_index,
+                content = excluded.content,
+                embedding = excluded.embedding,
+                chunk_hash = excluded.chunk_hash,
+                encoding = excluded.encoding",
             params![
                 embedding.id,
                 embedding.artifact_id,
                 embedding.chunk_index,
                 embedding.content,
-                embedding_bytes
+                embedding_bytes,
+                content_hash(&embedding.content),
+                encoding.as_str()
             ],
         )?;
+        self.embeddings_changed();
         Ok(())
     }
 
-    pub fn delete_embeddings_by_artifact(&self, artifact_id: &str) -> DbResult<()> {
-        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
-        conn.execute(
-            "DELETE FROM embeddings WHERE artifact_id = ?1",
-            [artifact_id],
-        )?;
-        Ok(())
-    }
-
-    pub fn get_all_embeddings(&self) -> DbResult<Vec<Embedding>> {
+    /// An artifact's stored vectors keyed by chunk hash, so unchanged chunks
+    /// can be reused when the note is re-indexed
+    pub fn get_chunk_embeddings(&self, artifact_id: &str) -> DbResult<HashMap<String, Vec<f32>>> {
         let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
         let mut stmt = conn.prepare(
-            "SELECT id, artifact_id, chunk_index, content, embedding FROM embeddings"
+            "SELECT chunk_hash, content, embedding, encoding FROM embeddings WHERE artifact_id = ?1"
         )?;
-        
-        let embeddings = stmt.query_map([], |row| {
-            let embedding_bytes: Vec<u8> = row.get(4)?;
-            Ok(Embedding {
-                id: row.get(0)?,
-                artifact_id: row.get(1)?,
-                chunk_index: row.get(2)?,
-                content: row.get(3)?,
-                embedding: bytes_to_embedding(&embedding_bytes),
-            })
+
+        let chunks = stmt.query_map([artifact_id], |row| {
+            let hash: Option<String> = row.get(0)?;
+            let content: String = row.get(1)?;
+            // Rows written before chunk hashes were stored are hashed here
+            Ok((hash.unwrap_or_else(|| content_hash(&content)), read_embedding(row, 2)?))
         })?.filter_map(|r| r.ok()).collect();
-        
-        Ok(embeddings)
+
+        Ok(chunks)
     }
 
-    // === Chat Message Methods ===
+    /// A vector `model` produced for content with this hash, from any note
+    pub fn get_cached_embedding(&self, content_hash: &str, model: &str) -> DbResult<Option<Vec<f32>>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let result = conn.query_row(
+            "SELECT embedding, encoding FROM embedding_cache WHERE content_hash = ?1 AND model = ?2",
+            params![content_hash, model],
+            |row| read_embedding(row, 0),
+        );
 
-    pub fn insert_chat_message(&self, role: &str, content: &str) -> DbResult<i64> {
+        match result {
+            Ok(embedding) => Ok(Some(embedding)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(DbError::Sqlite(e)),
+        }
+    }
+
+    pub fn cache_embedding(&self, content_hash: &str, model: &str, embedding: &[f32]) -> DbResult<()> {
+        let encoding = *self.embedding_encoding.lock().map_err(|_| DbError::Lock)?;
         let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
-        
         conn.execute(
-            "INSERT INTO chat_messages (role, content, timestamp) VALUES (?1, ?2, ?3)",
-            params![role, content, timestamp],
+            "INSERT OR REPLACE INTO embedding_cache (content_hash, model, embedding, encoding)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![content_hash, model, embedding_to_bytes(embedding, encoding), encoding.as_str()],
         )?;
-        
-        Ok(conn.last_insert_rowid())
+        Ok(())
     }
 
-    pub fn get_chat_history(&self) -> DbResult<Vec<ChatMessage>> {
+    /// Delete cached vectors for content no current chunk has
+    pub fn prune_embedding_cache(&self) -> DbResult<CachePruneReport> {
+        let mut conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let tx = conn.transaction()?;
+        let orphaned = "content_hash NOT IN (SELECT chunk_hash FROM embeddings WHERE chunk_hash IS NOT NULL)";
+
+        let reclaimed_bytes: i64 = tx.query_row(
+            &format!(
+                "SELECT COALESCE(SUM(length(content_hash) + length(model) + length(embedding)), 0)
+                 FROM embedding_cache WHERE {}",
+                orphaned
+            ),
+            [],
+            |row| row.get(0),
+        )?;
+        let removed_entries = tx.execute(&format!("DELETE FROM embedding_cache WHERE {}", orphaned), [])?;
+        tx.commit()?;
+
+        Ok(CachePruneReport {
+            removed_entries,
+            reclaimed_bytes: reclaimed_bytes as u64,
+            skipped: false,
+        })
+    }
+
+    pub fn delete_embeddings_by_artifact(&self, artifact_id: &str) -> DbResult<()> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        conn.execute(
+            "DELETE FROM embeddings WHERE artifact_id = ?1",
+            [artifact_id],
+        )?;
+        self.embeddings_changed();
+        Ok(())
+    }
+
+    /// The first chunk of every pinned artifact
+    pub fn get_pinned_chunks(&self) -> DbResult<Vec<Embedding>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut stmt = conn.prepare(
+            "SELECT e.id, e.artifact_id, e.chunk_index, e.content, e.embedding, e.encoding
+             FROM embeddings e JOIN artifacts a ON a.id = e.artifact_id
+             WHERE a.pinned = 1 AND e.chunk_index = 0
+             ORDER BY a.path"
+        )?;
+
+        let embeddings = stmt.query_map([], row_to_embedding)?.filter_map(|r| r.ok()).collect();
+
+        Ok(embeddings)
+    }
+
+    /// Every chunk of one artifact, in document order
+    pub fn get_artifact_chunks(&self, artifact_id: &str) -> DbResult<Vec<Embedding>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut stmt = conn.prepare(
+            "SELECT id, artifact_id, chunk_index, content, embedding, encoding
+             FROM embeddings WHERE artifact_id = ?1 ORDER BY chunk_index"
+        )?;
+
+        let embeddings = stmt.query_map([artifact_id], row_to_embedding)?.filter_map(|r| r.ok()).collect();
+
+        Ok(embeddings)
+    }
+
+    pub fn count_embeddings(&self) -> DbResult<usize> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM embeddings", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    pub fn get_all_embeddings(&self) -> DbResult<Vec<Embedding>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut stmt = conn.prepare(
+            "SELECT id, artifact_id, chunk_index, content, embedding, encoding FROM embeddings"
+        )?;
+        
+        let embeddings = stmt.query_map([], row_to_embedding)?.filter_map(|r| r.ok()).collect();
+        
+        Ok(embeddings)
+    }
+
+    /// Visit every embedding, reading `batch_size` rows at a time so a full
+    /// scan never holds more than one batch in memory. The connection is
+    /// unlocked between batches.
+    pub fn for_each_embedding<F>(&self, batch_size: usize, mut visit: F) -> DbResult<()>
+    where
+        F: FnMut(Embedding),
+    {
+        let mut last_rowid = 0i64;
+        loop {
+            let batch: Vec<(Embedding, i64)> = {
+                let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+                let mut stmt = conn.prepare_cached(
+                    "SELECT id, artifact_id, chunk_index, content, embedding, encoding, rowid
+                     FROM embeddings WHERE rowid > ?1 ORDER BY rowid LIMIT ?2"
+                )?;
+                let rows = stmt.query_map(params![last_rowid, batch_size.max(1) as i64], |row| {
+                    Ok((row_to_embedding(row)?, row.get(6)?))
+                })?;
+                rows.collect::<Result<_, _>>()?
+            };
+
+            let Some(&(_, rowid)) = batch.last() else {
+                return Ok(());
+            };
+            last_rowid = rowid;
+            for (embedding, _) in batch {
+                visit(embedding);
+            }
+        }
+    }
+
+    /// Embeddings of the given artifacts, queried in batches to stay under
+    /// SQLite's bound-parameter limit
+    pub fn get_embeddings_for_artifacts(&self, artifact_ids: &[String]) -> DbResult<Vec<Embedding>> {
+        const MAX_PARAMS: usize = 500;
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut embeddings = Vec::new();
+
+        for batch in artifact_ids.chunks(MAX_PARAMS) {
+            let placeholders = vec!["?"; batch.len()].join(", ");
+            let mut stmt = conn.prepare(&format!(
+                "SELECT id, artifact_id, chunk_index, content, embedding, encoding
+                 FROM embeddings WHERE artifact_id IN ({})",
+                placeholders
+            ))?;
+            embeddings.extend(
+                stmt.query_map(params_from_iter(batch), row_to_embedding)?.filter_map(|r| r.ok()),
+            );
+        }
+
+        Ok(embeddings)
+    }
+
+    /// Embeddings of artifacts modified at or after `since` (unix seconds)
+    pub fn get_embeddings_modified_since(&self, since: i64) -> DbResult<Vec<Embedding>> {
         let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
         let mut stmt = conn.prepare(
-            "SELECT id, role, content, timestamp FROM chat_messages ORDER BY timestamp ASC"
+            "SELECT e.id, e.artifact_id, e.chunk_index, e.content, e.embedding, e.encoding
+             FROM embeddings e JOIN artifacts a ON a.id = e.artifact_id
+             WHERE a.last_modified >= ?1"
+        )?;
+
+        let embeddings = stmt.query_map([since], row_to_embedding)?.filter_map(|r| r.ok()).collect();
+
+        Ok(embeddings)
+    }
+
+    /// Dimension of the stored vectors, taken from any one embedding
+    pub fn get_stored_embedding_dimension(&self) -> DbResult<Option<usize>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let result = conn.query_row(
+            "SELECT length(embedding), encoding FROM embeddings LIMIT 1",
+            [],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+        );
+
+        match result {
+            Ok((bytes, encoding)) => Ok(Some(EmbeddingEncoding::parse(&encoding).dimension(bytes as usize))),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(DbError::Sqlite(e)),
+        }
+    }
+
+    // === Model Dimension Methods ===
+
+    pub fn record_model_dimension(&self, model: &str, dimension: usize) -> DbResult<()> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        conn.execute(
+            "INSERT INTO model_dimensions (model, dimension) VALUES (?1, ?2)
+             ON CONFLICT(model) DO UPDATE SET dimension = excluded.dimension",
+            params![model, dimension as i64],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_model_dimensions(&self) -> DbResult<HashMap<String, usize>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut stmt = conn.prepare("SELECT model, dimension FROM model_dimensions")?;
+        
+        let dimensions = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))
+        })?.filter_map(|r| r.ok()).collect();
+        
+        Ok(dimensions)
+    }
+
+    // === Summary Methods ===
+
+    /// Saved summary of an artifact, if it was made from the current content
+    pub fn get_artifact_summary(&self, artifact_id: &str, content_hash: &str) -> DbResult<Option<String>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let result = conn.query_row(
+            "SELECT summary FROM artifact_summaries WHERE artifact_id = ?1 AND content_hash = ?2",
+            params![artifact_id, content_hash],
+            |row| row.get(0),
+        );
+
+        match result {
+            Ok(summary) => Ok(Some(summary)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(DbError::Sqlite(e)),
+        }
+    }
+
+    pub fn save_artifact_summary(&self, artifact_id: &str, content_hash: &str, summary: &str) -> DbResult<()> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        conn.execute(
+            "INSERT INTO artifact_summaries (artifact_id, content_hash, summary, created_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(artifact_id) DO UPDATE SET
+                content_hash = excluded.content_hash,
+                summary = excluded.summary,
+                created_at = excluded.created_at",
+            params![artifact_id, content_hash, summary, timestamp],
+        )?;
+        Ok(())
+    }
+
+    // === Sync Checkpoint Methods ===
+
+    /// Mark a file as done in the current sync of `vault_path`
+    pub fn record_sync_checkpoint(&self, vault_path: &str, path: &str, content_hash: &str) -> DbResult<()> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        conn.execute(
+            "INSERT INTO sync_checkpoints (vault_path, path, content_hash) VALUES (?1, ?2, ?3)
+             ON CONFLICT(vault_path, path) DO UPDATE SET content_hash = excluded.content_hash",
+            params![vault_path, path, content_hash],
+        )?;
+        Ok(())
+    }
+
+    /// Files completed by an unfinished sync of `vault_path`, with their hashes
+    pub fn get_sync_checkpoints(&self, vault_path: &str) -> DbResult<HashMap<String, String>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut stmt = conn.prepare("SELECT path, content_hash FROM sync_checkpoints WHERE vault_path = ?1")?;
+
+        let checkpoints = stmt.query_map([vault_path], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?.filter_map(|r| r.ok()).collect();
+
+        Ok(checkpoints)
+    }
+
+    pub fn clear_sync_checkpoints(&self, vault_path: &str) -> DbResult<()> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        conn.execute("DELETE FROM sync_checkpoints WHERE vault_path = ?1", [vault_path])?;
+        Ok(())
+    }
+
+    // === Retrieval Log Methods ===
+
+    /// Record the artifacts whose chunks were used to answer one query
+    pub fn log_retrieval(&self, query_id: &str, artifact_ids: &[String]) -> DbResult<()> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        for artifact_id in artifact_ids {
+            conn.execute(
+                "INSERT INTO retrieval_log (query_id, artifact_id, retrieved_at) VALUES (?1, ?2, ?3)",
+                params![query_id, artifact_id, timestamp],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Artifacts most often used as context since `since` (unix seconds)
+    pub fn get_top_sources(&self, limit: usize, since: Option<i64>) -> DbResult<Vec<SourceUsage>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, COUNT(*) AS retrievals FROM retrieval_log
+             JOIN artifacts ON artifacts.id = retrieval_log.artifact_id
+             WHERE retrieval_log.retrieved_at >= ?1
+             GROUP BY artifacts.id
+             ORDER BY retrievals DESC, artifacts.path
+             LIMIT ?2",
+            ARTIFACT_COLUMNS
+        ))?;
+
+        let sources = stmt.query_map(params![since.unwrap_or(0), limit as i64], |row| {
+            Ok(SourceUsage {
+                artifact: row_to_artifact(row)?,
+                retrievals: row.get::<_, i64>(11)? as usize,
+            })
+        })?.filter_map(|r| r.ok()).collect();
+
+        Ok(sources)
+    }
+
+    /// Record the chunks an assistant message was answered from
+    pub fn log_answer_chunks(&self, message_id: i64, chunks: &[(String, i32)]) -> DbResult<()> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        for (artifact_id, chunk_index) in chunks {
+            conn.execute(
+                "INSERT OR IGNORE INTO answer_chunks (message_id, artifact_id, chunk_index) VALUES (?1, ?2, ?3)",
+                params![message_id, artifact_id, chunk_index],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Rate an assistant message as helpful or not, replacing any earlier
+    /// rating. `NotFound` when no assistant message has that id
+    pub fn rate_message(&self, message_id: i64, helpful: bool) -> DbResult<()> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM chat_messages WHERE id = ?1 AND role = 'assistant')",
+            [message_id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(DbError::NotFound(message_id.to_string()));
+        }
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        conn.execute(
+            "INSERT OR REPLACE INTO message_feedback (message_id, helpful, rated_at) VALUES (?1, ?2, ?3)",
+            params![message_id, helpful, timestamp],
+        )?;
+        Ok(())
+    }
+
+    /// Chunks cited by more downvoted answers than upvoted ones, worst first
+    pub fn get_noisy_chunks(&self, limit: usize) -> DbResult<Vec<NoisyChunk>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, answer_chunks.chunk_index,
+                (SELECT content FROM embeddings
+                 WHERE embeddings.artifact_id = answer_chunks.artifact_id
+                 AND embeddings.chunk_index = answer_chunks.chunk_index),
+                COUNT(*) AS retrievals,
+                COALESCE(SUM(message_feedback.helpful = 1), 0) AS upvotes,
+                COALESCE(SUM(message_feedback.helpful = 0), 0) AS downvotes
+             FROM answer_chunks
+             JOIN artifacts ON artifacts.id = answer_chunks.artifact_id
+             LEFT JOIN message_feedback ON message_feedback.message_id = answer_chunks.message_id
+             GROUP BY answer_chunks.artifact_id, answer_chunks.chunk_index
+             HAVING downvotes > upvotes
+             ORDER BY downvotes - upvotes DESC, retrievals DESC, artifacts.path, answer_chunks.chunk_index
+             LIMIT ?1",
+            ARTIFACT_COLUMNS
+        ))?;
+
+        let chunks = stmt.query_map([limit as i64], |row| {
+            Ok(NoisyChunk {
+                artifact: row_to_artifact(row)?,
+                chunk_index: row.get(11)?,
+                content: row.get::<_, Option<String>>(12)?.unwrap_or_default(),
+                retrievals: row.get::<_, i64>(13)? as usize,
+                upvotes: row.get::<_, i64>(14)? as usize,
+                downvotes: row.get::<_, i64>(15)? as usize,
+            })
+        })?.filter_map(|r| r.ok()).collect();
+
+        Ok(chunks)
+    }
+
+    /// Every distinct tag with the number of artifacts carrying it, most used
+    /// first. Tags are stored already normalized by ingest, so this is a
+    /// plain aggregate
+    pub fn get_tag_counts(&self) -> DbResult<Vec<(String, usize)>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut stmt = conn.prepare(
+            "SELECT tag, COUNT(DISTINCT artifact_id) AS notes FROM artifact_tags
+             GROUP BY tag
+             ORDER BY notes DESC, tag"
+        )?;
+
+        let counts = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))
+        })?.filter_map(|r| r.ok()).collect();
+
+        Ok(counts)
+    }
+
+    // === Chat Message Methods ===
+
+    pub fn insert_chat_message(&self, role: &str, content: &str) -> DbResult<i64> {
+        self.insert_chat_message_with_sources(role, content, &[])
+    }
+
+    /// Save a message along with the notes it cites
+    pub fn insert_chat_message_with_sources(
+        &self,
+        role: &str,
+        content: &str,
+        sources: &[SourceCitation],
+    ) -> DbResult<i64> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        
+        conn.execute(
+            "INSERT INTO chat_messages (role, content, timestamp, sources) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                role,
+                content,
+                timestamp,
+                (!sources.is_empty()).then(|| serde_json::to_string(sources).unwrap_or_default())
+            ],
+        )?;
+        
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn get_chat_history(&self) -> DbResult<Vec<ChatMessage>> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let mut stmt = conn.prepare(
+            "SELECT id, role, content, timestamp, sources FROM chat_messages ORDER BY timestamp ASC"
         )?;
         
         let messages = stmt.query_map([], |row| {
+            let sources_json: Option<String> = row.get(4)?;
             Ok(ChatMessage {
                 id: row.get(0)?,
                 role: row.get(1)?,
                 content: row.get(2)?,
                 timestamp: row.get(3)?,
+                sources: sources_json
+                    .and_then(|json| serde_json::from_str(&json).ok())
+                    .unwrap_or_default(),
             })
         })?.filter_map(|r| r.ok()).collect();
         
@@ -316,6 +1461,67 @@ impl Database {
         Ok(())
     }
 
+    /// Verify the database accepts writes by making one and rolling it back
+    pub fn check_writable(&self) -> DbResult<()> {
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+        let result = conn.execute_batch(
+            "BEGIN;
+             INSERT OR REPLACE INTO settings (key, value) VALUES ('__write_probe', '');
+             ROLLBACK;",
+        );
+        if result.is_err() {
+            let _ = conn.execute_batch("ROLLBACK;");
+        }
+        Ok(result?)
+    }
+
+    /// Run SQLite's integrity check and look for orphaned, malformed and
+    /// duplicated embeddings, deleting them when `repair` is set
+    pub fn check_integrity(&self, repair: bool) -> DbResult<IntegrityReport> {
+        const ORPHANED: &str = "artifact_id NOT IN (SELECT id FROM artifacts)";
+        const INVALID: &str = "embedding IS NULL OR length(embedding) = 0
+            OR (encoding = 'int8' AND length(embedding) <= 4)
+            OR (encoding != 'int8' AND length(embedding) % 4 != 0)";
+        const DUPLICATE: &str = "rowid NOT IN (SELECT MIN(rowid) FROM embeddings GROUP BY artifact_id, chunk_index)";
+
+        let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
+
+        let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+        let messages: Vec<String> = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        let integrity_ok = messages.len() == 1 && messages[0] == "ok";
+
+        let count = |condition: &str| -> DbResult<usize> {
+            let count: i64 = conn.query_row(
+                &format!("SELECT COUNT(*) FROM embeddings WHERE {}", condition),
+                [],
+                |row| row.get(0),
+            )?;
+            Ok(count as usize)
+        };
+
+        let mut report = IntegrityReport {
+            integrity_ok,
+            integrity_errors: if integrity_ok { Vec::new() } else { messages },
+            orphaned_embeddings: count(ORPHANED)?,
+            invalid_embeddings: count(INVALID)?,
+            duplicate_chunks: count(DUPLICATE)?,
+            repaired: false,
+        };
+
+        if repair {
+            for condition in [ORPHANED, INVALID, DUPLICATE] {
+                conn.execute(&format!("DELETE FROM embeddings WHERE {}", condition), [])?;
+            }
+            conn.execute("DELETE FROM artifact_tags WHERE artifact_id NOT IN (SELECT id FROM artifacts)", [])?;
+            report.repaired = true;
+            self.embeddings_changed();
+        }
+
+        Ok(report)
+    }
+
     // === Settings Methods ===
 
     pub fn get_settings(&self) -> DbResult<Settings> {
@@ -327,31 +1533,203 @@ impl Database {
             Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
         })?;
 
+        // Before multiple vaults, a single `vault_path` was stored
+        let mut legacy_vault_path = None;
+        let mut has_vault_paths = false;
         for row in rows.flatten() {
             match row.0.as_str() {
-                "vault_path" => settings.vault_path = row.1,
+                "vault_path" => legacy_vault_path = Some(row.1),
+                "vault_paths" => {
+                    has_vault_paths = true;
+                    settings.vault_paths = serde_json::from_str(&row.1).unwrap_or_default()
+                }
+                "llm_provider" => settings.llm_provider = row.1,
                 "ollama_endpoint" => settings.ollama_endpoint = row.1,
+                "embedding_endpoint" => settings.embedding_endpoint = row.1,
                 "ollama_model" => settings.ollama_model = row.1,
                 "embedding_model" => settings.embedding_model = row.1,
                 "outline_api_key" => settings.outline_api_key = row.1,
                 "outline_base_url" => settings.outline_base_url = row.1,
+                "min_word_count" => {
+                    settings.min_word_count = row.1.parse().unwrap_or(settings.min_word_count)
+                }
+                "snap_chunk_overlap" => {
+                    settings.snap_chunk_overlap = row.1.parse().unwrap_or(settings.snap_chunk_overlap)
+                }
+                "chunk_strategy" => settings.chunk_strategy = row.1,
+                "chunk_max_tokens" => {
+                    settings.chunk_max_tokens = row.1.parse().unwrap_or(settings.chunk_max_tokens)
+                }
+                "embedding_document_prefix" => settings.embedding_document_prefix = row.1,
+                "embedding_query_prefix" => settings.embedding_query_prefix = row.1,
+                "embedding_request_format" => settings.embedding_request_format = row.1,
+                "embedding_response_format" => settings.embedding_response_format = row.1,
+                "embedding_max_input_bytes" => {
+                    settings.embedding_max_input_bytes = row.1.parse().unwrap_or(settings.embedding_max_input_bytes)
+                }
+                "max_file_size_mb" => {
+                    settings.max_file_size_mb = row.1.parse().unwrap_or(settings.max_file_size_mb)
+                }
+                "follow_symlinks" => {
+                    settings.follow_symlinks = row.1.parse().unwrap_or(settings.follow_symlinks)
+                }
+                "include_link_urls" => {
+                    settings.include_link_urls = row.1.parse().unwrap_or(settings.include_link_urls)
+                }
+                "include_image_sources" => {
+                    settings.include_image_sources = row.1.parse().unwrap_or(settings.include_image_sources)
+                }
+                "lowercase_tags" => {
+                    settings.lowercase_tags = row.1.parse().unwrap_or(settings.lowercase_tags)
+                }
+                "index_attachments" => {
+                    settings.index_attachments = row.1.parse().unwrap_or(settings.index_attachments)
+                }
+                "retrieval_boosts" => {
+                    settings.retrieval_boosts = serde_json::from_str(&row.1).unwrap_or_default()
+                }
+                "similarity_metric" => settings.similarity_metric = row.1,
+                "similarity_threshold" => settings.similarity_threshold = row.1.parse().ok(),
+                "subquery_similarity_floor" => settings.subquery_similarity_floor = row.1.parse().ok(),
+                "near_duplicate_threshold" => {
+                    settings.near_duplicate_threshold = row.1.parse().unwrap_or(settings.near_duplicate_threshold)
+                }
+                "cross_source_dedup" => {
+                    settings.cross_source_dedup = row.1.parse().unwrap_or(settings.cross_source_dedup)
+                }
+                "source_priority" => {
+                    settings.source_priority = serde_json::from_str(&row.1).unwrap_or_default()
+                }
+                "grounded_answers_only" => {
+                    settings.grounded_answers_only = row.1.parse().unwrap_or(settings.grounded_answers_only)
+                }
+                "track_retrieval" => {
+                    settings.track_retrieval = row.1.parse().unwrap_or(settings.track_retrieval)
+                }
+                "max_tokens" => settings.max_tokens = row.1.parse().ok(),
+                "context_window_tokens" => {
+                    settings.context_window_tokens = row.1.parse().unwrap_or(settings.context_window_tokens)
+                }
+                "seed" => settings.seed = row.1.parse().ok(),
+                "stop_sequences" => {
+                    settings.stop_sequences = serde_json::from_str(&row.1).unwrap_or_default()
+                }
+                "fuzzy_keyword_fallback" => {
+                    settings.fuzzy_keyword_fallback = row.1.parse().unwrap_or(settings.fuzzy_keyword_fallback)
+                }
+                "fuzzy_match_threshold" => {
+                    settings.fuzzy_match_threshold = row.1.parse().unwrap_or(settings.fuzzy_match_threshold)
+                }
+                "search_cache_size" => {
+                    settings.search_cache_size = row.1.parse().unwrap_or(settings.search_cache_size)
+                }
+                "enabled_sources" => {
+                    settings.enabled_sources = serde_json::from_str(&row.1).unwrap_or_default()
+                }
+                "embedding_encoding" => settings.embedding_encoding = row.1,
+                "reindex_debounce_ms" => {
+                    settings.reindex_debounce_ms = row.1.parse().unwrap_or(settings.reindex_debounce_ms)
+                }
+                "response_language" => settings.response_language = row.1,
+                "case_insensitive_paths" => {
+                    settings.case_insensitive_paths = row.1.parse().unwrap_or(settings.case_insensitive_paths)
+                }
+                "use_chat_context" => {
+                    settings.use_chat_context = row.1.parse().unwrap_or(settings.use_chat_context)
+                }
+                "allowed_hidden_dirs" => {
+                    settings.allowed_hidden_dirs = serde_json::from_str(&row.1).unwrap_or_default()
+                }
+                "trash_folder" => settings.trash_folder = row.1,
+                "quiet_unchanged_files" => {
+                    settings.quiet_unchanged_files = row.1.parse().unwrap_or(settings.quiet_unchanged_files)
+                }
+                "max_query_expansions" => {
+                    settings.max_query_expansions = row.1.parse().unwrap_or(settings.max_query_expansions)
+                }
+                "expansion_min_embeddings" => {
+                    settings.expansion_min_embeddings = row.1.parse().unwrap_or(settings.expansion_min_embeddings)
+                }
                 _ => {}
             }
         }
+
+        if !has_vault_paths {
+            settings.vault_paths = legacy_vault_path.into_iter().filter(|p| !p.is_empty()).collect();
+        }
         
         Ok(settings)
     }
 
+    /// Default settings, keeping the configured vaults when `keep_vault_paths` is set
+    pub fn default_settings(&self, keep_vault_paths: bool) -> DbResult<Settings> {
+        let mut settings = Settings::default();
+        if keep_vault_paths {
+            settings.vault_paths = self.get_settings()?.vault_paths;
+        }
+        Ok(settings)
+    }
+
     pub fn save_settings(&self, settings: &Settings) -> DbResult<()> {
         let conn = self.conn.lock().map_err(|_| DbError::Lock)?;
         
         let pairs = [
-            ("vault_path", &settings.vault_path),
-            ("ollama_endpoint", &settings.ollama_endpoint),
-            ("ollama_model", &settings.ollama_model),
-            ("embedding_model", &settings.embedding_model),
-            ("outline_api_key", &settings.outline_api_key),
-            ("outline_base_url", &settings.outline_base_url),
+            ("vault_paths", serde_json::to_string(&settings.vault_paths).unwrap_or_default()),
+            ("llm_provider", settings.llm_provider.clone()),
+            ("ollama_endpoint", settings.ollama_endpoint.clone()),
+            ("embedding_endpoint", settings.embedding_endpoint.clone()),
+            ("ollama_model", settings.ollama_model.clone()),
+            ("embedding_model", settings.embedding_model.clone()),
+            ("outline_api_key", settings.outline_api_key.clone()),
+            ("outline_base_url", settings.outline_base_url.clone()),
+            ("min_word_count", settings.min_word_count.to_string()),
+            ("snap_chunk_overlap", settings.snap_chunk_overlap.to_string()),
+            ("chunk_strategy", settings.chunk_strategy.clone()),
+            ("chunk_max_tokens", settings.chunk_max_tokens.to_string()),
+            ("embedding_document_prefix", settings.embedding_document_prefix.clone()),
+            ("embedding_query_prefix", settings.embedding_query_prefix.clone()),
+            ("embedding_request_format", settings.embedding_request_format.clone()),
+            ("embedding_response_format", settings.embedding_response_format.clone()),
+            ("embedding_max_input_bytes", settings.embedding_max_input_bytes.to_string()),
+            ("max_file_size_mb", settings.max_file_size_mb.to_string()),
+            ("follow_symlinks", settings.follow_symlinks.to_string()),
+            ("include_link_urls", settings.include_link_urls.to_string()),
+            ("include_image_sources", settings.include_image_sources.to_string()),
+            ("lowercase_tags", settings.lowercase_tags.to_string()),
+            ("index_attachments", settings.index_attachments.to_string()),
+            ("retrieval_boosts", serde_json::to_string(&settings.retrieval_boosts).unwrap_or_default()),
+            ("similarity_metric", settings.similarity_metric.clone()),
+            (
+                "similarity_threshold",
+                settings.similarity_threshold.map(|t| t.to_string()).unwrap_or_default(),
+            ),
+            (
+                "subquery_similarity_floor",
+                settings.subquery_similarity_floor.map(|t| t.to_string()).unwrap_or_default(),
+            ),
+            ("near_duplicate_threshold", settings.near_duplicate_threshold.to_string()),
+            ("cross_source_dedup", settings.cross_source_dedup.to_string()),
+            ("source_priority", serde_json::to_string(&settings.source_priority).unwrap_or_default()),
+            ("grounded_answers_only", settings.grounded_answers_only.to_string()),
+            ("track_retrieval", settings.track_retrieval.to_string()),
+            ("max_tokens", settings.max_tokens.map(|t| t.to_string()).unwrap_or_default()),
+            ("context_window_tokens", settings.context_window_tokens.to_string()),
+            ("seed", settings.seed.map(|s| s.to_string()).unwrap_or_default()),
+            ("stop_sequences", serde_json::to_string(&settings.stop_sequences).unwrap_or_default()),
+            ("fuzzy_keyword_fallback", settings.fuzzy_keyword_fallback.to_string()),
+            ("fuzzy_match_threshold", settings.fuzzy_match_threshold.to_string()),
+            ("search_cache_size", settings.search_cache_size.to_string()),
+            ("enabled_sources", serde_json::to_string(&settings.enabled_sources).unwrap_or_default()),
+            ("embedding_encoding", settings.embedding_encoding.clone()),
+            ("reindex_debounce_ms", settings.reindex_debounce_ms.to_string()),
+            ("response_language", settings.response_language.clone()),
+            ("case_insensitive_paths", settings.case_insensitive_paths.to_string()),
+            ("use_chat_context", settings.use_chat_context.to_string()),
+            ("allowed_hidden_dirs", serde_json::to_string(&settings.allowed_hidden_dirs).unwrap_or_default()),
+            ("trash_folder", settings.trash_folder.clone()),
+            ("quiet_unchanged_files", settings.quiet_unchanged_files.to_string()),
+            ("max_query_expansions", settings.max_query_expansions.to_string()),
+            ("expansion_min_embeddings", settings.expansion_min_embeddings.to_string()),
         ];
 
         for (key, value) in pairs {
@@ -361,26 +1739,524 @@ impl Database {
                 params![key, value],
             )?;
         }
-        
+        drop(conn);
+
+        self.set_embedding_encoding(EmbeddingEncoding::parse(&settings.embedding_encoding))?;
+        self.case_insensitive_paths.store(settings.case_insensitive_paths, Ordering::SeqCst);
         Ok(())
     }
 }
 
+/// Embedding columns `id, artifact_id, chunk_index, content, embedding, encoding`
+fn row_to_embedding(row: &Row) -> rusqlite::Result<Embedding> {
+    Ok(Embedding {
+        id: row.get(0)?,
+        artifact_id: row.get(1)?,
+        chunk_index: row.get(2)?,
+        content: row.get(3)?,
+        embedding: read_embedding(row, 4)?,
+    })
+}
+
+/// Decode the vector at `index`, with its encoding in the following column
+fn read_embedding(row: &Row, index: usize) -> rusqlite::Result<Vec<f32>> {
+    let bytes: Vec<u8> = row.get(index)?;
+    let encoding: String = row.get(index + 1)?;
+    Ok(bytes_to_embedding(&bytes, EmbeddingEncoding::parse(&encoding)))
+}
+
 // Helper functions to convert embeddings to/from bytes
-fn embedding_to_bytes(embedding: &[f32]) -> Vec<u8> {
-    embedding
-        .iter()
-        .flat_map(|f| f.to_le_bytes())
-        .collect()
+fn embedding_to_bytes(embedding: &[f32], encoding: EmbeddingEncoding) -> Vec<u8> {
+    match encoding {
+        EmbeddingEncoding::F32 => embedding
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect(),
+        EmbeddingEncoding::Int8 => {
+            // Symmetric per-vector scale so the largest component maps to ±127
+            let max = embedding.iter().fold(0.0f32, |max, v| max.max(v.abs()));
+            let scale = if max > 0.0 { max / 127.0 } else { 1.0 };
+            let mut bytes = scale.to_le_bytes().to_vec();
+            bytes.extend(embedding.iter().map(|v| (v / scale).round().clamp(-127.0, 127.0) as i8 as u8));
+            bytes
+        }
+    }
 }
 
-fn bytes_to_embedding(bytes: &[u8]) -> Vec<f32> {
-    bytes
-        .chunks_exact(4)
-        .map(|chunk| {
-            let arr: [u8; 4] = chunk.try_into().unwrap();
-            f32::from_le_bytes(arr)
-        })
-        .collect()
+fn bytes_to_embedding(bytes: &[u8], encoding: EmbeddingEncoding) -> Vec<f32> {
+    match encoding {
+        EmbeddingEncoding::F32 => bytes
+            .chunks_exact(4)
+            .map(|chunk| {
+                let arr: [u8; 4] = chunk.try_into().unwrap();
+                f32::from_le_bytes(arr)
+            })
+            .collect(),
+        EmbeddingEncoding::Int8 => {
+            let Some((scale, values)) = bytes.split_first_chunk::<4>() else {
+                return Vec::new();
+            };
+            let scale = f32::from_le_bytes(*scale);
+            values.iter().map(|&b| b as i8 as f32 * scale).collect()
+        }
+    }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_artifact_title_and_tags_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        db.upsert_artifact(&Artifact {
+            id: "a1".to_string(),
+            path: "/vault/plan.md".to_string(),
+            title: Some("Plan".to_string()),
+            tags: vec!["work".to_string(), "#idea".to_string()],
+            ..Default::default()
+        }).unwrap();
+
+        let artifact = db.get_artifact_by_path("/vault/plan.md").unwrap().unwrap();
+        assert_eq!(artifact.title.as_deref(), Some("Plan"));
+        let mut tags = artifact.tags;
+        tags.sort();
+        assert_eq!(tags, vec!["idea", "work"]);
+    }
+
+    #[test]
+    fn test_artifact_details_count_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        for (id, chunks) in [("a1", 2), ("a2", 1)] {
+            db.upsert_artifact(&Artifact { id: id.to_string(), path: format!("/vault/{}.md", id), ..Default::default() })
+                .unwrap();
+            for chunk_index in 0..chunks {
+                db.insert_embedding(&Embedding {
+                    id: format!("{}#{}", id, chunk_index),
+                    artifact_id: id.to_string(),
+                    chunk_index,
+                    content: "chunk".to_string(),
+                    embedding: vec![0.1, 0.2],
+                }).unwrap();
+            }
+        }
+
+        let details = db.get_artifact_details("a1").unwrap();
+        assert_eq!(details.artifact.path, "/vault/a1.md");
+        assert_eq!(details.chunk_count, 2);
+        assert!(matches!(db.get_artifact_details("missing"), Err(DbError::NotFound(id)) if id == "missing"));
+    }
+
+    #[test]
+    fn test_artifacts_changed_since() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        for (id, last_modified, indexed_at) in [("old", 100, 150), ("edited", 300, 150), ("reindexed", 100, 250)] {
+            db.upsert_artifact(&Artifact {
+                id: id.to_string(),
+                path: format!("/vault/{}.md", id),
+                last_modified,
+                indexed_at,
+                ..Default::default()
+            }).unwrap();
+        }
+        db.insert_embedding(&Embedding {
+            id: "edited#0".to_string(),
+            artifact_id: "edited".to_string(),
+            chunk_index: 0,
+            content: "chunk".to_string(),
+            embedding: vec![0.1, 0.2],
+        }).unwrap();
+
+        let changed = db.get_artifacts_changed_since(200).unwrap();
+        let summary: Vec<(&str, usize)> = changed.iter().map(|d| (d.artifact.id.as_str(), d.chunk_count)).collect();
+        assert_eq!(summary, vec![("edited", 1), ("reindexed", 0)]);
+        assert!(db.get_artifacts_changed_since(300).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_only_database_rejects_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(Database::open_read_only(dir.path(), None), Err(DbError::Open { .. })));
+
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        db.upsert_artifact(&Artifact { id: "a1".to_string(), path: "/vault/a1.md".to_string(), ..Default::default() })
+            .unwrap();
+
+        // Opened alongside the writable connection
+        let reader = Database::open_read_only(dir.path(), None).unwrap();
+        assert_eq!(reader.get_all_artifacts().unwrap().len(), 1);
+        assert_eq!(reader.get_settings().unwrap().min_word_count, Settings::default().min_word_count);
+
+        let write = reader.upsert_artifact(&Artifact { id: "a2".to_string(), path: "/vault/a2.md".to_string(), ..Default::default() });
+        assert!(matches!(write, Err(DbError::Sqlite(_))));
+        assert!(reader.save_settings(&Settings::default()).is_err());
+        assert_eq!(db.count_artifacts().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_downvoted_answers_mark_their_chunks_noisy() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        db.upsert_artifact(&Artifact { id: "a1".to_string(), path: "/vault/a1.md".to_string(), ..Default::default() })
+            .unwrap();
+        for chunk_index in 0..2 {
+            db.insert_embedding(&Embedding {
+                id: format!("a1#{}", chunk_index),
+                artifact_id: "a1".to_string(),
+                chunk_index,
+                content: format!("chunk {}", chunk_index),
+                embedding: vec![0.1, 0.2],
+            }).unwrap();
+        }
+
+        let question = db.insert_chat_message("user", "question").unwrap();
+        let mut answers = Vec::new();
+        for chunks in [vec![0, 1], vec![0], vec![1]] {
+            let id = db.insert_chat_message_with_sources("assistant", "answer", &[]).unwrap();
+            let cited: Vec<(String, i32)> = chunks.into_iter().map(|c| ("a1".to_string(), c)).collect();
+            db.log_answer_chunks(id, &cited).unwrap();
+            answers.push(id);
+        }
+        assert!(db.get_noisy_chunks(10).unwrap().is_empty());
+
+        // Chunk 0 is in both downvoted answers; chunk 1 is balanced by an upvote
+        db.rate_message(answers[0], false).unwrap();
+        db.rate_message(answers[1], false).unwrap();
+        db.rate_message(answers[2], true).unwrap();
+
+        let noisy = db.get_noisy_chunks(10).unwrap();
+        assert_eq!(noisy.len(), 1);
+        assert_eq!(noisy[0].chunk_index, 0);
+        assert_eq!(noisy[0].content, "chunk 0");
+        assert_eq!((noisy[0].retrievals, noisy[0].upvotes, noisy[0].downvotes), (2, 0, 2));
+
+        // Changing a rating replaces it
+        db.rate_message(answers[1], true).unwrap();
+        assert!(db.get_noisy_chunks(10).unwrap().is_empty());
+
+        assert!(matches!(db.rate_message(question, false), Err(DbError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_int8_quantization_roundtrip() {
+        let embedding: Vec<f32> = (0..384).map(|i| ((i as f32) * 0.37).sin() * 0.8).collect();
+
+        let bytes = embedding_to_bytes(&embedding, EmbeddingEncoding::Int8);
+        assert_eq!(bytes.len(), 4 + embedding.len());
+        assert_eq!(embedding_to_bytes(&embedding, EmbeddingEncoding::F32).len(), 4 * embedding.len());
+
+        let decoded = bytes_to_embedding(&bytes, EmbeddingEncoding::Int8);
+        assert_eq!(decoded.len(), embedding.len());
+        // Rounding error is at most half a quantization step
+        let step = 0.8 / 127.0;
+        for (original, restored) in embedding.iter().zip(&decoded) {
+            assert!((original - restored).abs() <= step / 2.0 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_quantized_embeddings_are_stored_and_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        db.upsert_artifact(&Artifact { id: "a1".to_string(), path: "/vault/a.md".to_string(), ..Default::default() }).unwrap();
+        let chunk = |id: &str, index| Embedding {
+            id: id.to_string(),
+            artifact_id: "a1".to_string(),
+            chunk_index: index,
+            content: id.to_string(),
+            embedding: vec![0.5, -0.25, 1.0],
+        };
+
+        db.insert_embedding(&chunk("exact", 0)).unwrap();
+        db.save_settings(&Settings { embedding_encoding: "int8".to_string(), ..Default::default() }).unwrap();
+        db.insert_embedding(&chunk("quantized", 1)).unwrap();
+
+        // Rows written under either encoding decode side by side
+        let chunks = db.get_artifact_chunks("a1").unwrap();
+        assert_eq!(chunks[0].embedding, vec![0.5, -0.25, 1.0]);
+        for (stored, expected) in chunks[1].embedding.iter().zip([0.5, -0.25, 1.0]) {
+            assert!((stored - expected).abs() < 0.01);
+        }
+        assert_eq!(db.get_stored_embedding_dimension().unwrap(), Some(3));
+        assert_eq!(db.check_integrity(false).unwrap().invalid_embeddings, 0);
+    }
+
+    #[test]
+    fn test_inserting_an_existing_embedding_id_updates_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        db.upsert_artifact(&Artifact { id: "a1".to_string(), path: "/vault/a.md".to_string(), ..Default::default() }).unwrap();
+        let chunk = |content: &str, embedding: Vec<f32>| Embedding {
+            id: "a1#0".to_string(),
+            artifact_id: "a1".to_string(),
+            chunk_index: 0,
+            content: content.to_string(),
+            embedding,
+        };
+
+        db.insert_embedding(&chunk("first draft", vec![1.0, 0.0])).unwrap();
+        db.insert_embedding(&chunk("second draft", vec![0.0, 1.0])).unwrap();
+
+        let chunks = db.get_artifact_chunks("a1").unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].content, "second draft");
+        assert_eq!(chunks[0].embedding, vec![0.0, 1.0]);
+        assert_eq!(db.get_chunk_embeddings("a1").unwrap().get(&content_hash("second draft")), Some(&vec![0.0, 1.0]));
+    }
+
+    #[test]
+    fn test_single_vault_path_setting_is_migrated() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        db.conn.lock().unwrap()
+            .execute("INSERT INTO settings (key, value) VALUES ('vault_path', '/notes')", [])
+            .unwrap();
+        assert_eq!(db.get_settings().unwrap().vault_paths, vec!["/notes"]);
+
+        let settings = Settings {
+            vault_paths: vec!["/notes".to_string(), "/work".to_string()],
+            ..Settings::default()
+        };
+        db.save_settings(&settings).unwrap();
+        assert_eq!(db.get_settings().unwrap().vault_paths, settings.vault_paths);
+    }
+
+    #[test]
+    fn test_reset_settings_to_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        db.save_settings(&Settings {
+            vault_paths: vec!["/notes".to_string()],
+            ollama_model: "mistral".to_string(),
+            similarity_threshold: Some(0.9),
+            stop_sequences: vec!["END".to_string()],
+            ..Settings::default()
+        }).unwrap();
+        let as_json = |settings: &Settings| serde_json::to_value(settings).unwrap();
+
+        let kept = db.default_settings(true).unwrap();
+        assert_eq!(kept.vault_paths, vec!["/notes"]);
+        assert_eq!(as_json(&Settings { vault_paths: Vec::new(), ..kept }), as_json(&Settings::default()));
+
+        db.save_settings(&db.default_settings(false).unwrap()).unwrap();
+        assert_eq!(as_json(&db.get_settings().unwrap()), as_json(&Settings::default()));
+    }
+
+    #[test]
+    fn test_tag_counts() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        for (id, tags) in [("a1", vec!["work", "#idea"]), ("a2", vec!["work"]), ("a3", vec!["idea", "work", "home"])] {
+            db.upsert_artifact(&Artifact {
+                id: id.to_string(),
+                path: format!("/vault/{}.md", id),
+                tags: tags.into_iter().map(String::from).collect(),
+                ..Default::default()
+            }).unwrap();
+        }
+
+        assert_eq!(db.get_tag_counts().unwrap(), vec![
+            ("work".to_string(), 3),
+            ("idea".to_string(), 2),
+            ("home".to_string(), 1),
+        ]);
+    }
+
+    #[test]
+    fn test_prune_embedding_cache_removes_only_orphans() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        db.upsert_artifact(&Artifact { id: "a1".to_string(), path: "/vault/a.md".to_string(), ..Default::default() })
+            .unwrap();
+        db.insert_embedding(&Embedding {
+            id: "a1#0".to_string(),
+            artifact_id: "a1".to_string(),
+            chunk_index: 0,
+            content: "still in a note".to_string(),
+            embedding: vec![0.1, 0.2],
+        }).unwrap();
+        let referenced = content_hash("still in a note");
+        let orphaned = content_hash("deleted long ago");
+        db.cache_embedding(&referenced, "nomic-embed-text", &[0.1, 0.2]).unwrap();
+        db.cache_embedding(&orphaned, "nomic-embed-text", &[0.3, 0.4]).unwrap();
+
+        let report = db.prune_embedding_cache().unwrap();
+
+        assert_eq!(report.removed_entries, 1);
+        assert!(report.reclaimed_bytes >= 8);
+        assert_eq!(db.get_cached_embedding(&referenced, "nomic-embed-text").unwrap(), Some(vec![0.1, 0.2]));
+        assert_eq!(db.get_cached_embedding(&orphaned, "nomic-embed-text").unwrap(), None);
+        assert_eq!(db.prune_embedding_cache().unwrap().removed_entries, 0);
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn test_encrypted_database_needs_the_right_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let db = Database::with_passphrase(dir.path().to_path_buf(), Some("correct horse")).unwrap();
+            db.insert_chat_message("user", "secret").unwrap();
+        }
+
+        let db = Database::with_passphrase(dir.path().to_path_buf(), Some("correct horse")).unwrap();
+        assert_eq!(db.get_chat_history().unwrap()[0].content, "secret");
+
+        // Read-only tools unlock it the same way
+        let reader = Database::open_read_only(dir.path(), Some("correct horse")).unwrap();
+        assert_eq!(reader.get_chat_history().unwrap()[0].content, "secret");
+        assert!(reader.insert_chat_message("user", "more").is_err());
+        assert!(matches!(
+            Database::open_read_only(dir.path(), Some("battery staple")),
+            Err(DbError::WrongPassphrase { .. })
+        ));
+        drop(reader);
+        drop(db);
+
+        assert!(matches!(
+            Database::with_passphrase(dir.path().to_path_buf(), Some("battery staple")),
+            Err(DbError::WrongPassphrase { .. })
+        ));
+        assert!(Database::new(dir.path().to_path_buf()).is_err());
+    }
+
+    #[cfg(not(feature = "sqlcipher"))]
+    #[test]
+    fn test_passphrase_without_encryption_support_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            Database::with_passphrase(dir.path().to_path_buf(), Some("secret")),
+            Err(DbError::EncryptionUnsupported)
+        ));
+
+        Database::new(dir.path().to_path_buf()).unwrap();
+        assert!(matches!(
+            Database::open_read_only(dir.path(), Some("secret")),
+            Err(DbError::EncryptionUnsupported)
+        ));
+    }
+
+    #[test]
+    fn test_unusable_data_dir_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        // A regular file where the data directory should go can't be created
+        // over, even with elevated permissions
+        let blocker = dir.path().join("blocker");
+        std::fs::write(&blocker, "not a directory").unwrap();
+
+        match Database::new(blocker.join("data")) {
+            Err(DbError::DataDir { path, .. }) => assert_eq!(path, blocker.join("data")),
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("database opened under a file"),
+        }
+    }
+
+    #[test]
+    fn test_integrity_check_repairs_orphaned_embeddings() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        db.upsert_artifact(&Artifact { id: "a1".to_string(), path: "note.md".to_string(), ..Default::default() })
+            .unwrap();
+        db.insert_embedding(&Embedding {
+            id: "a1#0".to_string(),
+            artifact_id: "a1".to_string(),
+            chunk_index: 0,
+            content: "kept".to_string(),
+            embedding: vec![0.1, 0.2],
+        }).unwrap();
+
+        // Simulate a row left behind before foreign keys were enforced
+        {
+            let conn = db.conn.lock().unwrap();
+            conn.execute_batch(
+                "PRAGMA foreign_keys = OFF;
+                 INSERT INTO embeddings (id, artifact_id, chunk_index, content, embedding)
+                 VALUES ('gone#0', 'gone', 0, 'orphan', x'00000000');
+                 PRAGMA foreign_keys = ON;",
+            ).unwrap();
+        }
+
+        let report = db.check_integrity(false).unwrap();
+        assert!(report.integrity_ok);
+        assert_eq!(report.orphaned_embeddings, 1);
+        assert_eq!(report.invalid_embeddings, 0);
+        assert!(!report.repaired);
+        assert_eq!(db.count_embeddings().unwrap(), 2);
+
+        let report = db.check_integrity(true).unwrap();
+        assert!(report.repaired);
+        assert_eq!(db.count_embeddings().unwrap(), 1);
+        assert_eq!(db.check_integrity(false).unwrap().orphaned_embeddings, 0);
+    }
+
+    #[test]
+    fn test_delete_artifacts_by_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        for (id, path) in [("o1", "outline://doc-1"), ("o2", "outline://doc-2"), ("f1", "/vault/outline.md")] {
+            db.upsert_artifact(&Artifact {
+                id: id.to_string(),
+                path: path.to_string(),
+                ..Default::default()
+            }).unwrap();
+            db.insert_embedding(&Embedding {
+                id: format!("{}#0", id),
+                artifact_id: id.to_string(),
+                chunk_index: 0,
+                content: String::new(),
+                embedding: vec![1.0],
+            }).unwrap();
+        }
+
+        assert_eq!(db.delete_artifacts_by_prefix("outline://").unwrap(), 2);
+
+        let remaining = db.get_all_artifacts().unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].id, "f1");
+        let embeddings = db.get_all_embeddings().unwrap();
+        assert_eq!(embeddings.len(), 1);
+        assert_eq!(embeddings[0].artifact_id, "f1");
+    }
+
+    #[test]
+    fn test_deduplicate_artifacts_keeps_latest() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        for (id, path, indexed_at) in [("old", "/vault/inbox/idea.md", 100), ("new", "/vault/ideas/idea.md", 200)] {
+            db.upsert_artifact(&Artifact {
+                id: id.to_string(),
+                path: path.to_string(),
+                content_hash: "same".to_string(),
+                indexed_at,
+                ..Default::default()
+            }).unwrap();
+            db.insert_embedding(&Embedding {
+                id: format!("{}#0", id),
+                artifact_id: id.to_string(),
+                chunk_index: 0,
+                content: "idea".to_string(),
+                embedding: vec![1.0],
+            }).unwrap();
+        }
+        db.upsert_artifact(&Artifact {
+            id: "other".to_string(),
+            path: "/vault/other.md".to_string(),
+            content_hash: "different".to_string(),
+            ..Default::default()
+        }).unwrap();
+
+        let merges = db.deduplicate_artifacts().unwrap();
+
+        assert_eq!(merges.len(), 1);
+        assert_eq!(merges[0].kept_path, "/vault/ideas/idea.md");
+        assert_eq!(merges[0].removed_paths, vec!["/vault/inbox/idea.md"]);
+        assert_eq!(merges[0].removed_embeddings, 1);
+        assert_eq!(db.count_artifacts().unwrap(), 2);
+        let embeddings = db.get_all_embeddings().unwrap();
+        assert_eq!(embeddings.len(), 1);
+        assert_eq!(embeddings[0].artifact_id, "new");
+    }
+}
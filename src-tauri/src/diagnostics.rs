@@ -0,0 +1,312 @@
+//! End-to-end self-test of the RAG pipeline for troubleshooting.
+
+use crate::db::{Database, Settings};
+use crate::embedding::{EmbeddingClient, EmbeddingResult};
+use crate::llm::{create_provider, GenerationOptions};
+use crate::vector::VectorStore;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Instant;
+
+const PROBE_TEXT: &str = "Metabrain diagnostics probe";
+
+/// Upper bound on requests in one embedding benchmark
+pub const MAX_BENCHMARK_SAMPLES: usize = 1000;
+
+/// How far from 1.0 a vector's norm may be and still count as normalized
+const UNIT_NORM_TOLERANCE: f32 = 1e-3;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticStep {
+    pub name: String,
+    pub passed: bool,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticsReport {
+    pub passed: bool,
+    pub steps: Vec<DiagnosticStep>,
+}
+
+impl DiagnosticsReport {
+    fn push(&mut self, name: &str, result: Result<String, String>) {
+        let (passed, message) = match result {
+            Ok(message) => (true, message),
+            Err(message) => (false, message),
+        };
+        self.passed &= passed;
+        self.steps.push(DiagnosticStep {
+            name: name.to_string(),
+            passed,
+            message,
+        });
+    }
+}
+
+/// Latency of the embedding endpoint over a run of short texts
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingBenchmark {
+    pub sample_count: usize,
+    pub total_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub embeds_per_second: f64,
+}
+
+/// What the configured embedding model returns for a fixed probe text
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingModelInfo {
+    pub model: String,
+    pub dimension: usize,
+    /// Euclidean length of the probe vector
+    pub norm: f32,
+    /// Whether the norm is 1, i.e. cosine and dot product rank alike
+    pub normalized: bool,
+}
+
+/// Embed the probe text once and describe the vector. Nothing is stored.
+pub async fn embedding_model_info(client: &EmbeddingClient) -> EmbeddingResult<EmbeddingModelInfo> {
+    let probe = client.embed_text(PROBE_TEXT).await?;
+    let norm = probe.embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+    Ok(EmbeddingModelInfo {
+        model: probe.model,
+        dimension: probe.dimension,
+        norm,
+        normalized: (norm - 1.0).abs() <= UNIT_NORM_TOLERANCE,
+    })
+}
+
+/// Embed `sample_count` short fixed texts one after another and time each
+/// request. Nothing is stored.
+pub async fn benchmark_embedding(client: &EmbeddingClient, sample_count: usize) -> EmbeddingResult<EmbeddingBenchmark> {
+    let started = Instant::now();
+    let mut latencies_ms = Vec::with_capacity(sample_count);
+    for i in 0..sample_count {
+        let request_started = Instant::now();
+        client.embed_document(&format!("{} {}", PROBE_TEXT, i)).await?;
+        latencies_ms.push(request_started.elapsed().as_secs_f64() * 1000.0);
+    }
+    let total_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    latencies_ms.sort_by(f64::total_cmp);
+    Ok(EmbeddingBenchmark {
+        sample_count,
+        total_ms,
+        p50_ms: percentile(&latencies_ms, 50.0),
+        p95_ms: percentile(&latencies_ms, 95.0),
+        embeds_per_second: if total_ms > 0.0 { sample_count as f64 / (total_ms / 1000.0) } else { 0.0 },
+    })
+}
+
+/// Nearest-rank percentile of sorted values, 0 when there are none
+fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Whether `wanted` is among the installed models, treating a missing tag as `:latest`
+fn has_model(models: &[String], wanted: &str) -> bool {
+    let normalize = |name: &str| {
+        if name.contains(':') { name.to_string() } else { format!("{}:latest", name) }
+    };
+    let wanted = normalize(wanted);
+    models.iter().any(|m| normalize(m) == wanted)
+}
+
+/// Check Ollama, the configured models, the database and a sample search
+pub async fn run_diagnostics(db: Arc<Database>, settings: &Settings) -> DiagnosticsReport {
+    let mut report = DiagnosticsReport {
+        passed: true,
+        steps: Vec::new(),
+    };
+
+    // 1. Ollama reachable (listing models doubles as the health check)
+    let provider = create_provider(
+        &settings.llm_provider,
+        &settings.ollama_endpoint,
+        &settings.ollama_model,
+        GenerationOptions::from_settings(settings),
+    );
+    let models = match provider {
+        Ok(provider) => provider.list_models().await.map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    report.push(
+        "ollama_reachable",
+        models
+            .as_ref()
+            .map(|m| format!("Ollama at {} is running with {} models", settings.ollama_endpoint, m.len()))
+            .map_err(|e| format!("Cannot reach Ollama at {}: {}", settings.ollama_endpoint, e)),
+    );
+
+    // 2-3. Chat and embedding models installed
+    for (name, model) in [
+        ("chat_model", &settings.ollama_model),
+        ("embedding_model", &settings.embedding_model),
+    ] {
+        // A separate embedding server isn't listed here; the search probe covers it
+        let separate_server = name == "embedding_model" && !settings.embedding_endpoint.trim().is_empty();
+        let result = match &models {
+            _ if separate_server => Ok(format!("{} is served by {}", model, settings.embedding_endpoint)),
+            Ok(models) if has_model(models, model) => Ok(format!("{} is installed", model)),
+            Ok(_) => Err(format!("{} is not installed; run `ollama pull {}`", model, model)),
+            Err(_) => Err("Skipped: Ollama is unreachable".to_string()),
+        };
+        report.push(name, result);
+    }
+
+    // 4. Database writable
+    report.push(
+        "database_writable",
+        db.check_writable()
+            .map(|_| "Database accepts writes".to_string())
+            .map_err(|e| format!("Database is not writable: {}", e)),
+    );
+
+    // 5. Something indexed
+    let artifact_count = db.count_artifacts();
+    report.push(
+        "artifacts_indexed",
+        match &artifact_count {
+            Ok(0) => Err("No notes indexed yet; sync your vault".to_string()),
+            Ok(count) => Ok(format!("{} notes indexed", count)),
+            Err(e) => Err(format!("Failed to count notes: {}", e)),
+        },
+    );
+
+    // 6. Embed a probe and search the index with it
+    let embedding_client = EmbeddingClient::from_settings(settings);
+    let search = match embedding_client.embed_query(PROBE_TEXT).await {
+        Ok(embedding) => match VectorStore::new(db.clone()).search(&embedding, 1) {
+            Ok(results) if results.is_empty() && matches!(artifact_count, Ok(n) if n > 0) => {
+                Err("Search returned no results despite indexed notes".to_string())
+            }
+            Ok(results) => Ok(format!("Embedded probe ({} dims), search returned {} results", embedding.len(), results.len())),
+            Err(e) => Err(format!("Search failed: {}", e)),
+        },
+        Err(e) => Err(format!("Embedding failed: {}", e)),
+    };
+    report.push("embed_and_search", search);
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{Artifact, Embedding};
+
+    async fn mock_ollama(server: &mut mockito::ServerGuard, models: &[&str]) {
+        let models: Vec<_> = models.iter().map(|m| serde_json::json!({ "name": m })).collect();
+        server
+            .mock("GET", "/api/tags")
+            .with_header("content-type", "application/json")
+            .with_body(serde_json::json!({ "models": models }).to_string())
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/api/embeddings")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"embedding": [1.0, 0.0, 0.0]}"#)
+            .create_async()
+            .await;
+    }
+
+    fn seeded_db(dir: &std::path::Path) -> Arc<Database> {
+        let db = Arc::new(Database::new(dir.to_path_buf()).unwrap());
+        db.upsert_artifact(&Artifact {
+            id: "a1".to_string(),
+            path: "/vault/note.md".to_string(),
+            ..Default::default()
+        }).unwrap();
+        db.insert_embedding(&Embedding {
+            id: "a1#0".to_string(),
+            artifact_id: "a1".to_string(),
+            chunk_index: 0,
+            content: "note".to_string(),
+            embedding: vec![1.0, 0.0, 0.0],
+        }).unwrap();
+        db
+    }
+
+    #[test]
+    fn test_nearest_rank_percentile() {
+        let latencies: Vec<f64> = (1..=20).map(f64::from).collect();
+        assert_eq!(percentile(&latencies, 50.0), 10.0);
+        assert_eq!(percentile(&latencies, 95.0), 19.0);
+        assert_eq!(percentile(&[], 95.0), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_embeds_the_requested_samples() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/embeddings")
+            .with_body(r#"{"embedding": [1.0, 0.0, 0.0]}"#)
+            .expect(7)
+            .create_async()
+            .await;
+        let client = EmbeddingClient::from_settings(&Settings { ollama_endpoint: server.url(), ..Settings::default() });
+
+        let benchmark = benchmark_embedding(&client, 7).await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(benchmark.sample_count, 7);
+        assert!(benchmark.p50_ms <= benchmark.p95_ms);
+        assert!(benchmark.p95_ms <= benchmark.total_ms);
+        assert!(benchmark.embeds_per_second > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_embedding_model_info_reports_dimension_and_norm() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/api/embeddings")
+            .with_body(r#"{"embedding": [3.0, 0.0, 4.0, 0.0]}"#)
+            .create_async()
+            .await;
+        let client = EmbeddingClient::from_settings(&Settings { ollama_endpoint: server.url(), ..Settings::default() });
+
+        let info = embedding_model_info(&client).await.unwrap();
+
+        assert_eq!(info.model, "nomic-embed-text");
+        assert_eq!(info.dimension, 4);
+        assert!((info.norm - 5.0).abs() < 1e-6);
+        assert!(!info.normalized);
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics_all_pass() {
+        let mut server = mockito::Server::new_async().await;
+        mock_ollama(&mut server, &["llama3.2:latest", "nomic-embed-text:latest"]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let settings = Settings { ollama_endpoint: server.url(), ..Settings::default() };
+
+        let report = run_diagnostics(seeded_db(dir.path()), &settings).await;
+
+        assert!(report.passed, "{:?}", report.steps);
+        assert_eq!(report.steps.len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics_missing_embedding_model() {
+        let mut server = mockito::Server::new_async().await;
+        mock_ollama(&mut server, &["llama3.2:latest"]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let settings = Settings { ollama_endpoint: server.url(), ..Settings::default() };
+
+        let report = run_diagnostics(seeded_db(dir.path()), &settings).await;
+
+        assert!(!report.passed);
+        let failed: Vec<_> = report.steps.iter().filter(|s| !s.passed).map(|s| s.name.as_str()).collect();
+        assert_eq!(failed, vec!["embedding_model"]);
+    }
+}
@@ -1,5 +1,9 @@
+use crate::db::{Database, Settings};
+use crate::endpoint::trim_endpoint;
+use crate::parser::normalize_text;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -8,14 +12,85 @@ pub enum EmbeddingError {
     Request(#[from] reqwest::Error),
     #[error("Ollama error: {0}")]
     Ollama(String),
+    #[error("Database error: {0}")]
+    Database(#[from] crate::db::DbError),
+    #[error("Unexpected embedding response: {0}")]
+    InvalidResponse(String),
 }
 
 pub type EmbeddingResult<T> = Result<T, EmbeddingError>;
 
-#[derive(Debug, Serialize)]
-struct EmbeddingRequest {
-    model: String,
-    prompt: String,
+/// Shape of the embedding request body
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmbeddingRequestFormat {
+    /// `{"model", "prompt": "text"}`, Ollama's `/api/embeddings`
+    #[default]
+    Prompt,
+    /// `{"model", "input": "text"}`
+    Input,
+    /// `{"model", "input": ["text"]}`, OpenAI-compatible servers
+    InputArray,
+}
+
+impl EmbeddingRequestFormat {
+    /// Parse a setting value, falling back to Ollama's format for unknown names
+    pub fn parse(name: &str) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "input" => EmbeddingRequestFormat::Input,
+            "input_array" => EmbeddingRequestFormat::InputArray,
+            _ => EmbeddingRequestFormat::Prompt,
+        }
+    }
+
+    fn body(self, model: &str, text: &str) -> serde_json::Value {
+        match self {
+            EmbeddingRequestFormat::Prompt => serde_json::json!({ "model": model, "prompt": text }),
+            EmbeddingRequestFormat::Input => serde_json::json!({ "model": model, "input": text }),
+            EmbeddingRequestFormat::InputArray => serde_json::json!({ "model": model, "input": [text] }),
+        }
+    }
+}
+
+/// Where the vector sits in the embedding response
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmbeddingResponseFormat {
+    /// `{"embedding": [...]}`, Ollama's `/api/embeddings`
+    #[default]
+    Embedding,
+    /// `{"embeddings": [[...]]}`, Ollama's `/api/embed`
+    Embeddings,
+    /// `{"data": [{"embedding": [...]}]}`, OpenAI-compatible servers
+    Data,
+}
+
+impl EmbeddingResponseFormat {
+    /// Parse a setting value, falling back to Ollama's format for unknown names
+    pub fn parse(name: &str) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "embeddings" => EmbeddingResponseFormat::Embeddings,
+            "data" => EmbeddingResponseFormat::Data,
+            _ => EmbeddingResponseFormat::Embedding,
+        }
+    }
+
+    fn extract(self, body: serde_json::Value) -> EmbeddingResult<Vec<f32>> {
+        let invalid = |e: serde_json::Error| EmbeddingError::InvalidResponse(e.to_string());
+        let first = match self {
+            EmbeddingResponseFormat::Embedding => {
+                Some(serde_json::from_value::<EmbeddingResponse>(body).map_err(invalid)?.embedding)
+            }
+            EmbeddingResponseFormat::Embeddings => {
+                serde_json::from_value::<EmbeddingsResponse>(body).map_err(invalid)?.embeddings.into_iter().next()
+            }
+            EmbeddingResponseFormat::Data => serde_json::from_value::<DataResponse>(body)
+                .map_err(invalid)?
+                .data
+                .into_iter()
+                .next()
+                .map(|item| item.embedding),
+        };
+        first.ok_or_else(|| EmbeddingError::InvalidResponse("no embeddings returned".to_string()))
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -23,10 +98,86 @@ struct EmbeddingResponse {
     embedding: Vec<f32>,
 }
 
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DataResponse {
+    data: Vec<EmbeddingResponse>,
+}
+
+/// An embedding along with the model that produced it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextEmbedding {
+    pub model: String,
+    pub dimension: usize,
+    pub embedding: Vec<f32>,
+}
+
+/// Output dimensions of common Ollama embedding models, used when a model
+/// hasn't been seen locally yet
+const KNOWN_MODEL_DIMENSIONS: &[(&str, usize)] = &[
+    ("nomic-embed-text", 768),
+    ("mxbai-embed-large", 1024),
+    ("all-minilm", 384),
+    ("snowflake-arctic-embed", 1024),
+    ("bge-m3", 1024),
+    ("bge-large", 1024),
+];
+
+/// The configured embedding model produces vectors that don't match the index
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DimensionMismatch {
+    pub model: String,
+    pub expected_dimension: usize,
+    pub stored_dimension: usize,
+}
+
+/// Expected output dimension for a model: recorded dimensions win over the
+/// built-in table. Tags like `:latest` are ignored for the table lookup.
+pub fn expected_dimension(model: &str, recorded: &HashMap<String, usize>) -> Option<usize> {
+    if let Some(dimension) = recorded.get(model) {
+        return Some(*dimension);
+    }
+
+    let base = model.split(':').next().unwrap_or(model);
+    KNOWN_MODEL_DIMENSIONS
+        .iter()
+        .find(|(name, _)| *name == base)
+        .map(|(_, dimension)| *dimension)
+}
+
+/// Decide whether the stored index is incompatible with the configured model.
+/// Unknown models or an empty index are never reported as mismatches.
+pub fn check_dimension_mismatch(
+    model: &str,
+    recorded: &HashMap<String, usize>,
+    stored_dimension: Option<usize>,
+) -> Option<DimensionMismatch> {
+    let stored_dimension = stored_dimension?;
+    let expected_dimension = expected_dimension(model, recorded)?;
+
+    (expected_dimension != stored_dimension).then(|| DimensionMismatch {
+        model: model.to_string(),
+        expected_dimension,
+        stored_dimension,
+    })
+}
+
 pub struct EmbeddingClient {
     client: Client,
     endpoint: String,
     model: String,
+    document_prefix: String,
+    query_prefix: String,
+    request_format: EmbeddingRequestFormat,
+    response_format: EmbeddingResponseFormat,
+    /// Longer input is truncated before sending, 0 for no limit
+    max_input_bytes: usize,
 }
 
 impl EmbeddingClient {
@@ -35,16 +186,43 @@ impl EmbeddingClient {
             client: Client::new(),
             endpoint,
             model,
+            document_prefix: String::new(),
+            query_prefix: String::new(),
+            request_format: EmbeddingRequestFormat::default(),
+            response_format: EmbeddingResponseFormat::default(),
+            max_input_bytes: 0,
+        }
+    }
+
+    /// Create a client for the configured embedding model, including its task
+    /// prefixes and the server's request/response format
+    pub fn from_settings(settings: &Settings) -> Self {
+        let endpoint = if settings.embedding_endpoint.trim().is_empty() {
+            &settings.ollama_endpoint
+        } else {
+            &settings.embedding_endpoint
+        };
+        Self {
+            document_prefix: settings.embedding_document_prefix.clone(),
+            query_prefix: settings.embedding_query_prefix.clone(),
+            request_format: EmbeddingRequestFormat::parse(&settings.embedding_request_format),
+            response_format: EmbeddingResponseFormat::parse(&settings.embedding_response_format),
+            max_input_bytes: settings.embedding_max_input_bytes,
+            ..Self::new(trim_endpoint(endpoint).to_string(), settings.embedding_model.clone())
         }
     }
 
     pub async fn embed(&self, text: &str) -> EmbeddingResult<Vec<f32>> {
         let url = format!("{}/api/embeddings", self.endpoint);
+
+        // Embed the prefix of oversized text rather than have the server reject it
+        let truncated = truncate_to_bytes(text, self.max_input_bytes);
+        if truncated.len() < text.len() {
+            let preview: String = text.chars().take(80).collect();
+            log::warn!("Truncated {}-byte embedding input to {} bytes: {:?}", text.len(), truncated.len(), preview);
+        }
         
-        let request = EmbeddingRequest {
-            model: self.model.clone(),
-            prompt: text.to_string(),
-        };
+        let request = self.request_format.body(&self.model, truncated);
 
         let response = self.client
             .post(&url)
@@ -57,8 +235,53 @@ impl EmbeddingClient {
             return Err(EmbeddingError::Ollama(error_text));
         }
 
-        let embedding_response: EmbeddingResponse = response.json().await?;
-        Ok(embedding_response.embedding)
+        let body: serde_json::Value = response.json().await?;
+        let embedding = self.response_format.extract(body)?;
+        if is_degenerate(&embedding) {
+            let preview: String = text.chars().take(80).collect();
+            log::warn!("Model {} returned an unusable embedding for: {:?}", self.model, preview);
+            return Err(EmbeddingError::InvalidResponse(
+                "embedding is all zeros or contains NaN".to_string(),
+            ));
+        }
+        Ok(embedding)
+    }
+
+    /// Output dimension of the configured model, probing it with a short
+    /// embed the first time and reusing the recorded value afterwards
+    pub async fn probe_dimension(&self, db: &Database) -> EmbeddingResult<usize> {
+        if let Some(dimension) = db.get_model_dimensions()?.get(&self.model) {
+            return Ok(*dimension);
+        }
+
+        let dimension = self.embed("dimension probe").await?.len();
+        log::info!("Embedding model {} produces {}-dim vectors", self.model, dimension);
+        db.record_model_dimension(&self.model, dimension)?;
+        Ok(dimension)
+    }
+
+    pub fn model_name(&self) -> &str {
+        &self.model
+    }
+
+    /// Embed a chunk for storage, applying the document prefix
+    pub async fn embed_document(&self, text: &str) -> EmbeddingResult<Vec<f32>> {
+        self.embed(&format!("{}{}", self.document_prefix, text)).await
+    }
+
+    /// Embed a search query, applying the query prefix
+    pub async fn embed_query(&self, text: &str) -> EmbeddingResult<Vec<f32>> {
+        self.embed(&format!("{}{}", self.query_prefix, normalize_text(text))).await
+    }
+
+    /// Embed arbitrary text, reporting the model and vector dimension
+    pub async fn embed_text(&self, text: &str) -> EmbeddingResult<TextEmbedding> {
+        let embedding = self.embed(text).await?;
+        Ok(TextEmbedding {
+            model: self.model.clone(),
+            dimension: embedding.len(),
+            embedding,
+        })
     }
 
     pub async fn embed_batch(&self, texts: &[String]) -> EmbeddingResult<Vec<Vec<f32>>> {
@@ -73,3 +296,251 @@ impl EmbeddingClient {
     }
 }
 
+
+/// The longest prefix of `text` within `max_bytes` that ends on a character
+/// boundary; all of it when `max_bytes` is 0
+fn truncate_to_bytes(text: &str, max_bytes: usize) -> &str {
+    if max_bytes == 0 || text.len() <= max_bytes {
+        return text;
+    }
+    let end = (0..=max_bytes).rev().find(|&i| text.is_char_boundary(i)).unwrap_or(0);
+    &text[..end]
+}
+
+/// A vector that can't be ranked: all zeros (no direction for cosine) or
+/// carrying NaN/infinite components that poison every score
+fn is_degenerate(embedding: &[f32]) -> bool {
+    embedding.iter().all(|v| *v == 0.0) || embedding.iter().any(|v| !v.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_embed_text_against_mock_server() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/embeddings")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "model": "nomic-embed-text",
+                "prompt": "find similar notes"
+            })))
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"embedding": [0.5, -0.25, 1.0]}"#)
+            .create_async()
+            .await;
+
+        let client = EmbeddingClient::new(server.url(), "nomic-embed-text".to_string());
+        let result = client.embed_text("find similar notes").await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(result.model, "nomic-embed-text");
+        assert_eq!(result.dimension, 3);
+        assert_eq!(result.embedding, vec![0.5, -0.25, 1.0]);
+    }
+
+    #[tokio::test]
+    async fn test_document_and_query_prefixes() {
+        let mut server = mockito::Server::new_async().await;
+        let document = server
+            .mock("POST", "/api/embeddings")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "prompt": "search_document: Notes on Rust lifetimes"
+            })))
+            .with_body(r#"{"embedding": [1.0]}"#)
+            .create_async()
+            .await;
+        let query = server
+            .mock("POST", "/api/embeddings")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "prompt": "search_query: what are lifetimes?"
+            })))
+            .with_body(r#"{"embedding": [2.0]}"#)
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            ollama_endpoint: server.url(),
+            embedding_document_prefix: "search_document: ".to_string(),
+            embedding_query_prefix: "search_query: ".to_string(),
+            ..Settings::default()
+        };
+        let client = EmbeddingClient::from_settings(&settings);
+
+        assert_eq!(client.embed_document("Notes on Rust lifetimes").await.unwrap(), vec![1.0]);
+        assert_eq!(client.embed_query("what are lifetimes?").await.unwrap(), vec![2.0]);
+        document.assert_async().await;
+        query.assert_async().await;
+    }
+
+    #[test]
+    fn test_dimension_mismatch_decision() {
+        let mut recorded = HashMap::new();
+        recorded.insert("custom-embed".to_string(), 512);
+
+        // Recorded dimension matches the index
+        assert_eq!(check_dimension_mismatch("custom-embed", &recorded, Some(512)), None);
+        // Switched to a known model with a different size
+        assert_eq!(
+            check_dimension_mismatch("mxbai-embed-large:latest", &recorded, Some(512)),
+            Some(DimensionMismatch {
+                model: "mxbai-embed-large:latest".to_string(),
+                expected_dimension: 1024,
+                stored_dimension: 512,
+            })
+        );
+        // Unknown model or empty index: nothing to report
+        assert_eq!(check_dimension_mismatch("mystery-model", &recorded, Some(512)), None);
+        assert_eq!(check_dimension_mismatch("custom-embed", &recorded, None), None);
+    }
+
+    #[tokio::test]
+    async fn test_probe_dimension_is_recorded_and_reused() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/embeddings")
+            .with_body(r#"{"embedding": [0.1, 0.2, 0.3, 0.4]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        let client = EmbeddingClient::new(server.url(), "custom-embedder".to_string());
+
+        assert_eq!(client.probe_dimension(&db).await.unwrap(), 4);
+        assert_eq!(db.get_model_dimensions().unwrap().get("custom-embedder"), Some(&4));
+
+        // Second call is served from the recorded value without another request
+        assert_eq!(client.probe_dimension(&db).await.unwrap(), 4);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_configurable_request_and_response_shapes() {
+        let cases = [
+            ("prompt", serde_json::json!({"prompt": "hello"}), "embedding", r#"{"embedding": [0.5, 1.5]}"#),
+            ("input", serde_json::json!({"input": "hello"}), "embeddings", r#"{"embeddings": [[0.5, 1.5]]}"#),
+            (
+                "input_array",
+                serde_json::json!({"input": ["hello"]}),
+                "data",
+                r#"{"object": "list", "data": [{"index": 0, "embedding": [0.5, 1.5]}]}"#,
+            ),
+        ];
+
+        for (request_format, expected_body, response_format, response) in cases {
+            let mut server = mockito::Server::new_async().await;
+            let mock = server
+                .mock("POST", "/api/embeddings")
+                .match_body(mockito::Matcher::PartialJson(expected_body))
+                .with_body(response)
+                .create_async()
+                .await;
+
+            let settings = Settings {
+                ollama_endpoint: server.url(),
+                embedding_request_format: request_format.to_string(),
+                embedding_response_format: response_format.to_string(),
+                ..Settings::default()
+            };
+            let client = EmbeddingClient::from_settings(&settings);
+
+            assert_eq!(client.embed("hello").await.unwrap(), vec![0.5, 1.5], "{}", request_format);
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embedding_endpoint_overrides_ollama_endpoint() {
+        let mut embedder = mockito::Server::new_async().await;
+        let mock = embedder
+            .mock("POST", "/api/embeddings")
+            .with_body(r#"{"embedding": [0.25]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        // The chat server would refuse the connection
+        let settings = Settings {
+            ollama_endpoint: "http://127.0.0.1:9".to_string(),
+            embedding_endpoint: embedder.url(),
+            ..Settings::default()
+        };
+        let client = EmbeddingClient::from_settings(&settings);
+
+        assert_eq!(client.embed("hello").await.unwrap(), vec![0.25]);
+        mock.assert_async().await;
+
+        let fallback = EmbeddingClient::from_settings(&Settings { embedding_endpoint: String::new(), ..settings });
+        assert!(matches!(fallback.embed("hello").await, Err(EmbeddingError::Request(_))));
+    }
+
+    #[tokio::test]
+    async fn test_response_in_wrong_shape_is_an_error() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/api/embeddings")
+            .with_body(r#"{"embeddings": []}"#)
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            ollama_endpoint: server.url(),
+            embedding_response_format: "embeddings".to_string(),
+            ..Settings::default()
+        };
+        let client = EmbeddingClient::from_settings(&settings);
+        assert!(matches!(client.embed("hello").await, Err(EmbeddingError::InvalidResponse(_))));
+
+        let client = EmbeddingClient::new(server.url(), "nomic-embed-text".to_string());
+        assert!(matches!(client.embed("hello").await, Err(EmbeddingError::InvalidResponse(_))));
+    }
+
+    #[tokio::test]
+    async fn test_zero_or_non_finite_embedding_is_rejected() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/api/embeddings")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({ "prompt": "zero" })))
+            .with_body(r#"{"embedding": [0.0, 0.0, 0.0]}"#)
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/api/embeddings")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({ "prompt": "overflow" })))
+            .with_body(r#"{"embedding": [0.5, 1e39, 0.1]}"#)
+            .create_async()
+            .await;
+
+        let client = EmbeddingClient::new(server.url(), "nomic-embed-text".to_string());
+        assert!(matches!(client.embed("zero").await, Err(EmbeddingError::InvalidResponse(_))));
+        assert!(matches!(client.embed("overflow").await, Err(EmbeddingError::InvalidResponse(_))));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_input_is_truncated_before_sending() {
+        let mut server = mockito::Server::new_async().await;
+        // "é" is two bytes, so a 7-byte cut has to back off to 6
+        let truncated = server
+            .mock("POST", "/api/embeddings")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({ "prompt": "ab\u{e9}\u{e9}" })))
+            .with_body(r#"{"embedding": [1.0, 0.5]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            ollama_endpoint: server.url(),
+            embedding_max_input_bytes: 7,
+            ..Settings::default()
+        };
+        let client = EmbeddingClient::from_settings(&settings);
+        assert_eq!(client.embed("ab\u{e9}\u{e9}\u{e9} and more").await.unwrap(), vec![1.0, 0.5]);
+        truncated.assert_async().await;
+
+        assert_eq!(truncate_to_bytes("short", 7), "short");
+        assert_eq!(truncate_to_bytes("unbounded text", 0), "unbounded text");
+    }
+}
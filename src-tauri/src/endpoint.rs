@@ -0,0 +1,143 @@
+//! Cleaning up server URLs pasted into settings before API paths are
+//! appended to them, and checking that one points at a live server.
+
+use crate::db::Settings;
+use reqwest::{Client, Url};
+use serde::Serialize;
+use std::time::Duration;
+
+/// How long `check_endpoint` waits for any response
+const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether an endpoint is usable as entered
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointCheck {
+    /// The URL requests would be sent to, `None` when malformed
+    pub normalized: Option<String>,
+    pub well_formed: bool,
+    pub reachable: bool,
+    pub message: String,
+}
+
+/// Surrounding whitespace and trailing slashes removed, so `{endpoint}/path`
+/// never doubles a slash
+pub fn trim_endpoint(endpoint: &str) -> &str {
+    endpoint.trim().trim_end_matches('/')
+}
+
+/// Trim an endpoint and check it is an absolute http(s) URL
+pub fn normalize_endpoint(endpoint: &str) -> Result<String, String> {
+    let trimmed = trim_endpoint(endpoint);
+    if !trimmed.starts_with("http://") && !trimmed.starts_with("https://") {
+        return Err(format!("Endpoint '{}' must start with http:// or https://", endpoint.trim()));
+    }
+    match Url::parse(trimmed) {
+        Ok(url) if url.host_str().is_some_and(|host| !host.is_empty()) => Ok(trimmed.to_string()),
+        _ => Err(format!("Endpoint '{}' is not a valid URL", endpoint.trim())),
+    }
+}
+
+/// Normalize an Ollama server URL, dropping an `/api` suffix the client
+/// appends itself
+pub fn normalize_ollama_endpoint(endpoint: &str) -> Result<String, String> {
+    let normalized = normalize_endpoint(endpoint)?;
+    match normalized.strip_suffix("/api") {
+        Some(base) => {
+            log::warn!("Removed /api from Ollama endpoint {}", normalized);
+            Ok(trim_endpoint(base).to_string())
+        }
+        None => Ok(normalized),
+    }
+}
+
+/// Normalize every configured endpoint in place. The optional ones may be
+/// blank: no embedding endpoint means the Ollama one is used, and no Outline
+/// URL just means Outline isn't set up
+pub fn normalize_settings_endpoints(settings: &mut Settings) -> Result<(), String> {
+    settings.ollama_endpoint = normalize_ollama_endpoint(&settings.ollama_endpoint)?;
+    settings.embedding_endpoint = normalize_optional(&settings.embedding_endpoint, normalize_ollama_endpoint)?;
+    settings.outline_base_url = normalize_optional(&settings.outline_base_url, normalize_endpoint)?;
+    Ok(())
+}
+
+fn normalize_optional(
+    endpoint: &str,
+    normalize: fn(&str) -> Result<String, String>,
+) -> Result<String, String> {
+    if endpoint.trim().is_empty() {
+        Ok(String::new())
+    } else {
+        normalize(endpoint)
+    }
+}
+
+/// Normalize `endpoint` and see whether anything answers there. Any HTTP
+/// response counts as reachable; only the connection is being tested
+pub async fn check_endpoint(endpoint: &str) -> EndpointCheck {
+    let normalized = match normalize_endpoint(endpoint) {
+        Ok(normalized) => normalized,
+        Err(message) => {
+            return EndpointCheck { normalized: None, well_formed: false, reachable: false, message };
+        }
+    };
+
+    let client = Client::builder()
+        .timeout(REACHABILITY_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    let (reachable, message) = match client.get(&normalized).send().await {
+        Ok(response) => (true, format!("{} responded with {}", normalized, response.status())),
+        Err(e) => (false, format!("Cannot reach {}: {}", normalized, e)),
+    };
+
+    EndpointCheck { normalized: Some(normalized), well_formed: true, reachable, message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_malformed_endpoints() {
+        assert_eq!(normalize_endpoint(" http://localhost:11434/ ").unwrap(), "http://localhost:11434");
+        assert_eq!(normalize_endpoint("https://example.com/api//").unwrap(), "https://example.com/api");
+        assert!(normalize_endpoint("localhost:11434").is_err());
+        assert!(normalize_endpoint("ftp://localhost").is_err());
+        assert!(normalize_endpoint("http://").is_err());
+        assert!(normalize_endpoint("").is_err());
+
+        assert_eq!(normalize_ollama_endpoint("http://localhost:11434/api/").unwrap(), "http://localhost:11434");
+        assert_eq!(normalize_ollama_endpoint("http://host/apis").unwrap(), "http://host/apis");
+
+        let mut settings = Settings {
+            ollama_endpoint: "http://localhost:11434/api".to_string(),
+            embedding_endpoint: "  ".to_string(),
+            outline_base_url: "https://app.getoutline.com/api/".to_string(),
+            ..Settings::default()
+        };
+        normalize_settings_endpoints(&mut settings).unwrap();
+        assert_eq!(settings.ollama_endpoint, "http://localhost:11434");
+        assert_eq!(settings.embedding_endpoint, "");
+        assert_eq!(settings.outline_base_url, "https://app.getoutline.com/api");
+
+        settings.embedding_endpoint = "gpu-box:11434".to_string();
+        assert!(normalize_settings_endpoints(&mut settings).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_endpoint_reachability() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("GET", "/").with_body("Ollama is running").create_async().await;
+
+        let check = check_endpoint(&format!("{}/", server.url())).await;
+        assert!(check.well_formed && check.reachable);
+        assert_eq!(check.normalized, Some(server.url()));
+
+        let check = check_endpoint("http://127.0.0.1:9").await;
+        assert!(check.well_formed && !check.reachable);
+
+        let check = check_endpoint("127.0.0.1:11434").await;
+        assert!(!check.well_formed && check.normalized.is_none());
+    }
+}
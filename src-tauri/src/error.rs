@@ -0,0 +1,158 @@
+//! The error every command returns: a stable `code` the frontend can branch
+//! on (e.g. "start Ollama" vs "check your API key") plus a readable message.
+
+use crate::db::DbError;
+use crate::embedding::EmbeddingError;
+use crate::ingest::IngestError;
+use crate::llm::LLMError;
+use crate::outline::OutlineError;
+use crate::rag::RagError;
+use crate::vector::VectorError;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AppError {
+    #[error("{0}")]
+    Database(#[from] DbError),
+    #[error("{0}")]
+    Embedding(#[from] EmbeddingError),
+    #[error("{0}")]
+    Llm(#[from] LLMError),
+    #[error("{0}")]
+    Outline(#[from] OutlineError),
+    #[error("{0}")]
+    Ingest(#[from] IngestError),
+    #[error("{0}")]
+    Rag(#[from] RagError),
+    #[error("{0}")]
+    Vector(#[from] VectorError),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("A sync is already running")]
+    SyncRunning,
+}
+
+impl AppError {
+    /// Machine-readable kind of failure, the same whichever layer it came through
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Database(e) => db_code(e),
+            AppError::Embedding(e) => embedding_code(e),
+            AppError::Llm(e) => llm_code(e),
+            AppError::Outline(e) => outline_code(e),
+            AppError::Ingest(e) => ingest_code(e),
+            AppError::Rag(e) => rag_code(e),
+            AppError::Vector(e) => vector_code(e),
+            AppError::Io(_) => "file_error",
+            AppError::InvalidInput(_) => "invalid_input",
+            AppError::SyncRunning => "sync_running",
+        }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("AppError", 2)?;
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.end()
+    }
+}
+
+/// Ollama not running or not reachable, as opposed to it answering with an error
+fn provider_request_code(e: &reqwest::Error) -> &'static str {
+    if e.is_connect() || e.is_timeout() {
+        "provider_unreachable"
+    } else {
+        "provider_error"
+    }
+}
+
+fn db_code(e: &DbError) -> &'static str {
+    match e {
+        DbError::NotFound(_) => "not_found",
+        #[cfg(feature = "sqlcipher")]
+        DbError::WrongPassphrase { .. } => "wrong_passphrase",
+        _ => "database",
+    }
+}
+
+fn embedding_code(e: &EmbeddingError) -> &'static str {
+    match e {
+        EmbeddingError::Request(e) => provider_request_code(e),
+        EmbeddingError::Database(e) => db_code(e),
+        EmbeddingError::Ollama(_) | EmbeddingError::InvalidResponse(_) => "provider_error",
+    }
+}
+
+fn llm_code(e: &LLMError) -> &'static str {
+    match e {
+        LLMError::Request(e) => provider_request_code(e),
+        LLMError::UnknownProvider(_) => "unknown_provider",
+        LLMError::Provider(_) | LLMError::Stream(_) => "provider_error",
+    }
+}
+
+fn outline_code(e: &OutlineError) -> &'static str {
+    match e {
+        OutlineError::MissingApiKey | OutlineError::Unauthorized => "outline_auth",
+        OutlineError::Request(e) if e.is_connect() || e.is_timeout() => "outline_unreachable",
+        _ => "outline_error",
+    }
+}
+
+fn ingest_code(e: &IngestError) -> &'static str {
+    match e {
+        IngestError::Database(e) => db_code(e),
+        IngestError::Embedding(e) => embedding_code(e),
+        IngestError::FileTooLarge { .. } => "file_too_large",
+        IngestError::Io(_) | IngestError::Parser(_) => "file_error",
+    }
+}
+
+fn rag_code(e: &RagError) -> &'static str {
+    match e {
+        RagError::Database(e) => db_code(e),
+        RagError::Embedding(e) => embedding_code(e),
+        RagError::Vector(e) => vector_code(e),
+        RagError::Llm(e) => llm_code(e),
+        RagError::NoContext => "no_content",
+    }
+}
+
+fn vector_code(e: &VectorError) -> &'static str {
+    match e {
+        VectorError::Database(e) => db_code(e),
+        VectorError::NoEmbeddings => "no_content",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_underlying_errors_map_to_codes() {
+        // Nothing listens on port 9 (discard), so the request fails to connect
+        let unreachable = reqwest::Client::new().get("http://127.0.0.1:9").send().await.unwrap_err();
+        let error = AppError::from(RagError::Embedding(EmbeddingError::Request(unreachable)));
+        assert_eq!(error.code(), "provider_unreachable");
+
+        assert_eq!(AppError::from(OutlineError::MissingApiKey).code(), "outline_auth");
+        assert_eq!(AppError::from(OutlineError::Unauthorized).code(), "outline_auth");
+        assert_eq!(AppError::from(LLMError::UnknownProvider("openai".to_string())).code(), "unknown_provider");
+        assert_eq!(AppError::from(RagError::Vector(VectorError::NoEmbeddings)).code(), "no_content");
+        assert_eq!(
+            AppError::from(IngestError::FileTooLarge { size: 10, limit: 5 }).code(),
+            "file_too_large"
+        );
+        assert_eq!(AppError::SyncRunning.code(), "sync_running");
+
+        let json = serde_json::to_value(AppError::from(DbError::NotFound("note".to_string()))).unwrap();
+        assert_eq!(json, serde_json::json!({ "code": "not_found", "message": "Not found: note" }));
+    }
+}
@@ -0,0 +1,236 @@
+//! Lightweight fuzzy matching over note titles and paths for jump-to-note UX,
+//! trigram keyword matching that tolerates typos in search queries, and the
+//! shared terms that explain why a chunk matched.
+
+use crate::db::Artifact;
+use crate::parser::normalize_text;
+use serde::Serialize;
+use std::collections::HashSet;
+
+const MATCH_SCORE: i64 = 1;
+const CONSECUTIVE_BONUS: i64 = 5;
+const WORD_START_BONUS: i64 = 8;
+const PREFIX_BONUS: i64 = 50;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FuzzyMatch {
+    pub artifact: Artifact,
+    pub score: i64,
+}
+
+/// Score `candidate` against `query` as a case-insensitive subsequence match.
+/// Whitespace in the query is ignored, so "prj notes" matches "Project Notes".
+/// Consecutive and word-start matches score higher, and an exact prefix wins.
+/// Returns `None` when the query isn't a subsequence of the candidate.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let query_chars: Vec<char> = query
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    if query_chars.is_empty() {
+        return None;
+    }
+
+    let candidate_lower = candidate.to_lowercase();
+    let candidate_chars: Vec<char> = candidate_lower.chars().collect();
+
+    let mut score = 0;
+    let mut matched = 0;
+    let mut last_match: Option<usize> = None;
+
+    for (i, ch) in candidate_chars.iter().enumerate() {
+        if matched == query_chars.len() {
+            break;
+        }
+        if *ch != query_chars[matched] {
+            continue;
+        }
+
+        score += MATCH_SCORE;
+        if i > 0 && last_match == Some(i - 1) {
+            score += CONSECUTIVE_BONUS;
+        }
+        if i == 0 || !candidate_chars[i - 1].is_alphanumeric() {
+            score += WORD_START_BONUS;
+        }
+        last_match = Some(i);
+        matched += 1;
+    }
+
+    if matched < query_chars.len() {
+        return None;
+    }
+
+    if candidate_lower.starts_with(query.trim().to_lowercase().as_str()) {
+        score += PREFIX_BONUS;
+    }
+
+    // Prefer shorter candidates among otherwise equal matches
+    Some(score - candidate_chars.len() as i64 / 10)
+}
+
+/// Rank artifacts by the better of their title and file-name scores
+pub fn rank_artifacts(query: &str, artifacts: Vec<Artifact>, limit: usize) -> Vec<FuzzyMatch> {
+    let mut matches: Vec<FuzzyMatch> = artifacts
+        .into_iter()
+        .filter_map(|artifact| {
+            let file_name = artifact.path.rsplit(['/', '\\']).next().unwrap_or(&artifact.path);
+            let title_score = artifact.title.as_deref().and_then(|t| fuzzy_score(query, t));
+            let path_score = fuzzy_score(query, file_name);
+            let score = title_score.max(path_score)?;
+            Some(FuzzyMatch { artifact, score })
+        })
+        .collect();
+
+    matches.sort_by_key(|m| std::cmp::Reverse(m.score));
+    matches.truncate(limit);
+    matches
+}
+
+/// Words shorter than this carry too few trigrams to compare meaningfully
+const MIN_KEYWORD_LEN: usize = 3;
+
+/// At most this many shared terms explain a match
+const MAX_SHARED_TERMS: usize = 5;
+
+/// Common words that say nothing about why a chunk matched
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "are", "was", "were", "what", "when", "where", "which", "who", "why",
+    "how", "with", "about", "from", "this", "that", "these", "those", "have", "has", "had",
+    "did", "does", "you", "your", "our", "their", "there", "into", "can", "not", "but", "all",
+];
+
+fn trigrams(word: &str) -> HashSet<String> {
+    let padded: Vec<char> = format!("  {} ", word).chars().collect();
+    padded.windows(3).map(|w| w.iter().collect()).collect()
+}
+
+fn keywords(text: &str) -> Vec<String> {
+    normalize_text(text)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= MIN_KEYWORD_LEN)
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// Jaccard similarity of two words' trigram sets, from 0.0 to 1.0
+pub fn trigram_similarity(a: &str, b: &str) -> f32 {
+    let (a, b) = (trigrams(&a.to_lowercase()), trigrams(&b.to_lowercase()));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f32 / union as f32
+}
+
+/// How well `text` covers the query's keywords, allowing misspellings: the
+/// mean over query keywords of their best trigram match among the text's words
+pub fn keyword_score(query: &str, text: &str) -> f32 {
+    let query_words = keywords(query);
+    if query_words.is_empty() {
+        return 0.0;
+    }
+    let text_words: HashSet<String> = keywords(text).into_iter().collect();
+
+    let total: f32 = query_words
+        .iter()
+        .map(|q| {
+            text_words
+                .iter()
+                .map(|w| trigram_similarity(q, w))
+                .fold(0.0, f32::max)
+        })
+        .sum();
+    total / query_words.len() as f32
+}
+
+/// Plural and singular forms of a word compare equal
+fn stem(word: &str) -> &str {
+    match word.strip_suffix('s') {
+        Some(stem) if stem.len() >= MIN_KEYWORD_LEN && !stem.ends_with('s') => stem,
+        _ => word,
+    }
+}
+
+/// Significant words the query and text have in common, in the text's
+/// spelling and query order, for highlighting why a chunk matched
+pub fn shared_terms(query: &str, text: &str) -> Vec<String> {
+    let text_words = keywords(text);
+    let mut terms: Vec<String> = Vec::new();
+
+    for query_word in keywords(query) {
+        if STOPWORDS.contains(&query_word.as_str()) {
+            continue;
+        }
+        let matched = text_words.iter().find(|w| stem(w) == stem(&query_word));
+        if let Some(word) = matched {
+            if !terms.contains(word) {
+                terms.push(word.clone());
+            }
+        }
+        if terms.len() == MAX_SHARED_TERMS {
+            break;
+        }
+    }
+
+    terms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn artifact(title: &str, path: &str) -> Artifact {
+        Artifact {
+            id: path.to_string(),
+            path: path.to_string(),
+            title: Some(title.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_subsequence_matching() {
+        assert!(fuzzy_score("prj notes", "Project Notes").is_some());
+        assert!(fuzzy_score("xyz", "Project Notes").is_none());
+        assert!(fuzzy_score("  ", "Project Notes").is_none());
+    }
+
+    #[test]
+    fn test_prefix_matches_rank_higher() {
+        let artifacts = vec![
+            artifact("My project list", "/vault/lists/my-project-list.md"),
+            artifact("Project Notes", "/vault/Project Notes.md"),
+            artifact("Groceries", "/vault/groceries.md"),
+        ];
+
+        let results = rank_artifacts("proj", artifacts, 10);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].artifact.title.as_deref(), Some("Project Notes"));
+        assert!(results[0].score > results[1].score);
+    }
+
+    #[test]
+    fn test_shared_terms_explain_match() {
+        let terms = shared_terms(
+            "What did we decide about the hiring budget for the meetings?",
+            "Meeting notes: the hiring budget was approved, pending the Q3 review.",
+        );
+        assert_eq!(terms, vec!["hiring", "budget", "meeting"]);
+
+        assert!(shared_terms("the and what", "the and what").is_empty());
+    }
+
+    #[test]
+    fn test_keyword_score_tolerates_typos() {
+        assert!(trigram_similarity("kubernets", "kubernetes") > 0.6);
+        assert!(trigram_similarity("kubernets", "gardening") < 0.1);
+
+        let text = "Deploying services to Kubernetes with Helm charts";
+        assert!(keyword_score("kubernets deploy", text) > 0.5);
+        assert!(keyword_score("sourdough starter", text) < 0.2);
+        assert_eq!(keyword_score("a b", text), 0.0);
+    }
+}
@@ -1,12 +1,15 @@
-use crate::db::{Artifact, Database, Embedding};
+use crate::db::{Artifact, Database, Embedding, Settings};
 use crate::embedding::EmbeddingClient;
-use crate::parser::MarkdownParser;
-use crate::watcher::scan_directory;
-use crate::SyncStatus;
-use std::path::Path;
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tauri::Manager;
+use crate::outline::{OutlineClient, OutlineDocument};
+use crate::parser::{content_hash, is_canvas_path, local_links, LocalLink, MarkdownParser, ParsedDocument, ParserOptions};
+use crate::watcher::{scan_directory, FileEvent, IndexingGate, ScanOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use uuid::Uuid;
 
@@ -20,23 +23,210 @@ pub enum IngestError {
     Embedding(#[from] crate::embedding::EmbeddingError),
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("File is {size} bytes, over the {limit} byte limit")]
+    FileTooLarge { size: u64, limit: u64 },
 }
 
 pub type IngestResult<T> = Result<T, IngestError>;
 
+/// Attachment types whose text can be indexed with the note linking them
+const ATTACHMENT_EXTENSIONS: &[&str] = &["txt", "md", "markdown", "csv", "json", "org", "rst"];
+
+/// Number of chunks embedded to measure throughput for an estimate
+const ESTIMATE_SAMPLE_CHUNKS: usize = 3;
+
+/// Projected size and duration of a full sync
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncEstimate {
+    pub total_files: usize,
+    /// Files that would be skipped for size or being near-empty
+    pub skipped_files: usize,
+    pub total_chunks: usize,
+    pub sampled_chunks: usize,
+    pub chunks_per_second: Option<f64>,
+    pub estimated_seconds: Option<f64>,
+}
+
+/// A note whose stored content hash doesn't match its file
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HashMismatch {
+    pub path: String,
+    pub stored_hash: String,
+    pub current_hash: String,
+}
+
+/// Result of checking stored content hashes against the files on disk
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HashVerification {
+    /// File-backed notes whose file could be read
+    pub checked: usize,
+    pub mismatches: Vec<HashMismatch>,
+    /// Mismatched notes re-ingested successfully
+    pub repaired: usize,
+}
+
+/// Progress of the current or most recent sync
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStatus {
+    pub is_running: bool,
+    pub total_files: usize,
+    pub processed_files: usize,
+    /// Files skipped for exceeding the size limit
+    pub skipped_files: usize,
+    /// Files already finished by an interrupted sync and not redone
+    pub resumed_files: usize,
+    pub last_sync_at: Option<i64>,
+    pub error: Option<String>,
+}
+
+impl Default for SyncStatus {
+    fn default() -> Self {
+        Self {
+            is_running: false,
+            total_files: 0,
+            processed_files: 0,
+            skipped_files: 0,
+            resumed_files: 0,
+            last_sync_at: None,
+            error: None,
+        }
+    }
+}
+
+/// Extrapolate a throughput and total duration from timing `sampled` embeds
+fn extrapolate_duration(total_chunks: usize, sampled: usize, elapsed: Duration) -> (Option<f64>, Option<f64>) {
+    let seconds = elapsed.as_secs_f64();
+    if sampled == 0 || seconds <= 0.0 {
+        return (None, None);
+    }
+    let rate = sampled as f64 / seconds;
+    (Some(rate), Some(total_chunks as f64 / rate))
+}
+
+/// Lets one sync run at a time across every trigger (vault, Outline,
+/// repair). Checked before waiting on the ingest engine, so a second
+/// trigger returns at once instead of queueing another full pass. It also
+/// holds the running sync's status, readable while the engine is busy.
+#[derive(Debug, Clone, Default)]
+pub struct SyncGuard {
+    running: Arc<AtomicBool>,
+    status: Arc<Mutex<SyncStatus>>,
+}
+
+impl SyncGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Status of the running sync, or of the last one once it has finished
+    pub fn status(&self) -> SyncStatus {
+        self.status.lock().map(|status| status.clone()).unwrap_or_default()
+    }
+
+    /// Record the running sync's file progress
+    pub fn report_progress(&self, processed: usize, total: usize) {
+        if let Ok(mut status) = self.status.lock() {
+            status.processed_files = processed;
+            status.total_files = total;
+        }
+    }
+
+    /// Run `sync` unless another sync is in progress, in which case return
+    /// `None` without running it
+    pub async fn run<T, Fut>(&self, sync: impl FnOnce() -> Fut) -> Option<T>
+    where
+        Fut: Future<Output = T>,
+    {
+        let _permit = self.try_start()?;
+        Some(sync().await)
+    }
+
+    fn try_start(&self) -> Option<SyncPermit> {
+        self.running
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .ok()?;
+        if let Ok(mut status) = self.status.lock() {
+            *status = SyncStatus {
+                is_running: true,
+                last_sync_at: status.last_sync_at,
+                ..SyncStatus::default()
+            };
+        }
+        Some(SyncPermit { running: self.running.clone(), status: self.status.clone() })
+    }
+}
+
+/// Marks a sync as running until dropped, so a failed or cancelled sync
+/// still frees the guard
+struct SyncPermit {
+    running: Arc<AtomicBool>,
+    status: Arc<Mutex<SyncStatus>>,
+}
+
+impl Drop for SyncPermit {
+    fn drop(&mut self) {
+        if let Ok(mut status) = self.status.lock() {
+            status.is_running = false;
+        }
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Notes parsed ahead of a sync to size its embedding work. Notes that will
+/// be embedded are kept so indexing doesn't parse them a second time;
+/// unchanged ones are only recorded so indexing can skip them.
+#[derive(Default)]
+struct SyncPlan {
+    parsed: HashMap<PathBuf, ParsedDocument>,
+    unchanged: HashSet<PathBuf>,
+    /// Chunks each new or changed note will embed
+    chunks: HashMap<PathBuf, usize>,
+    total_chunks: usize,
+    embedded_chunks: usize,
+}
+
 pub struct IngestEngine {
     db: Arc<Database>,
     parser: MarkdownParser,
     embedding_client: EmbeddingClient,
+    min_word_count: usize,
+    /// Maximum file size in bytes, 0 for no limit
+    max_file_size: u64,
+    index_attachments: bool,
+    scan_options: ScanOptions,
+    /// Report progress only for files that get embedded, not unchanged ones
+    quiet_unchanged_files: bool,
+    /// Vault roots, used to tag each note with the vault it came from
+    vault_paths: Vec<String>,
     status: SyncStatus,
 }
 
 impl IngestEngine {
-    pub fn new(db: Arc<Database>, ollama_endpoint: String, embedding_model: String) -> Self {
+    pub fn new(db: Arc<Database>, settings: &Settings) -> Self {
+        Self::with_embedding_client(db, EmbeddingClient::from_settings(settings), settings)
+    }
+
+    /// Build an engine around an existing client, e.g. to index a folder
+    /// headless: the sync reports progress only through its callback
+    pub fn with_embedding_client(db: Arc<Database>, embedding_client: EmbeddingClient, settings: &Settings) -> Self {
         Self {
             db,
-            parser: MarkdownParser::new(),
-            embedding_client: EmbeddingClient::new(ollama_endpoint, embedding_model),
+            parser: MarkdownParser::with_options(ParserOptions::from_settings(settings)),
+            embedding_client,
+            min_word_count: settings.min_word_count,
+            max_file_size: settings.max_file_size_mb.saturating_mul(1024 * 1024),
+            index_attachments: settings.index_attachments,
+            scan_options: ScanOptions::from_settings(settings),
+            quiet_unchanged_files: settings.quiet_unchanged_files,
+            vault_paths: settings.vault_paths.clone(),
             status: SyncStatus::default(),
         }
     }
@@ -45,32 +235,93 @@ impl IngestEngine {
         self.status.clone()
     }
 
-    pub async fn sync_vault(
+    /// Sync several vaults as one run, reporting `(processed, total,
+    /// current_file)` through `on_progress` and `(embedded_chunks,
+    /// total_chunks)` across the whole run through `on_embedding`. Notes are
+    /// parsed up front to count the chunks that need embedding. A vault whose
+    /// folder is missing is reported in the status and skipped, leaving its
+    /// notes in the index.
+    pub async fn sync_vaults_with_embedding_progress<F, P>(
         &mut self,
-        vault_path: &str,
-        app_handle: &tauri::AppHandle,
-    ) -> IngestResult<SyncStatus> {
-        let path = Path::new(vault_path);
-        
-        if !path.exists() || !path.is_dir() {
-            self.status.error = Some("Invalid vault path".to_string());
+        vault_paths: &[String],
+        on_progress: F,
+        on_embedding: P,
+    ) -> IngestResult<SyncStatus>
+    where
+        F: Fn(usize, usize, &str),
+        P: Fn(usize, usize),
+    {
+        let (valid, invalid): (Vec<&String>, Vec<&String>) =
+            vault_paths.iter().partition(|p| Path::new(p.as_str()).is_dir());
+        let invalid_error = (!invalid.is_empty()).then(|| {
+            let paths: Vec<&str> = invalid.iter().map(|p| p.as_str()).collect();
+            format!("Invalid vault path: {}", paths.join(", "))
+        });
+
+        if valid.is_empty() {
+            self.status.error = Some(invalid_error.unwrap_or_else(|| "No vault path configured".to_string()));
             return Ok(self.status.clone());
         }
 
         self.status.is_running = true;
         self.status.error = None;
         self.status.processed_files = 0;
+        self.status.skipped_files = 0;
+        self.status.resumed_files = 0;
         
-        // Scan for all markdown files
-        let files = scan_directory(path);
-        self.status.total_files = files.len();
+        // Scan every vault for markdown files up front so progress covers the whole run
+        let mut vaults = Vec::new();
+        for vault_path in valid {
+            if !self.vault_paths.contains(vault_path) {
+                self.vault_paths.push(vault_path.clone());
+            }
+            vaults.push((vault_path.as_str(), scan_directory(Path::new(vault_path), &self.scan_options)));
+        }
+        self.status.total_files = vaults.iter().map(|(_, files)| files.len()).sum();
+        let mut plan = self.plan_sync(vaults.iter().flat_map(|(_, files)| files))?;
+        log::info!("Sync will embed {} chunks", plan.total_chunks);
         
-        // Emit initial progress
-        let _ = app_handle.emit_all("sync-progress", serde_json::json!({
-            "processed": 0,
-            "total": self.status.total_files,
-            "currentFile": ""
-        }));
+        // Initial progress
+        on_progress(0, self.status.total_files, "");
+        on_embedding(0, plan.total_chunks);
+
+        for (vault_path, files) in vaults {
+            self.sync_files(vault_path, files, &mut plan, &on_progress, &on_embedding).await?;
+        }
+
+        self.status.is_running = false;
+        self.status.error = invalid_error;
+        self.status.last_sync_at = Some(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64
+        );
+
+        Ok(self.status.clone())
+    }
+
+    /// Index one vault's scanned files, then drop its notes that are no
+    /// longer on disk. Notes of other vaults and sources are left alone.
+    async fn sync_files<F, P>(
+        &mut self,
+        vault_path: &str,
+        files: Vec<PathBuf>,
+        plan: &mut SyncPlan,
+        on_progress: &F,
+        on_embedding: &P,
+    ) -> IngestResult<()>
+    where
+        F: Fn(usize, usize, &str),
+        P: Fn(usize, usize),
+    {
+        // Files an interrupted sync already finished, unchanged since
+        let checkpoints = self.db.get_sync_checkpoints(vault_path)?;
+        if !checkpoints.is_empty() {
+            log::info!("Resuming interrupted sync: {} files already done", checkpoints.len());
+        }
+        let mut failed = false;
+        let scanned: HashSet<String> = files.iter().map(|f| f.to_string_lossy().to_string()).collect();
 
         // Process each file
         for file_path in files {
@@ -78,100 +329,1407 @@ impl IngestEngine {
                 .and_then(|n| n.to_str())
                 .unwrap_or("unknown");
             
-            // Emit progress
-            let _ = app_handle.emit_all("sync-progress", serde_json::json!({
-                "processed": self.status.processed_files,
-                "total": self.status.total_files,
-                "currentFile": file_name
-            }));
+            if !self.quiet_unchanged_files {
+                on_progress(self.status.processed_files, self.status.total_files, file_name);
+            }
+
+            let path_str = file_path.to_string_lossy().to_string();
+            if let Some(done_hash) = checkpoints.get(&path_str) {
+                let unchanged = std::fs::read(&file_path)
+                    .map(|bytes| content_hash(&String::from_utf8_lossy(&bytes)) == *done_hash)
+                    .unwrap_or(false);
+                if unchanged {
+                    self.status.resumed_files += 1;
+                    self.status.processed_files += 1;
+                    continue;
+                }
+            }
+
+            if plan.unchanged.contains(&file_path) {
+                self.status.processed_files += 1;
+                continue;
+            }
 
-            if let Err(e) = self.process_file(&file_path).await {
-                log::warn!("Failed to process file {:?}: {}", file_path, e);
-                // Continue with other files
+            // Count this note's planned chunks as they are embedded
+            let planned_end = plan.embedded_chunks + plan.chunks.get(&file_path).copied().unwrap_or(0);
+            let parsed = plan.parsed.remove(&file_path);
+            let mut on_chunk = || {
+                if plan.embedded_chunks < planned_end {
+                    plan.embedded_chunks += 1;
+                    on_embedding(plan.embedded_chunks, plan.total_chunks);
+                }
+            };
+
+            match self.index_file(&file_path, false, parsed, &mut on_chunk).await {
+                Ok(embedded) => {
+                    if let Ok(content) = std::fs::read_to_string(&file_path) {
+                        self.db.record_sync_checkpoint(vault_path, &path_str, &content_hash(&content))?;
+                    }
+                    if embedded && self.quiet_unchanged_files {
+                        on_progress(self.status.processed_files, self.status.total_files, file_name);
+                    }
+                }
+                Err(IngestError::FileTooLarge { size, limit }) => {
+                    log::info!("Skipping {:?}: {} bytes exceeds the {} byte limit", file_path, size, limit);
+                    self.status.skipped_files += 1;
+                }
+                Err(e) => {
+                    log::warn!("Failed to process file {:?}: {}", file_path, e);
+                    failed = true;
+                    // Continue with other files
+                }
+            }
+
+            // A note that was moved or failed embeds fewer chunks than planned
+            if plan.embedded_chunks < planned_end {
+                plan.embedded_chunks = planned_end;
+                on_embedding(plan.embedded_chunks, plan.total_chunks);
             }
             
             self.status.processed_files += 1;
         }
 
-        self.status.is_running = false;
-        self.status.last_sync_at = Some(
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as i64
-        );
+        for artifact in self.db.get_all_artifacts()? {
+            if in_vault(&artifact, vault_path) && !scanned.contains(&artifact.path) {
+                log::info!("Removing {:?}: no longer in vault", artifact.path);
+                self.db.delete_artifact_by_path(&artifact.path)?;
+            }
+        }
 
-        // Emit completion
-        let _ = app_handle.emit_all("sync-complete", &self.status);
+        // A clean run needs no resume point; keep it while files still fail
+        if !failed {
+            self.db.clear_sync_checkpoints(vault_path)?;
+        }
+        Ok(())
+    }
 
-        Ok(self.status.clone())
+    /// Parse the scanned notes and count the chunks of those that are new or
+    /// changed, caching only their parses. Notes that can't be indexed are
+    /// left out of the plan and indexing reports them as usual.
+    fn plan_sync<'a>(&self, files: impl IntoIterator<Item = &'a PathBuf>) -> IngestResult<SyncPlan> {
+        let mut plan = SyncPlan::default();
+        for path in files {
+            let too_large = std::fs::metadata(path)
+                .map(|m| self.max_file_size > 0 && m.len() > self.max_file_size)
+                .unwrap_or(true);
+            if too_large || self.in_trash(path) {
+                continue;
+            }
+            let Ok(parsed) = self.parse_note(path) else {
+                continue;
+            };
+
+            if !parsed.frontmatter.noindex && parsed.word_count() >= self.min_word_count {
+                let unchanged = self.db
+                    .get_artifact_by_path(&path.to_string_lossy())?
+                    .is_some_and(|existing| existing.content_hash == parsed.content_hash);
+                if unchanged {
+                    plan.unchanged.insert(path.clone());
+                } else {
+                    plan.chunks.insert(path.clone(), parsed.chunks.len());
+                    plan.total_chunks += parsed.chunks.len();
+                    plan.parsed.insert(path.clone(), parsed);
+                }
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Parse a note along with the attachments it links to
+    fn parse_note(&self, path: &Path) -> IngestResult<ParsedDocument> {
+        let mut parsed = self.parser.parse_file(path)?;
+        if self.index_attachments && !is_canvas_path(path) {
+            self.add_attachments(path, &mut parsed)?;
+        }
+        Ok(parsed)
+    }
+
+    /// The configured vault containing `path`, the innermost if vaults nest
+    fn vault_for(&self, path: &Path) -> Option<String> {
+        self.vault_paths
+            .iter()
+            .filter(|vault| path.starts_with(vault.as_str()))
+            .max_by_key(|vault| vault.len())
+            .cloned()
+    }
+
+    /// Whether `path` is inside the trash folder of the vault containing it
+    fn in_trash(&self, path: &Path) -> bool {
+        self.vault_for(path)
+            .and_then(|vault| path.strip_prefix(vault).ok().map(|relative| self.scan_options.is_trashed(relative)))
+            .unwrap_or(false)
+    }
+
+    /// Count files and chunks in a vault and time a few embeds to project how
+    /// long a full sync would take, without writing anything
+    pub async fn estimate_sync(&self, vault_path: &str) -> IngestResult<SyncEstimate> {
+        let files = scan_directory(Path::new(vault_path), &self.scan_options);
+        let mut estimate = SyncEstimate {
+            total_files: files.len(),
+            ..SyncEstimate::default()
+        };
+        let mut samples: Vec<String> = Vec::new();
+
+        for file_path in &files {
+            let too_large = std::fs::metadata(file_path)
+                .map(|m| self.max_file_size > 0 && m.len() > self.max_file_size)
+                .unwrap_or(true);
+            if too_large {
+                estimate.skipped_files += 1;
+                continue;
+            }
+            match self.parser.parse_file(file_path) {
+                Ok(parsed) if parsed.word_count() >= self.min_word_count => {
+                    estimate.total_chunks += parsed.chunks.len();
+                    let needed = ESTIMATE_SAMPLE_CHUNKS - samples.len();
+                    samples.extend(parsed.chunks.into_iter().take(needed));
+                }
+                _ => estimate.skipped_files += 1,
+            }
+        }
+
+        let started = Instant::now();
+        for sample in &samples {
+            self.embedding_client.embed_document(sample).await?;
+        }
+        estimate.sampled_chunks = samples.len();
+        (estimate.chunks_per_second, estimate.estimated_seconds) =
+            extrapolate_duration(estimate.total_chunks, samples.len(), started.elapsed());
+
+        Ok(estimate)
     }
 
     pub async fn process_file(&mut self, path: &Path) -> IngestResult<()> {
+        self.index_file(path, false, None, &mut || {}).await.map(|_| ())
+    }
+
+    /// Re-embed artifacts that have no embeddings from their source files,
+    /// returning how many were fixed. Artifacts whose file is gone, or that
+    /// come from another source, are left alone.
+    pub async fn repair_embeddings(&mut self) -> IngestResult<usize> {
+        let mut fixed = 0;
+        for artifact in self.db.get_artifacts_without_embeddings()? {
+            let path = Path::new(&artifact.path);
+            if artifact.path.contains("://") || !path.exists() {
+                log::info!("Not repairing {:?}: source file not found", artifact.path);
+                continue;
+            }
+            match self.index_file(path, true, None, &mut || {}).await {
+                Ok(_) if !self.db.get_artifact_chunks(&artifact.id)?.is_empty() => fixed += 1,
+                Ok(_) => {}
+                Err(e) => log::warn!("Failed to repair {:?}: {}", artifact.path, e),
+            }
+        }
+        Ok(fixed)
+    }
+
+    /// Recompute the content hash of every file-backed note and report those
+    /// that differ from the stored one, which sync would wrongly skip or
+    /// redo. With `repair`, mismatched notes are re-ingested. Notes from other
+    /// sources or whose file is gone are left alone.
+    pub async fn verify_hashes(&mut self, repair: bool) -> IngestResult<HashVerification> {
+        let mut report = HashVerification::default();
+        for artifact in self.db.get_all_artifacts()? {
+            let path = Path::new(&artifact.path);
+            if artifact.path.contains("://") || !path.is_file() {
+                continue;
+            }
+            let parsed = match self.parse_note(path) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    log::warn!("Cannot verify {:?}: {}", artifact.path, e);
+                    continue;
+                }
+            };
+            report.checked += 1;
+            if parsed.content_hash == artifact.content_hash {
+                continue;
+            }
+
+            log::info!("Stored hash of {:?} is stale", artifact.path);
+            report.mismatches.push(HashMismatch {
+                path: artifact.path.clone(),
+                stored_hash: artifact.content_hash,
+                current_hash: parsed.content_hash.clone(),
+            });
+            // The hashes differ, so this re-indexes and keeps unchanged chunks' vectors
+            if repair {
+                match self.index_file(path, false, Some(parsed), &mut || {}).await {
+                    Ok(_) => report.repaired += 1,
+                    Err(e) => log::warn!("Failed to repair {:?}: {}", artifact.path, e),
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Index one file; `force` re-embeds it even if its content is unchanged.
+    /// `parsed` reuses a parse done ahead of time, and `on_chunk` is called as
+    /// each chunk is stored. Returns whether the note was embedded, as
+    /// opposed to skipped or moved.
+    async fn index_file<C: FnMut()>(
+        &mut self,
+        path: &Path,
+        force: bool,
+        parsed: Option<ParsedDocument>,
+        on_chunk: &mut C,
+    ) -> IngestResult<bool> {
         let path_str = path.to_string_lossy().to_string();
-        log::info!("Processing file {:?}", path_str);
-        // Parse the markdown file
-        let parsed = self.parser.parse_file(path)?;
+        log::debug!("Processing file {:?}", path_str);
+
+        // A note moved to the trash folder is deleted, not moved
+        if self.in_trash(path) {
+            log::debug!("Skipping trashed file {:?}", path_str);
+            self.db.delete_artifact_by_path(&path_str)?;
+            return Ok(false);
+        }
+
+        // Refuse oversized files (generated logs, exported chats) before reading them
+        let metadata = std::fs::metadata(path)?;
+        if self.max_file_size > 0 && metadata.len() > self.max_file_size {
+            return Err(IngestError::FileTooLarge {
+                size: metadata.len(),
+                limit: self.max_file_size,
+            });
+        }
+
+        // Parse the markdown file, unless a sync already has
+        let parsed = match parsed {
+            Some(parsed) => parsed,
+            None => self.parse_note(path)?,
+        };
+
+        // Skip notes opted out in frontmatter, dropping any artifact indexed before
+        if parsed.frontmatter.noindex {
+            log::debug!("Skipping noindex file {:?}", path_str);
+            self.db.delete_artifact_by_path(&path_str)?;
+            return Ok(false);
+        }
+
+        // Skip empty or near-empty notes, dropping any artifact indexed before
+        if parsed.word_count() < self.min_word_count {
+            log::debug!("Skipping near-empty file {:?}", path_str);
+            self.db.delete_artifact_by_path(&path_str)?;
+            return Ok(false);
+        }
         
         // Check if file has changed
+        let mut existing_id = None;
+        let mut reusable: HashMap<String, Vec<f32>> = HashMap::new();
         if let Some(existing) = self.db.get_artifact_by_path(&path_str)? {
-            if existing.content_hash == parsed.content_hash {
+            if !force && existing.content_hash == parsed.content_hash {
                 // File hasn't changed, skip
-                return Ok(());
+                return Ok(false);
             }
-            // File has changed, delete old embeddings
+            // File has changed: keep vectors of unchanged chunks, delete old embeddings
+            reusable = self.db.get_chunk_embeddings(&existing.id)?;
             self.db.delete_embeddings_by_artifact(&existing.id)?;
+            existing_id = Some(existing.id);
         }
         
-        // Get file metadata
-        let metadata = std::fs::metadata(path)?;
         let last_modified = metadata
             .modified()
             .ok()
             .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
             .map(|d| d.as_secs() as i64)
             .unwrap_or(0);
+
+        // Prefer the frontmatter title, falling back to the file name
+        let title = parsed.frontmatter.title.clone().or_else(|| {
+            path.file_stem()
+                .and_then(|n| n.to_str())
+                .map(|n| n.to_string())
+        });
+
+        // A new path with the same content as an artifact whose file is gone
+        // is a move: repoint the artifact and keep its embeddings
+        let moved = match existing_id {
+            Some(_) => None,
+            None => self.find_moved_artifact(&path_str, &parsed.content_hash)?,
+        };
+        if let Some(mut moved) = moved {
+            log::info!("Detected move {:?} -> {:?}", moved.path, path_str);
+            moved.path = path_str;
+            moved.last_modified = last_modified;
+            moved.title = title;
+            moved.vault = self.vault_for(path);
+            self.db.upsert_artifact(&moved)?;
+            return Ok(false);
+        }
         
-        // Create artifact ID
-        let artifact_id = Uuid::new_v4().to_string();
+        // Keep the ID of a changed file, otherwise create one
+        let artifact_id = existing_id.unwrap_or_else(|| Uuid::new_v4().to_string());
         
-        // Create and store artifact
-        let artifact = Artifact {
+        log::info!("Indexing file {:?}", path_str);
+
+        // Store the artifact without its hash until every chunk is in, so a
+        // note whose embedding fails partway is redone rather than skipped
+        let mut artifact = Artifact {
             id: artifact_id.clone(),
             path: path_str,
             last_modified,
-            content_hash: parsed.content_hash,
+            content_hash: String::new(),
             indexed_at: SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
                 .as_secs() as i64,
+            title,
+            tags: parsed.frontmatter.tags.clone(),
+            pinned: false,
+            vault: self.vault_for(path),
+            collection_id: None,
+            collection: None,
         };
         self.db.upsert_artifact(&artifact)?;
+        self.embed_chunks(&artifact_id, &parsed.chunks, &reusable, on_chunk).await?;
+
+        artifact.content_hash = parsed.content_hash;
+        self.db.upsert_artifact(&artifact)?;
         
-        // Process each chunk
-        for (chunk_index, chunk_content) in parsed.chunks.iter().enumerate() {
-            // Generate embedding
-            let embedding_vec = self.embedding_client.embed(chunk_content).await?;
+        Ok(true)
+    }
+
+    /// Store an embedding for each chunk of an artifact, calling `on_chunk`
+    /// after each. A chunk reuses the vector in `reusable` under its hash or
+    /// the cached vector of the same text, and is embedded otherwise.
+    async fn embed_chunks<C: FnMut()>(
+        &self,
+        artifact_id: &str,
+        chunks: &[String],
+        reusable: &HashMap<String, Vec<f32>>,
+        on_chunk: &mut C,
+    ) -> IngestResult<()> {
+        for (chunk_index, chunk_content) in chunks.iter().enumerate() {
+            let chunk_hash = content_hash(chunk_content);
+            let model = self.embedding_client.model_name();
+            let embedding_vec = match reusable.get(&chunk_hash) {
+                Some(existing) => existing.clone(),
+                None => match self.db.get_cached_embedding(&chunk_hash, model)? {
+                    Some(cached) => cached,
+                    None => {
+                        let embedding_vec = self.embedding_client.embed_document(chunk_content).await?;
+                        self.db.record_model_dimension(model, embedding_vec.len())?;
+                        self.db.cache_embedding(&chunk_hash, model, &embedding_vec)?;
+                        embedding_vec
+                    }
+                },
+            };
             
             // Create embedding record
             let embedding = Embedding {
                 id: format!("{}#{}", artifact_id, chunk_index),
-                artifact_id: artifact_id.clone(),
+                artifact_id: artifact_id.to_string(),
                 chunk_index: chunk_index as i32,
                 content: chunk_content.clone(),
                 embedding: embedding_vec,
             };
             
             self.db.insert_embedding(&embedding)?;
+            on_chunk();
+        }
+        Ok(())
+    }
+
+    /// Append the text of local attachments the note links to as extra
+    /// chunks, folding their hashes into the note's so edits trigger a re-index.
+    /// Only files inside the note's vault (its folder, outside any vault) are
+    /// read, whatever `../` or absolute paths a link uses.
+    fn add_attachments(&self, note_path: &Path, parsed: &mut ParsedDocument) -> IngestResult<()> {
+        let raw = std::fs::read_to_string(note_path)?;
+        let note_dir = note_path.parent().unwrap_or(Path::new(""));
+        let vault_root = self
+            .vault_for(note_path)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| note_dir.to_path_buf())
+            .canonicalize()?;
+        let note_path_canonical = note_path.canonicalize()?;
+        let mut hashes = vec![parsed.content_hash.clone()];
+
+        for link in local_links(&raw) {
+            let (base, target) = match &link {
+                LocalLink::Relative(target) => (note_dir, target),
+                LocalLink::Wiki(target) => (vault_root.as_path(), target),
+            };
+            let supported = Path::new(target)
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| ATTACHMENT_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+                .unwrap_or(false);
+            if !supported {
+                continue;
+            }
+            let attachment = match base.join(target).canonicalize() {
+                Ok(path) if path.starts_with(&vault_root) => path,
+                Ok(path) => {
+                    log::warn!("Ignoring attachment {:?} outside the vault of {:?}", path, note_path);
+                    continue;
+                }
+                Err(_) => {
+                    log::debug!("Attachment {:?} linked from {:?} not found", target, note_path);
+                    continue;
+                }
+            };
+            if attachment == note_path_canonical {
+                continue;
+            }
+            let within_limit = std::fs::metadata(&attachment)
+                .is_ok_and(|metadata| metadata.is_file() && (self.max_file_size == 0 || metadata.len() <= self.max_file_size));
+            if !within_limit {
+                continue;
+            }
+
+            let attachment_doc = match self.parser.parse_file(&attachment) {
+                Ok(doc) => doc,
+                Err(e) => {
+                    log::warn!("Failed to read attachment {:?}: {}", attachment, e);
+                    continue;
+                }
+            };
+            let name = attachment.file_name().and_then(|n| n.to_str()).unwrap_or(target);
+            parsed.chunks.extend(
+                attachment_doc.chunks.iter().map(|chunk| format!("[Attachment: {}]\n{}", name, chunk)),
+            );
+            parsed.content.push('\n');
+            parsed.content.push_str(&attachment_doc.content);
+            hashes.push(attachment_doc.content_hash);
+        }
+
+        if hashes.len() > 1 {
+            parsed.content_hash = content_hash(&hashes.join(""));
         }
-        
         Ok(())
     }
 
+    /// Find a file-backed artifact with the given content hash whose path no
+    /// longer exists on disk
+    fn find_moved_artifact(&self, path_str: &str, content_hash: &str) -> IngestResult<Option<Artifact>> {
+        let candidates = self.db.get_artifacts_by_hash(content_hash)?;
+        Ok(candidates.into_iter().find(|a| {
+            a.path != path_str && !a.path.contains("://") && !Path::new(&a.path).exists()
+        }))
+    }
+
     pub async fn remove_file(&mut self, path: &Path) -> IngestResult<()> {
         let path_str = path.to_string_lossy().to_string();
         self.db.delete_artifact_by_path(&path_str)?;
         Ok(())
     }
+
+    /// Index Outline documents under `outline://{id}` paths, reporting
+    /// `(processed, total, title)` through `on_progress` before each one. A
+    /// changed document keeps its artifact id; failures are collected in the
+    /// returned status rather than ending the sync.
+    pub async fn sync_outline_documents<F>(
+        &mut self,
+        client: &OutlineClient,
+        documents: Vec<OutlineDocument>,
+        on_progress: F,
+    ) -> SyncStatus
+    where
+        F: Fn(usize, usize, &str),
+    {
+        let total = documents.len();
+        let mut processed = 0;
+        let mut errors = Vec::new();
+        // Collection names, fetched once per collection
+        let mut collection_names: HashMap<String, Option<String>> = HashMap::new();
+
+        for doc in documents {
+            on_progress(processed, total, &doc.title);
+
+            // Fetch full document content
+            match client.get_document(&doc.id).await {
+                Ok(full_doc) => {
+                    let path = format!("outline://{}", doc.id);
+
+                    // Parse the markdown content
+                    match self.parser.parse_content(&full_doc.text) {
+                        Ok(parsed) if parsed.word_count() < self.min_word_count => {
+                            log::debug!("Skipping near-empty document: {}", doc.title);
+                            let _ = self.db.delete_artifact_by_path(&path);
+                        }
+                        Ok(parsed) => {
+                            // Check if document has changed
+                            let existing = self.db.get_artifact_by_path(&path).ok().flatten();
+                            if existing.as_ref().is_some_and(|e| e.content_hash == parsed.content_hash) {
+                                log::debug!("Skipping unchanged document: {}", doc.title);
+                                processed += 1;
+                                continue;
+                            }
+
+                            // Keep the ID of a changed document and the vectors of its
+                            // unchanged chunks, deleting its old embeddings
+                            let mut reusable = HashMap::new();
+                            let artifact_id = match existing {
+                                Some(existing) => {
+                                    reusable = self.db.get_chunk_embeddings(&existing.id).unwrap_or_default();
+                                    let _ = self.db.delete_embeddings_by_artifact(&existing.id);
+                                    existing.id
+                                }
+                                None => Uuid::new_v4().to_string(),
+                            };
+                            let now = SystemTime::now()
+                                .duration_since(UNIX_EPOCH)
+                                .unwrap()
+                                .as_secs() as i64;
+
+                            let last_modified = match doc.updated() {
+                                Ok(updated) => updated.timestamp(),
+                                Err(e) => {
+                                    log::warn!("Outline document {}: {}", doc.title, e);
+                                    now
+                                }
+                            };
+
+                            let collection = match &doc.collection_id {
+                                Some(id) => match collection_names.get(id) {
+                                    Some(name) => name.clone(),
+                                    None => {
+                                        let name = match client.get_collection(id).await {
+                                            Ok(collection) => Some(collection.name),
+                                            Err(e) => {
+                                                log::warn!("Failed to fetch collection {}: {}", id, e);
+                                                None
+                                            }
+                                        };
+                                        collection_names.insert(id.clone(), name.clone());
+                                        name
+                                    }
+                                },
+                                None => None,
+                            };
+
+                            // Stored without its hash until every chunk is in, so a
+                            // document whose embedding fails is redone next sync
+                            let mut artifact = Artifact {
+                                id: artifact_id.clone(),
+                                path,
+                                last_modified,
+                                content_hash: String::new(),
+                                indexed_at: now,
+                                title: Some(doc.title.clone()),
+                                tags: parsed.frontmatter.tags.clone(),
+                                pinned: false,
+                                vault: None,
+                                collection_id: doc.collection_id.clone(),
+                                collection,
+                            };
+
+                            if let Err(e) = self.db.upsert_artifact(&artifact) {
+                                errors.push(format!("Failed to save artifact {}: {}", doc.title, e));
+                                continue;
+                            }
+
+                            match self.embed_chunks(&artifact_id, &parsed.chunks, &reusable, &mut || {}).await {
+                                Ok(()) => {
+                                    artifact.content_hash = parsed.content_hash;
+                                    match self.db.upsert_artifact(&artifact) {
+                                        Ok(()) => log::info!("Indexed Outline document: {}", doc.title),
+                                        Err(e) => errors.push(format!("Failed to save artifact {}: {}", doc.title, e)),
+                                    }
+                                }
+                                Err(e) => errors.push(format!("Failed to embed {}: {}", doc.title, e)),
+                            }
+                        }
+                        Err(e) => {
+                            errors.push(format!("Failed to parse {}: {}", doc.title, e));
+                        }
+                    }
+                }
+                Err(e) => {
+                    errors.push(format!("Failed to fetch {}: {}", doc.title, e));
+                }
+            }
+
+            processed += 1;
+        }
+
+        SyncStatus {
+            is_running: false,
+            total_files: total,
+            processed_files: processed,
+            skipped_files: 0,
+            resumed_files: 0,
+            last_sync_at: Some(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as i64,
+            ),
+            error: if errors.is_empty() { None } else { Some(errors.join("; ")) },
+        }
+    }
+
+    /// Resume `gate` and apply the file events it queued while paused,
+    /// returning how many there were
+    pub async fn apply_queued_events(&mut self, gate: &IndexingGate) -> IngestResult<usize> {
+        let queued = gate.resume();
+        let count = queued.len();
+        self.apply_events(queued).await?;
+        Ok(count)
+    }
+
+    /// Apply a batch of file watcher events to the index
+    pub async fn apply_events(&mut self, events: Vec<FileEvent>) -> IngestResult<()> {
+        for event in events {
+            let result = match &event {
+                FileEvent::Created(path) | FileEvent::Modified(path) => self.process_file(path).await,
+                FileEvent::Deleted(path) => self.remove_file(path).await,
+            };
+            if let Err(e) = result {
+                log::warn!("Failed to apply {:?}: {}", event, e);
+            }
+        }
+        Ok(())
+    }
 }
 
+/// Whether a note belongs to `vault_path`; notes indexed before vaults were
+/// recorded are matched by path
+fn in_vault(artifact: &Artifact, vault_path: &str) -> bool {
+    match &artifact.vault {
+        Some(vault) => vault == vault_path,
+        None => Path::new(&artifact.path).starts_with(vault_path),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers every embedding request with the same vector
+    async fn mock_embeddings(server: &mut mockito::ServerGuard) {
+        server
+            .mock("POST", "/api/embeddings")
+            .with_body(r#"{"embedding": [0.1, 0.2, 0.3]}"#)
+            .create_async()
+            .await;
+    }
+
+    /// An engine over a fresh database under a temp dir, which tests also
+    /// use for their notes
+    fn test_engine(settings: Settings) -> (tempfile::TempDir, Arc<Database>, IngestEngine) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(dir.path().join("data")).unwrap());
+        let engine = IngestEngine::new(db.clone(), &settings);
+        (dir, db, engine)
+    }
+
+    #[test]
+    fn test_extrapolate_sync_duration() {
+        // 3 chunks in 1.5s is 2 chunks/s, so 500 chunks take 250s
+        let (rate, seconds) = extrapolate_duration(500, 3, Duration::from_millis(1500));
+        assert!((rate.unwrap() - 2.0).abs() < 1e-9);
+        assert!((seconds.unwrap() - 250.0).abs() < 1e-9);
+
+        assert_eq!(extrapolate_duration(500, 0, Duration::ZERO), (None, None));
+    }
+
+    #[tokio::test]
+    async fn test_empty_notes_are_not_indexed() {
+        let (dir, db, mut engine) = test_engine(Settings::default());
+
+        let empty = dir.path().join("empty.md");
+        std::fs::write(&empty, "").unwrap();
+        let image_only = dir.path().join("image.md");
+        std::fs::write(&image_only, "![](attachments/photo.png)\n").unwrap();
+
+        engine.process_file(&empty).await.unwrap();
+        engine.process_file(&image_only).await.unwrap();
+
+        assert!(db.get_all_artifacts().unwrap().is_empty());
+        assert!(db.get_all_embeddings().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_noindex_notes_are_removed_and_not_indexed() {
+        let (dir, db, mut engine) = test_engine(Settings::default());
+
+        // A copy indexed before the note was flagged
+        let private = dir.path().join("private.md");
+        db.upsert_artifact(&Artifact {
+            id: "old".to_string(),
+            path: private.to_string_lossy().to_string(),
+            ..Default::default()
+        }).unwrap();
+        db.insert_embedding(&Embedding {
+            id: "old#0".to_string(),
+            artifact_id: "old".to_string(),
+            chunk_index: 0,
+            content: "secret plans".to_string(),
+            embedding: vec![0.1, 0.2],
+        }).unwrap();
+
+        std::fs::write(&private, "---\nnoindex: true\n---\nMy secret plans for the weekend").unwrap();
+        let scratch = dir.path().join("scratch.md");
+        std::fs::write(&scratch, "---\nmetabrain: false\n---\nScratch thoughts not worth keeping").unwrap();
+
+        engine.process_file(&private).await.unwrap();
+        engine.process_file(&scratch).await.unwrap();
+
+        assert!(db.get_all_artifacts().unwrap().is_empty());
+        assert!(db.get_all_embeddings().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sync_two_vaults() {
+        let mut server = mockito::Server::new_async().await;
+        mock_embeddings(&mut server).await;
+
+        let (dir, db, mut engine) = test_engine(Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        });
+        let work = dir.path().join("work");
+        let personal = dir.path().join("personal");
+        for (vault, note) in [(&work, "Quarterly roadmap review notes"), (&personal, "Recipes for the holiday dinner")] {
+            std::fs::create_dir_all(vault).unwrap();
+            std::fs::write(vault.join("note.md"), note).unwrap();
+        }
+        let vault_paths = vec![work.to_string_lossy().to_string(), personal.to_string_lossy().to_string()];
+
+        let status = engine.sync_vaults_with_embedding_progress(&vault_paths, |_, _, _| {}, |_, _| {}).await.unwrap();
+        assert_eq!(status.total_files, 2);
+        assert!(status.error.is_none());
+        let mut vaults: Vec<Option<String>> = db.get_all_artifacts().unwrap().into_iter().map(|a| a.vault).collect();
+        vaults.sort();
+        assert_eq!(vaults, vec![Some(vault_paths[1].clone()), Some(vault_paths[0].clone())]);
+
+        // Losing one vault's folder keeps its notes; a note deleted from the
+        // other vault is removed
+        std::fs::remove_dir_all(&personal).unwrap();
+        std::fs::write(work.join("second.md"), "Hiring plan for next year").unwrap();
+        std::fs::remove_file(work.join("note.md")).unwrap();
+        let status = engine.sync_vaults_with_embedding_progress(&vault_paths, |_, _, _| {}, |_, _| {}).await.unwrap();
+        assert!(status.error.unwrap().contains("personal"));
+
+        let mut paths: Vec<String> = db.get_all_artifacts().unwrap().into_iter().map(|a| a.path).collect();
+        paths.sort();
+        assert_eq!(paths, vec![
+            personal.join("note.md").to_string_lossy().to_string(),
+            work.join("second.md").to_string_lossy().to_string(),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_case_variant_paths_share_an_artifact() {
+        let mut server = mockito::Server::new_async().await;
+        mock_embeddings(&mut server).await;
+
+        for case_insensitive_paths in [true, false] {
+            let settings = Settings { ollama_endpoint: server.url(), case_insensitive_paths, ..Settings::default() };
+            let (dir, db, mut engine) = test_engine(settings.clone());
+            db.save_settings(&settings).unwrap();
+
+            // Stand-ins for one file seen under two casings
+            let upper = dir.path().join("Foo.md");
+            let lower = dir.path().join("foo.md");
+            std::fs::write(&upper, "Quarterly roadmap review notes").unwrap();
+            std::fs::write(&lower, "Quarterly roadmap review notes, revised").unwrap();
+            engine.index_file(&upper, false, None, &mut || {}).await.unwrap();
+            engine.index_file(&lower, false, None, &mut || {}).await.unwrap();
+
+            let artifacts = db.get_all_artifacts().unwrap();
+            if case_insensitive_paths {
+                assert_eq!(artifacts.len(), 1);
+                assert_eq!(artifacts[0].path, lower.to_string_lossy());
+            } else {
+                assert_eq!(artifacts.len(), 2);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_note_moved_to_trash_is_pruned() {
+        let mut server = mockito::Server::new_async().await;
+        mock_embeddings(&mut server).await;
+
+        let (dir, db, mut engine) = test_engine(Settings {
+            ollama_endpoint: server.url(),
+            trash_folder: "Trash".to_string(),
+            ..Settings::default()
+        });
+        // A visible trash folder, which the hidden-directory rule wouldn't catch
+        let vault = dir.path().join("vault");
+        std::fs::create_dir_all(vault.join("Trash")).unwrap();
+        std::fs::write(vault.join("note.md"), "Quarterly roadmap review notes").unwrap();
+        let vault_paths = vec![vault.to_string_lossy().to_string()];
+        engine.sync_vaults_with_embedding_progress(&vault_paths, |_, _, _| {}, |_, _| {}).await.unwrap();
+        assert_eq!(db.count_artifacts().unwrap(), 1);
+
+        // The watcher sees the note appear in the trash
+        let trashed = vault.join("Trash").join("note.md");
+        std::fs::rename(vault.join("note.md"), &trashed).unwrap();
+        engine.process_file(&trashed).await.unwrap();
+        engine.sync_vaults_with_embedding_progress(&vault_paths, |_, _, _| {}, |_, _| {}).await.unwrap();
+
+        assert_eq!(db.count_artifacts().unwrap(), 0);
+        assert_eq!(db.count_embeddings().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_embedding_progress_covers_the_whole_sync() {
+        let mut server = mockito::Server::new_async().await;
+        mock_embeddings(&mut server).await;
+
+        let (dir, _db, mut engine) = test_engine(Settings { ollama_endpoint: server.url(), ..Settings::default() });
+        let vault = dir.path().join("vault");
+        std::fs::create_dir_all(&vault).unwrap();
+        let long_note: String = (0..1200).map(|i| format!("word{} ", i)).collect();
+        std::fs::write(vault.join("long.md"), &long_note).unwrap();
+        std::fs::write(vault.join("short.md"), "A short note about the garden.").unwrap();
+
+        let expected_total = engine.parser.parse_content(&long_note).unwrap().chunks.len() + 1;
+        assert!(expected_total > 2);
+
+        let vault_paths = vec![vault.to_string_lossy().to_string()];
+        let reports = std::sync::Mutex::new(Vec::new());
+        engine
+            .sync_vaults_with_embedding_progress(&vault_paths, |_, _, _| {}, |embedded, total| {
+                reports.lock().unwrap().push((embedded, total));
+            })
+            .await
+            .unwrap();
+
+        let reports = reports.into_inner().unwrap();
+        assert_eq!(reports.first(), Some(&(0, expected_total)));
+        assert_eq!(reports.last(), Some(&(expected_total, expected_total)));
+        assert!(reports.iter().all(|(_, total)| *total == expected_total));
+        assert!(reports.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+        // Nothing changed, so a second sync has nothing to embed
+        let reports = std::sync::Mutex::new(Vec::new());
+        engine
+            .sync_vaults_with_embedding_progress(&vault_paths, |_, _, _| {}, |embedded, total| {
+                reports.lock().unwrap().push((embedded, total));
+            })
+            .await
+            .unwrap();
+        assert_eq!(reports.into_inner().unwrap(), vec![(0, 0)]);
+
+        // Only the edited note's parse is kept for indexing
+        std::fs::write(vault.join("short.md"), "A short note about the vegetable garden.").unwrap();
+        let files = scan_directory(&vault, &engine.scan_options);
+        let plan = engine.plan_sync(&files).unwrap();
+        assert_eq!(plan.parsed.keys().collect::<Vec<_>>(), vec![&vault.join("short.md")]);
+        assert_eq!(plan.unchanged, HashSet::from([vault.join("long.md")]));
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_files_report_no_progress_when_quiet() {
+        let mut server = mockito::Server::new_async().await;
+        mock_embeddings(&mut server).await;
+
+        let (dir, _db, mut engine) =
+            test_engine(Settings { ollama_endpoint: server.url(), quiet_unchanged_files: true, ..Settings::default() });
+        let vault = dir.path().join("vault");
+        std::fs::create_dir_all(&vault).unwrap();
+        for (name, text) in [("a.md", "Quarterly roadmap review notes"), ("b.md", "Recipes for the holiday dinner")] {
+            std::fs::write(vault.join(name), text).unwrap();
+        }
+        engine.sync_vaults_with_embedding_progress(&[vault.to_str().unwrap().to_string()], |_, _, _| {}, |_, _| {}).await.unwrap();
+
+        std::fs::write(vault.join("b.md"), "Recipes for the new year dinner").unwrap();
+        let progress = std::sync::Mutex::new(Vec::new());
+        let status = engine
+            .sync_vaults_with_embedding_progress(&[vault.to_str().unwrap().to_string()], |processed, total, file| {
+                progress.lock().unwrap().push((processed, total, file.to_string()));
+            }, |_, _| {})
+            .await
+            .unwrap();
+
+        // Only the initial report and the edited file
+        let progress = progress.into_inner().unwrap();
+        assert_eq!(progress.len(), 2);
+        assert_eq!(progress[1].2, "b.md");
+        assert_eq!(progress[1].1, 2);
+        assert_eq!(status.processed_files, 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_syncs_run_once() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/embeddings")
+            .with_body(r#"{"embedding": [0.1, 0.2, 0.3]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let (dir, _db, engine) = test_engine(Settings { ollama_endpoint: server.url(), ..Settings::default() });
+        let engine = tokio::sync::Mutex::new(engine);
+        let vault = dir.path().join("vault");
+        std::fs::create_dir_all(&vault).unwrap();
+        std::fs::write(vault.join("note.md"), "Quarterly roadmap review notes").unwrap();
+        let guard = SyncGuard::new();
+
+        let sync = || async {
+            engine
+                .lock()
+                .await
+                .sync_vaults_with_embedding_progress(&[vault.to_str().unwrap().to_string()], |_, _, _| {}, |_, _| {})
+                .await
+                .unwrap()
+        };
+        let (first, second) = tokio::join!(guard.run(sync), guard.run(sync));
+
+        assert_eq!(first.unwrap().processed_files, 1);
+        assert!(second.is_none());
+        assert!(!guard.is_running());
+        mock.assert_async().await;
+
+        // The guard is free again once the first sync finishes
+        assert!(guard.run(sync).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_second_trigger_sees_live_status() {
+        let guard = SyncGuard::new();
+
+        guard
+            .run(|| async {
+                guard.report_progress(3, 10);
+                assert!(guard.run(|| async {}).await.is_none());
+
+                let status = guard.status();
+                assert!(status.is_running);
+                assert_eq!((status.processed_files, status.total_files), (3, 10));
+            })
+            .await
+            .unwrap();
+
+        assert!(!guard.status().is_running);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_files_are_skipped() {
+        let (dir, db, mut engine) = test_engine(Settings {
+            max_file_size_mb: 1,
+            ..Settings::default()
+        });
+
+        let vault = dir.path().join("vault");
+        std::fs::create_dir_all(&vault).unwrap();
+        std::fs::write(vault.join("dump.md"), "log line\n".repeat(200_000)).unwrap();
+
+        let status = engine
+            .sync_vaults_with_embedding_progress(&[vault.to_str().unwrap().to_string()], |_, _, _| {}, |_, _| {})
+            .await
+            .unwrap();
+
+        assert_eq!(status.total_files, 1);
+        assert_eq!(status.processed_files, 1);
+        assert_eq!(status.skipped_files, 1);
+        assert!(db.get_all_artifacts().unwrap().is_empty());
+
+        // A huge limit caps at the largest size instead of overflowing
+        let settings = Settings {
+            max_file_size_mb: u64::MAX / 2,
+            ..Settings::default()
+        };
+        assert_eq!(IngestEngine::new(db, &settings).max_file_size, u64::MAX);
+    }
+
+    #[tokio::test]
+    async fn test_events_queued_while_paused_are_applied_on_resume() {
+        let mut server = mockito::Server::new_async().await;
+        mock_embeddings(&mut server).await;
+
+        let (dir, db, mut engine) = test_engine(Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        });
+        let gate = IndexingGate::new();
+        let note = dir.path().join("note.md");
+        std::fs::write(&note, "Written while indexing was paused").unwrap();
+
+        gate.pause();
+        assert!(gate.admit(vec![FileEvent::Modified(note.clone())]).is_empty());
+        assert_eq!(db.count_artifacts().unwrap(), 0);
+
+        // Resuming waits its turn behind a running sync
+        let guard = SyncGuard::new();
+        let resumed = guard
+            .run(|| async {
+                let blocked = guard.run(|| async { engine.apply_queued_events(&gate).await }).await;
+                assert!(blocked.is_none());
+                assert!(gate.is_paused());
+            })
+            .await;
+        assert!(resumed.is_some());
+
+        let applied = guard.run(|| engine.apply_queued_events(&gate)).await.unwrap().unwrap();
+        assert_eq!(applied, 1);
+        assert!(!gate.is_paused());
+        assert!(db.get_artifact_by_path(&note.to_string_lossy()).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_changed_outline_document_keeps_its_artifact() {
+        let mut server = mockito::Server::new_async().await;
+        mock_embeddings(&mut server).await;
+        server
+            .mock("POST", "/collections.info")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({ "id": "col-eng" })))
+            .with_body(r#"{"data": {"name": "Engineering"}}"#)
+            .create_async()
+            .await;
+        let document = |text: &str| {
+            serde_json::json!({
+                "id": "doc-1",
+                "title": "Runbook",
+                "urlId": "runbook-abc",
+                "text": text,
+                "updatedAt": "2024-03-05T10:15:30.123Z",
+                "collectionId": "col-eng"
+            })
+        };
+
+        let (_dir, db, mut engine) = test_engine(Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        });
+        let client = OutlineClient::new(server.url(), "test_key".to_string()).unwrap();
+        let listed: OutlineDocument = serde_json::from_value(document("")).unwrap();
+
+        let mut synced_ids = Vec::new();
+        for text in ["Restart the service with systemctl", "Restart the service with the deploy script"] {
+            let info = server
+                .mock("POST", "/documents.info")
+                .with_body(serde_json::json!({ "data": document(text) }).to_string())
+                .create_async()
+                .await;
+            let status = engine.sync_outline_documents(&client, vec![listed.clone()], |_, _, _| {}).await;
+            info.remove_async().await;
+
+            assert_eq!(status.error, None);
+            let artifact = db.get_artifact_by_path("outline://doc-1").unwrap().unwrap();
+            assert_eq!(artifact.collection.as_deref(), Some("Engineering"));
+            let chunks = db.get_artifact_chunks(&artifact.id).unwrap();
+            assert!(chunks.iter().any(|c| c.content.contains(text)));
+            synced_ids.push(artifact.id);
+        }
+
+        assert_eq!(synced_ids[0], synced_ids[1]);
+        assert_eq!(db.count_artifacts().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_outline_document_with_failed_embedding_is_retried() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/documents.info")
+            .with_body(serde_json::json!({ "data": {
+                "id": "doc-1",
+                "title": "Runbook",
+                "urlId": "runbook-abc",
+                "text": "Restart the service with systemctl",
+                "updatedAt": "2024-03-05T10:15:30.123Z"
+            }}).to_string())
+            .create_async()
+            .await;
+        let failing = server
+            .mock("POST", "/api/embeddings")
+            .with_status(500)
+            .create_async()
+            .await;
+
+        let (_dir, db, mut engine) = test_engine(Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        });
+        let client = OutlineClient::new(server.url(), "test_key".to_string()).unwrap();
+        let listed: OutlineDocument = serde_json::from_value(serde_json::json!({
+            "id": "doc-1",
+            "title": "Runbook",
+            "urlId": "runbook-abc",
+            "text": "",
+            "updatedAt": "2024-03-05T10:15:30.123Z"
+        }))
+        .unwrap();
+
+        let status = engine.sync_outline_documents(&client, vec![listed.clone()], |_, _, _| {}).await;
+        assert!(status.error.unwrap().contains("Failed to embed Runbook"));
+        let artifact = db.get_artifact_by_path("outline://doc-1").unwrap().unwrap();
+        assert!(artifact.content_hash.is_empty());
+
+        // The next sync doesn't take the document as unchanged
+        failing.remove_async().await;
+        mock_embeddings(&mut server).await;
+        let status = engine.sync_outline_documents(&client, vec![listed], |_, _, _| {}).await;
+        assert_eq!(status.error, None);
+        let artifact = db.get_artifact_by_path("outline://doc-1").unwrap().unwrap();
+        assert!(!artifact.content_hash.is_empty());
+        assert_eq!(db.get_artifact_chunks(&artifact.id).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_moved_file_reuses_embeddings() {
+        // Unreachable endpoint: any embedding request would fail the test
+        let (dir, db, mut engine) = test_engine(Settings {
+            ollama_endpoint: "http://127.0.0.1:9".to_string(),
+            ..Settings::default()
+        });
+
+        let content = "Meeting notes about the quarterly roadmap";
+        let old_path = dir.path().join("old.md");
+        let new_path = dir.path().join("archive").join("new.md");
+        std::fs::create_dir_all(new_path.parent().unwrap()).unwrap();
+        std::fs::write(&new_path, content).unwrap();
+
+        let parsed = MarkdownParser::new().parse_content(content).unwrap();
+        db.upsert_artifact(&Artifact {
+            id: "a1".to_string(),
+            path: old_path.to_string_lossy().to_string(),
+            content_hash: parsed.content_hash,
+            ..Default::default()
+        }).unwrap();
+        db.insert_embedding(&Embedding {
+            id: "a1#0".to_string(),
+            artifact_id: "a1".to_string(),
+            chunk_index: 0,
+            content: content.to_string(),
+            embedding: vec![0.1, 0.2, 0.3],
+        }).unwrap();
+
+        engine.process_file(&new_path).await.unwrap();
+
+        let artifacts = db.get_all_artifacts().unwrap();
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].id, "a1");
+        assert_eq!(artifacts[0].path, new_path.to_string_lossy());
+        let embeddings = db.get_all_embeddings().unwrap();
+        assert_eq!(embeddings.len(), 1);
+        assert_eq!(embeddings[0].id, "a1#0");
+    }
+
+    #[tokio::test]
+    async fn test_only_changed_chunks_are_re_embedded() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/embeddings")
+            .with_body(r#"{"embedding": [0.1, 0.2, 0.3]}"#)
+            .expect(4)
+            .create_async()
+            .await;
+
+        let (dir, db, mut engine) = test_engine(Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        });
+
+        // 1000 distinct words: three overlapping chunks
+        let mut words: Vec<String> = (0..1000).map(|i| format!("word{}", i)).collect();
+        let note = dir.path().join("long.md");
+        std::fs::write(&note, words.join(" ")).unwrap();
+        engine.process_file(&note).await.unwrap();
+        let artifact_id = db.get_artifact_by_path(&note.to_string_lossy()).unwrap().unwrap().id;
+        assert_eq!(db.count_embeddings().unwrap(), 3);
+
+        // Edit a word only the last chunk contains
+        words[990] = "edited".to_string();
+        std::fs::write(&note, words.join(" ")).unwrap();
+        engine.process_file(&note).await.unwrap();
+
+        mock.assert_async().await;
+        let artifact = db.get_artifact_by_path(&note.to_string_lossy()).unwrap().unwrap();
+        assert_eq!(artifact.id, artifact_id);
+        assert_eq!(db.count_embeddings().unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_linked_attachment_is_indexed_with_note() {
+        let mut server = mockito::Server::new_async().await;
+        mock_embeddings(&mut server).await;
+
+        let (dir, db, mut engine) = test_engine(Settings {
+            ollama_endpoint: server.url(),
+            index_attachments: true,
+            ..Settings::default()
+        });
+        let vault = dir.path().join("vault");
+        engine.vault_paths.push(vault.to_string_lossy().to_string());
+
+        std::fs::create_dir_all(vault.join("data")).unwrap();
+        std::fs::create_dir_all(vault.join("projects")).unwrap();
+        std::fs::write(vault.join("data").join("facts.txt"), "The launch code is swordfish").unwrap();
+        std::fs::write(vault.join("data").join("more.txt"), "The backup code is marlin").unwrap();
+        let secret = dir.path().join("secret.txt");
+        std::fs::write(&secret, "Private key material outside the vault").unwrap();
+        let note = vault.join("projects").join("wrapper.md");
+        std::fs::write(
+            &note,
+            format!(
+                "Details in [facts](../data/facts.txt), [[data/more.txt]] and [[missing.txt]]. \
+                 Not [escape](../../secret.txt) or [absolute]({}).",
+                secret.display()
+            ),
+        )
+        .unwrap();
+
+        engine.process_file(&note).await.unwrap();
+
+        let artifact = db.get_artifact_by_path(&note.to_string_lossy()).unwrap().unwrap();
+        let embeddings = db.get_all_embeddings().unwrap();
+        assert_eq!(embeddings.len(), 3);
+        assert!(embeddings.iter().all(|e| e.artifact_id == artifact.id));
+        assert!(embeddings.iter().any(|e| e.content.contains("swordfish")));
+        assert!(embeddings.iter().any(|e| e.content.contains("marlin")));
+        assert!(!embeddings.iter().any(|e| e.content.contains("Private key")));
+    }
+
+    #[tokio::test]
+    async fn test_repair_embeddings_re_embeds_empty_artifacts() {
+        let mut server = mockito::Server::new_async().await;
+        mock_embeddings(&mut server).await;
+
+        let (dir, db, mut engine) = test_engine(Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        });
+
+        // Indexed, but its embeddings never made it in
+        let note = dir.path().join("note.md");
+        std::fs::write(&note, "Notes on the quarterly planning meeting").unwrap();
+        db.upsert_artifact(&Artifact {
+            id: "a1".to_string(),
+            path: note.to_string_lossy().to_string(),
+            content_hash: engine.parser.parse_file(&note).unwrap().content_hash,
+            ..Default::default()
+        }).unwrap();
+        // Source deleted since; nothing to re-embed from
+        db.upsert_artifact(&Artifact {
+            id: "gone".to_string(),
+            path: dir.path().join("gone.md").to_string_lossy().to_string(),
+            ..Default::default()
+        }).unwrap();
+
+        assert_eq!(engine.repair_embeddings().await.unwrap(), 1);
+        assert_eq!(db.get_artifact_chunks("a1").unwrap().len(), 1);
+        assert_eq!(db.get_artifacts_without_embeddings().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stale_hash_is_detected_and_repaired() {
+        let mut server = mockito::Server::new_async().await;
+        mock_embeddings(&mut server).await;
+
+        let (dir, db, mut engine) = test_engine(Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        });
+
+        let note = dir.path().join("note.md");
+        std::fs::write(&note, "Notes on the quarterly planning meeting").unwrap();
+        engine.process_file(&note).await.unwrap();
+        let path = note.to_string_lossy().to_string();
+        let current_hash = db.get_artifact_by_path(&path).unwrap().unwrap().content_hash;
+        let fresh = engine.verify_hashes(false).await.unwrap();
+        assert_eq!((fresh.checked, fresh.mismatches.len()), (1, 0));
+
+        // As after a manual edit of the database
+        let mut artifact = db.get_artifact_by_path(&path).unwrap().unwrap();
+        artifact.content_hash = "wrong".to_string();
+        db.upsert_artifact(&artifact).unwrap();
+        db.upsert_artifact(&Artifact {
+            id: "outline".to_string(),
+            path: "outline://doc-1".to_string(),
+            content_hash: "anything".to_string(),
+            ..Default::default()
+        }).unwrap();
+
+        let report = engine.verify_hashes(false).await.unwrap();
+        assert_eq!(report.checked, 1);
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].stored_hash, "wrong");
+        assert_eq!(report.mismatches[0].current_hash, current_hash);
+        assert_eq!(report.repaired, 0);
+
+        let report = engine.verify_hashes(true).await.unwrap();
+        assert_eq!(report.repaired, 1);
+        assert_eq!(db.get_artifact_by_path(&path).unwrap().unwrap().content_hash, current_hash);
+        assert!(engine.verify_hashes(false).await.unwrap().mismatches.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resumed_sync_skips_completed_files() {
+        let mut server = mockito::Server::new_async().await;
+        let alpha = server
+            .mock("POST", "/api/embeddings")
+            .match_body(mockito::Matcher::Regex("alpha".to_string()))
+            .with_body(r#"{"embedding": [0.1, 0.2, 0.3]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let beta_down = server
+            .mock("POST", "/api/embeddings")
+            .match_body(mockito::Matcher::Regex("beta".to_string()))
+            .with_status(500)
+            .create_async()
+            .await;
+
+        let (dir, db, mut engine) = test_engine(Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        });
+        let vault = dir.path().join("vault");
+        std::fs::create_dir_all(&vault).unwrap();
+        std::fs::write(vault.join("a.md"), "alpha notes about the garden").unwrap();
+        std::fs::write(vault.join("b.md"), "beta notes about the kitchen").unwrap();
+        let vault_path = vault.to_str().unwrap();
+
+        // First run is cut short by b.md failing
+        engine.sync_vaults_with_embedding_progress(&[vault_path.to_string()], |_, _, _| {}, |_, _| {}).await.unwrap();
+        assert_eq!(db.get_sync_checkpoints(vault_path).unwrap().len(), 1);
+
+        beta_down.remove_async().await;
+        let beta_up = server
+            .mock("POST", "/api/embeddings")
+            .match_body(mockito::Matcher::Regex("beta".to_string()))
+            .with_body(r#"{"embedding": [0.3, 0.2, 0.1]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let status = engine.sync_vaults_with_embedding_progress(&[vault_path.to_string()], |_, _, _| {}, |_, _| {}).await.unwrap();
+
+        assert_eq!(status.resumed_files, 1);
+        assert_eq!(status.processed_files, 2);
+        assert_eq!(db.count_artifacts().unwrap(), 2);
+        assert!(db.get_sync_checkpoints(vault_path).unwrap().is_empty());
+        let beta = db.get_artifact_by_path(vault.join("b.md").to_str().unwrap()).unwrap().unwrap();
+        assert_eq!(db.get_artifact_chunks(&beta.id).unwrap().len(), 1);
+        alpha.assert_async().await;
+        beta_up.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_headless_ingest_without_app_handle() {
+        let mut server = mockito::Server::new_async().await;
+        mock_embeddings(&mut server).await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(dir.path().join("data")).unwrap());
+        let vault = dir.path().join("notes");
+        std::fs::create_dir_all(vault.join("sub")).unwrap();
+        std::fs::write(vault.join("one.md"), "# One\n\nThe first note in the folder.").unwrap();
+        std::fs::write(vault.join("sub/two.md"), "# Two\n\nA second note one level down.").unwrap();
+
+        let client = EmbeddingClient::new(server.url(), "nomic-embed-text".to_string());
+        let mut engine = IngestEngine::with_embedding_client(db.clone(), client, &Settings::default());
+        let progress = std::sync::Mutex::new(Vec::new());
+        let status = engine
+            .sync_vaults_with_embedding_progress(&[vault.to_str().unwrap().to_string()], |processed, total, _| {
+                progress.lock().unwrap().push((processed, total));
+            }, |_, _| {})
+            .await
+            .unwrap();
+
+        assert!(!status.is_running);
+        assert_eq!(status.processed_files, 2);
+        assert_eq!(progress.into_inner().unwrap().last(), Some(&(1, 2)));
+        assert_eq!(db.count_artifacts().unwrap(), 2);
+        assert!(db.count_embeddings().unwrap() >= 2);
+    }
+}
@@ -0,0 +1,117 @@
+//! Stopword-based guess at the language a query is written in, so answers
+//! can be requested in the same language instead of the prompt's English.
+
+use crate::db::Settings;
+
+/// Common function words per language; a query's language is the one whose
+/// list it hits most
+const STOPWORDS: &[(&str, &[&str])] = &[
+    ("English", &[
+        "the", "and", "is", "are", "what", "how", "of", "to", "my", "do", "does", "with",
+        "for", "was", "about", "which", "why", "this", "that", "did", "have", "i",
+    ]),
+    ("German", &[
+        "der", "die", "das", "und", "ist", "sind", "was", "wie", "ich", "nicht", "mit", "mein",
+        "meine", "meinen", "ein", "eine", "zu", "den", "dem", "von", "für", "über", "habe",
+        "welche", "warum", "auf", "auch", "wir",
+    ]),
+    ("French", &[
+        "le", "la", "les", "et", "est", "sont", "que", "quoi", "comment", "je", "pas", "avec",
+        "mon", "ma", "mes", "un", "une", "des", "du", "pour", "sur", "pourquoi", "quel", "quelle",
+    ]),
+    ("Spanish", &[
+        "el", "los", "las", "y", "es", "son", "qué", "cómo", "como", "yo", "con", "mi", "mis",
+        "una", "del", "para", "por", "sobre", "cuál", "cuándo", "dónde",
+    ]),
+    ("Italian", &[
+        "il", "lo", "gli", "e", "è", "sono", "che", "cosa", "come", "io", "non", "con", "mio",
+        "mia", "della", "per", "perché", "quale", "sul",
+    ]),
+    ("Dutch", &[
+        "de", "het", "een", "en", "zijn", "wat", "hoe", "ik", "niet", "met", "mijn", "van",
+        "voor", "over", "waarom", "welke", "heb",
+    ]),
+];
+
+/// Fewer stopword hits than this is too little to go on
+const MIN_STOPWORD_HITS: usize = 2;
+
+/// Which language answers are requested in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponseLanguage {
+    /// No instruction; the model answers as it likes
+    Off,
+    /// Answer in the language each query is written in
+    Auto,
+    /// Always answer in this language
+    Forced(String),
+}
+
+impl ResponseLanguage {
+    /// `off` (or empty), `auto`, or the name of a language to always answer in
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "" | "off" => ResponseLanguage::Off,
+            "auto" => ResponseLanguage::Auto,
+            _ => ResponseLanguage::Forced(value.trim().to_string()),
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self::parse(&settings.response_language)
+    }
+
+    /// Language to answer `query` in, if an instruction should be added
+    pub fn for_query(&self, query: &str) -> Option<String> {
+        match self {
+            ResponseLanguage::Off => None,
+            ResponseLanguage::Auto => detect_language(query).map(String::from),
+            ResponseLanguage::Forced(language) => Some(language.clone()),
+        }
+    }
+}
+
+/// Best guess at the language of `text`, or `None` when it's too short or
+/// ambiguous to tell
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+
+    let mut scores: Vec<(&'static str, usize)> = STOPWORDS
+        .iter()
+        .map(|(language, stopwords)| {
+            let hits = words.iter().filter(|w| stopwords.contains(&w.as_str())).count();
+            (*language, hits)
+        })
+        .collect();
+    scores.sort_by_key(|&(_, hits)| std::cmp::Reverse(hits));
+
+    match scores.as_slice() {
+        [(language, best), (_, second), ..] if *best >= MIN_STOPWORD_HITS && best > second => Some(language),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(detect_language("Was habe ich über das Projekt geschrieben?"), Some("German"));
+        assert_eq!(detect_language("What did I write about the project?"), Some("English"));
+        assert_eq!(detect_language("Pourquoi est-ce que le projet est en retard?"), Some("French"));
+        assert_eq!(detect_language("Kubernetes"), None);
+
+        assert_eq!(ResponseLanguage::parse("auto"), ResponseLanguage::Auto);
+        assert_eq!(ResponseLanguage::parse(""), ResponseLanguage::Off);
+        assert_eq!(
+            ResponseLanguage::parse("Japanese").for_query("anything"),
+            Some("Japanese".to_string())
+        );
+    }
+}
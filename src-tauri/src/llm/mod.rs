@@ -1,6 +1,9 @@
 pub mod ollama;
 
+use crate::db::Settings;
+use crate::endpoint::trim_endpoint;
 use async_trait::async_trait;
+use serde::Serialize;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -11,29 +14,138 @@ pub enum LLMError {
     Provider(String),
     #[error("Streaming error: {0}")]
     Stream(String),
+    #[error("Unknown LLM provider: {0}")]
+    UnknownProvider(String),
 }
 
 pub type LLMResult<T> = Result<T, LLMError>;
 
+/// Timing and token counts for one generation
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationStats {
+    pub time_to_first_token_ms: Option<u64>,
+    pub total_time_ms: u64,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    pub tokens_per_second: Option<f64>,
+}
+
+/// Limits applied to every generation
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GenerationOptions {
+    /// Maximum number of tokens to generate; the model's default when unset
+    pub max_tokens: Option<u32>,
+    /// Generation stops before any of these sequences
+    pub stop: Vec<String>,
+    /// Fixed sampling seed, so the same prompt gets the same answer at the
+    /// same temperature
+    pub seed: Option<u32>,
+    /// Context window to request, in tokens; the provider's default when unset
+    pub context_tokens: Option<u32>,
+}
+
+impl GenerationOptions {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            max_tokens: settings.max_tokens,
+            stop: settings
+                .stop_sequences
+                .iter()
+                .filter(|s| !s.is_empty())
+                .cloned()
+                .collect(),
+            seed: settings.seed,
+            context_tokens: (settings.context_window_tokens > 0).then_some(settings.context_window_tokens),
+        }
+    }
+
+    /// Byte offset of the earliest stop sequence in `text`
+    pub fn stop_position(&self, text: &str) -> Option<usize> {
+        self.stop.iter().filter_map(|stop| text.find(stop.as_str())).min()
+    }
+}
+
+/// What a provider and model support, so callers can adapt to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProviderCapabilities {
+    /// Responses can be streamed chunk by chunk
+    pub supports_streaming: bool,
+    /// Size of the context window in tokens, when known
+    pub max_context_tokens: Option<usize>,
+    /// A system prompt can be passed separately from the user's prompt
+    pub supports_system_role: bool,
+}
+
 /// Callback type for streaming chunks
 pub type StreamCallback = Box<dyn Fn(&str) + Send + Sync>;
 
 /// Trait defining the interface for LLM providers
 #[async_trait]
 pub trait LLMProvider: Send + Sync {
-    /// Generate a response for the given prompt
-    async fn generate(&self, prompt: &str) -> LLMResult<String>;
+    /// Generate a response for the given prompt. `system` is only passed to
+    /// providers that report `supports_system_role`.
+    async fn generate(&self, prompt: &str, system: Option<&str>) -> LLMResult<String>;
     
     /// Generate a streaming response, calling the callback for each chunk
-    async fn generate_stream(&self, prompt: &str, on_chunk: StreamCallback) -> LLMResult<String>;
+    async fn generate_stream(&self, prompt: &str, system: Option<&str>, on_chunk: StreamCallback) -> LLMResult<String>;
+    
+    /// Stats for the most recent streamed generation, if the provider records them
+    fn last_generation_stats(&self) -> Option<GenerationStats> {
+        None
+    }
+    
+    /// What this provider and model support
+    fn capabilities(&self) -> ProviderCapabilities;
+    
+    /// List the models available on the provider
+    async fn list_models(&self) -> LLMResult<Vec<String>>;
     
     /// Get the model name
     fn model_name(&self) -> &str;
 }
 
 /// Factory function to create an LLM provider based on configuration
-pub fn create_provider(provider_type: &str, endpoint: &str, model: &str) -> Box<dyn LLMProvider> {
+pub fn create_provider(
+    provider_type: &str,
+    endpoint: &str,
+    model: &str,
+    options: GenerationOptions,
+) -> LLMResult<Box<dyn LLMProvider>> {
     match provider_type {
-        "ollama" | _ => Box::new(ollama::OllamaProvider::new(endpoint.to_string(), model.to_string())),
+        "ollama" => Ok(Box::new(ollama::OllamaProvider::with_options(
+            trim_endpoint(endpoint).to_string(),
+            model.to_string(),
+            options,
+        ))),
+        other => Err(LLMError::UnknownProvider(other.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_provider_rejects_unknown_type() {
+        let provider = create_provider("ollama", "http://localhost:11434", "llama3.2", GenerationOptions::default()).unwrap();
+        assert_eq!(provider.model_name(), "llama3.2");
+
+        match create_provider("openai", "http://localhost:11434", "gpt-4o", GenerationOptions::default()) {
+            Err(LLMError::UnknownProvider(name)) => assert_eq!(name, "openai"),
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("unknown provider type was accepted"),
+        }
+    }
+
+    #[test]
+    fn test_stop_position_finds_earliest_sequence() {
+        let options = GenerationOptions {
+            stop: vec!["\n\nSources:".to_string(), "###".to_string()],
+            ..GenerationOptions::default()
+        };
+        assert_eq!(options.stop_position("Answer ### more\n\nSources: x"), Some(7));
+        assert_eq!(options.stop_position("no markers here"), None);
+        assert_eq!(GenerationOptions::default().stop_position("###"), None);
     }
 }
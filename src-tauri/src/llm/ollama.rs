@@ -1,50 +1,259 @@
-use super::{LLMError, LLMProvider, LLMResult, StreamCallback};
+use super::{
+    GenerationOptions, GenerationStats, LLMError, LLMProvider, LLMResult, ProviderCapabilities, StreamCallback,
+};
 use async_trait::async_trait;
 use futures::StreamExt;
 use reqwest::Client;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Ollama's default `num_ctx`, used when no context window is configured
+const DEFAULT_CONTEXT_TOKENS: usize = 2048;
 
 #[derive(Debug, Serialize)]
 struct GenerateRequest {
     model: String,
     prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<RequestOptions>,
+}
+
+/// The subset of Ollama's model options we set
+#[derive(Debug, Serialize)]
+struct RequestOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_ctx: Option<u32>,
+}
+
+impl RequestOptions {
+    fn from_options(options: &GenerationOptions) -> Option<Self> {
+        if options.max_tokens.is_none()
+            && options.stop.is_empty()
+            && options.seed.is_none()
+            && options.context_tokens.is_none()
+        {
+            return None;
+        }
+        Some(Self {
+            num_predict: options.max_tokens,
+            stop: options.stop.clone(),
+            seed: options.seed,
+            num_ctx: options.context_tokens,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TagsResponse {
+    models: Vec<ModelInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelInfo {
+    name: String,
 }
 
 #[derive(Debug, Deserialize)]
 struct GenerateResponse {
     response: String,
     done: bool,
+    // Timing fields Ollama adds to the final `done` object (durations in ns)
+    #[serde(default)]
+    prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    eval_count: Option<u64>,
+    #[serde(default)]
+    eval_duration: Option<u64>,
+}
+
+impl GenerateResponse {
+    /// Combine the server-reported counts with client-side timings
+    fn stats(&self, time_to_first_token: Option<Duration>, total_time: Duration) -> GenerationStats {
+        let tokens_per_second = match (self.eval_count, self.eval_duration) {
+            (Some(count), Some(duration)) if duration > 0 => Some(count as f64 / (duration as f64 / 1e9)),
+            _ => None,
+        };
+
+        GenerationStats {
+            time_to_first_token_ms: time_to_first_token.map(|d| d.as_millis() as u64),
+            total_time_ms: total_time.as_millis() as u64,
+            prompt_tokens: self.prompt_eval_count,
+            completion_tokens: self.eval_count,
+            tokens_per_second,
+        }
+    }
+}
+
+/// Holds back a multi-byte UTF-8 character split across network chunks
+#[derive(Debug, Default)]
+struct Utf8Buffer {
+    pending: Vec<u8>,
+}
+
+impl Utf8Buffer {
+    /// Append bytes and return all text up to the last complete character
+    fn push(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+        let mut text = String::new();
+
+        loop {
+            match std::str::from_utf8(&self.pending) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    self.pending.clear();
+                    break;
+                }
+                Err(e) => {
+                    let valid_up_to = e.valid_up_to();
+                    text.push_str(&String::from_utf8_lossy(&self.pending[..valid_up_to]));
+                    match e.error_len() {
+                        // Truncated character at the end: wait for the rest
+                        None => {
+                            self.pending.drain(..valid_up_to);
+                            break;
+                        }
+                        // Genuinely invalid bytes: replace and keep going
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            self.pending.drain(..valid_up_to + len);
+                        }
+                    }
+                }
+            }
+        }
+
+        text
+    }
+}
+
+/// Collects streamed text into complete JSON values, whether they arrive one
+/// per line, split across reads or spread over several lines
+#[derive(Debug, Default)]
+struct JsonStreamBuffer {
+    pending: String,
+}
+
+impl JsonStreamBuffer {
+    /// Append text and return every value it completes, in order. A value
+    /// that can't be parsed is dropped up to the end of its line.
+    fn push<T: DeserializeOwned>(&mut self, text: &str) -> Vec<T> {
+        self.pending.push_str(text);
+        let mut values = Vec::new();
+
+        loop {
+            let mut stream = serde_json::Deserializer::from_str(&self.pending).into_iter::<T>();
+            let consumed = match stream.next() {
+                Some(Ok(value)) => {
+                    values.push(value);
+                    stream.byte_offset()
+                }
+                // The value continues in a later read
+                Some(Err(e)) if e.is_eof() => break,
+                Some(Err(_)) => match self.pending.find('\n') {
+                    Some(newline_pos) => newline_pos + 1,
+                    None => break,
+                },
+                // Only whitespace left
+                None => {
+                    self.pending.clear();
+                    break;
+                }
+            };
+            self.pending.drain(..consumed);
+        }
+
+        values
+    }
 }
 
 pub struct OllamaProvider {
     client: Client,
     endpoint: String,
     model: String,
+    options: GenerationOptions,
+    last_stats: Mutex<Option<GenerationStats>>,
 }
 
 impl OllamaProvider {
-    pub fn new(endpoint: String, model: String) -> Self {
+    pub fn with_options(endpoint: String, model: String, options: GenerationOptions) -> Self {
         Self {
             client: Client::new(),
             endpoint,
             model,
+            options,
+            last_stats: Mutex::new(None),
+        }
+    }
+
+    fn request(&self, prompt: &str, system: Option<&str>, stream: bool) -> GenerateRequest {
+        GenerateRequest {
+            model: self.model.clone(),
+            prompt: prompt.to_string(),
+            system: system.map(String::from),
+            stream,
+            options: RequestOptions::from_options(&self.options),
+        }
+    }
+
+    /// Append a streamed piece to `full_response` and pass it on, unless it
+    /// completes a stop sequence: then truncate there and return true
+    fn accept_chunk(&self, text: &str, full_response: &mut String, on_chunk: &StreamCallback) -> bool {
+        let emitted = full_response.len();
+        full_response.push_str(text);
+
+        match self.options.stop_position(full_response) {
+            Some(pos) => {
+                if pos > emitted {
+                    on_chunk(&full_response[emitted..pos]);
+                }
+                full_response.truncate(pos);
+                true
+            }
+            None => {
+                on_chunk(text);
+                false
+            }
+        }
+    }
+
+    fn record_stats(&self, stats: GenerationStats) {
+        log::info!(
+            "Generation finished in {}ms (first token {:?}ms, {:?} prompt tokens, {:?} tokens, {:.1} tok/s)",
+            stats.total_time_ms,
+            stats.time_to_first_token_ms,
+            stats.prompt_tokens,
+            stats.completion_tokens,
+            stats.tokens_per_second.unwrap_or_default()
+        );
+        if let Ok(mut last) = self.last_stats.lock() {
+            *last = Some(stats);
         }
     }
 
     fn generate_url(&self) -> String {
         format!("{}/api/generate", self.endpoint)
     }
+
+    fn tags_url(&self) -> String {
+        format!("{}/api/tags", self.endpoint)
+    }
 }
 
 #[async_trait]
 impl LLMProvider for OllamaProvider {
-    async fn generate(&self, prompt: &str) -> LLMResult<String> {
-        let request = GenerateRequest {
-            model: self.model.clone(),
-            prompt: prompt.to_string(),
-            stream: false,
-        };
+    async fn generate(&self, prompt: &str, system: Option<&str>) -> LLMResult<String> {
+        let request = self.request(prompt, system, false);
 
         let response = self.client
             .post(&self.generate_url())
@@ -57,16 +266,18 @@ impl LLMProvider for OllamaProvider {
             return Err(LLMError::Provider(error_text));
         }
 
-        let gen_response: GenerateResponse = response.json().await?;
+        let mut gen_response: GenerateResponse = response.json().await?;
+        if let Some(pos) = self.options.stop_position(&gen_response.response) {
+            gen_response.response.truncate(pos);
+        }
         Ok(gen_response.response)
     }
 
-    async fn generate_stream(&self, prompt: &str, on_chunk: StreamCallback) -> LLMResult<String> {
-        let request = GenerateRequest {
-            model: self.model.clone(),
-            prompt: prompt.to_string(),
-            stream: true,
-        };
+    async fn generate_stream(&self, prompt: &str, system: Option<&str>, on_chunk: StreamCallback) -> LLMResult<String> {
+        let request = self.request(prompt, system, true);
+
+        let started = Instant::now();
+        let mut first_token: Option<Duration> = None;
 
         let response = self.client
             .post(&self.generate_url())
@@ -81,40 +292,270 @@ impl LLMProvider for OllamaProvider {
 
         let mut stream = response.bytes_stream();
         let mut full_response = String::new();
-        let mut buffer = String::new();
+        let mut json = JsonStreamBuffer::default();
+        let mut utf8 = Utf8Buffer::default();
 
         while let Some(chunk_result) = stream.next().await {
             let chunk = chunk_result.map_err(|e| LLMError::Stream(e.to_string()))?;
-            
-            // Append chunk to buffer
-            buffer.push_str(&String::from_utf8_lossy(&chunk));
-            
-            // Process complete JSON lines
-            while let Some(newline_pos) = buffer.find('\n') {
-                let line = buffer[..newline_pos].to_string();
-                buffer = buffer[newline_pos + 1..].to_string();
-                
-                if line.trim().is_empty() {
-                    continue;
-                }
-                
-                if let Ok(gen_response) = serde_json::from_str::<GenerateResponse>(&line) {
-                    if !gen_response.response.is_empty() {
-                        on_chunk(&gen_response.response);
-                        full_response.push_str(&gen_response.response);
-                    }
-                    
-                    if gen_response.done {
+
+            // Hold back any partial character, then any partial JSON object
+            for gen_response in json.push::<GenerateResponse>(&utf8.push(&chunk)) {
+                if !gen_response.response.is_empty() {
+                    first_token.get_or_insert_with(|| started.elapsed());
+                    // Backstop in case the server doesn't honor `stop`
+                    if self.accept_chunk(&gen_response.response, &mut full_response, &on_chunk) {
+                        self.record_stats(GenerationStats {
+                            time_to_first_token_ms: first_token.map(|d| d.as_millis() as u64),
+                            total_time_ms: started.elapsed().as_millis() as u64,
+                            ..Default::default()
+                        });
                         return Ok(full_response);
                     }
                 }
+
+                if gen_response.done {
+                    self.record_stats(gen_response.stats(first_token, started.elapsed()));
+                    return Ok(full_response);
+                }
             }
         }
 
-        Ok(full_response)
+        // The connection closed before Ollama sent `done`: the answer is truncated
+        log::warn!("Stream ended without a done line after {} chars", full_response.len());
+        Err(LLMError::Stream("incomplete response".to_string()))
+    }
+
+    async fn list_models(&self) -> LLMResult<Vec<String>> {
+        let response = self.client
+            .get(self.tags_url())
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(LLMError::Provider(error_text));
+        }
+
+        let tags: TagsResponse = response.json().await?;
+        Ok(tags.models.into_iter().map(|m| m.name).collect())
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            supports_streaming: true,
+            max_context_tokens: Some(
+                self.options.context_tokens.map_or(DEFAULT_CONTEXT_TOKENS, |tokens| tokens as usize),
+            ),
+            // `/api/generate` takes a separate `system` field
+            supports_system_role: true,
+        }
+    }
+
+    fn last_generation_stats(&self) -> Option<GenerationStats> {
+        self.last_stats.lock().ok().and_then(|last| last.clone())
     }
 
     fn model_name(&self) -> &str {
         &self.model
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parse_final_done_line_stats() {
+        let line = r#"{"model":"llama3.2","created_at":"2024-05-01T10:00:00Z","response":"","done":true,
+            "total_duration":5043500667,"load_duration":5025959,"prompt_eval_count":26,
+            "prompt_eval_duration":325953000,"eval_count":290,"eval_duration":4709213000}"#;
+
+        let response: GenerateResponse = serde_json::from_str(line).unwrap();
+        assert!(response.done);
+
+        let stats = response.stats(Some(Duration::from_millis(350)), Duration::from_millis(5100));
+        assert_eq!(stats.time_to_first_token_ms, Some(350));
+        assert_eq!(stats.total_time_ms, 5100);
+        assert_eq!(stats.prompt_tokens, Some(26));
+        assert_eq!(stats.completion_tokens, Some(290));
+        let tps = stats.tokens_per_second.unwrap();
+        assert!((tps - 61.58).abs() < 0.01, "tokens/sec was {}", tps);
+    }
+
+    #[test]
+    fn test_stats_without_timing_fields() {
+        let response: GenerateResponse = serde_json::from_str(r#"{"response":"hi","done":true}"#).unwrap();
+        let stats = response.stats(None, Duration::from_millis(10));
+        assert_eq!(stats.tokens_per_second, None);
+        assert_eq!(stats.completion_tokens, None);
+    }
+
+    #[test]
+    fn test_utf8_split_across_chunks_is_reassembled() {
+        let bytes = "naïve 日本".as_bytes();
+        // Split inside the three-byte '日'
+        let split = bytes.len() - 4;
+        let mut buffer = Utf8Buffer::default();
+
+        let first = buffer.push(&bytes[..split]);
+        let second = buffer.push(&bytes[split..]);
+
+        assert_eq!(first, "naïve ");
+        assert_eq!(second, "日本");
+        assert!(!format!("{}{}", first, second).contains(char::REPLACEMENT_CHARACTER));
+    }
+
+    #[test]
+    fn test_invalid_utf8_is_replaced() {
+        let mut buffer = Utf8Buffer::default();
+        assert_eq!(buffer.push(&[b'a', 0xff, b'b']), "a\u{fffd}b");
+    }
+
+    #[test]
+    fn test_json_object_split_across_reads_is_parsed_once() {
+        let mut buffer = JsonStreamBuffer::default();
+
+        let first: Vec<GenerateResponse> = buffer.push("{\"response\":\"Hel");
+        let second: Vec<GenerateResponse> = buffer.push("lo\",\n  \"done\": false\n}\n{\"response\":\"!\",");
+        let third: Vec<GenerateResponse> = buffer.push("\"done\":true}");
+
+        assert!(first.is_empty());
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].response, "Hello");
+        assert_eq!(third.len(), 1);
+        assert!(third[0].done);
+        assert!(buffer.push::<GenerateResponse>("\n").is_empty());
+    }
+
+    #[test]
+    fn test_malformed_json_line_is_skipped() {
+        let mut buffer = JsonStreamBuffer::default();
+        let values: Vec<GenerateResponse> =
+            buffer.push("{\"error\":\"overloaded\"}\n{\"response\":\"ok\",\"done\":true}\n");
+
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].response, "ok");
+    }
+
+    #[tokio::test]
+    async fn test_stream_without_done_is_incomplete() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/api/generate")
+            .with_body("{\"response\":\"The answer\",\"done\":false}\n{\"response\":\" is\",\"done\":false}\n")
+            .create_async()
+            .await;
+
+        let provider = OllamaProvider::with_options(server.url(), "llama3.2".to_string(), GenerationOptions::default());
+        let result = provider.generate_stream("question", None, Box::new(|_| {})).await;
+
+        match result {
+            Err(LLMError::Stream(message)) => assert_eq!(message, "incomplete response"),
+            other => panic!("expected incomplete stream error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_accepts_done_without_trailing_newline() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/api/generate")
+            .with_body("{\"response\":\"Hello\",\"done\":false}\n{\"response\":\"\",\"done\":true}")
+            .create_async()
+            .await;
+
+        let provider = OllamaProvider::with_options(server.url(), "llama3.2".to_string(), GenerationOptions::default());
+        let response = provider.generate_stream("question", None, Box::new(|_| {})).await.unwrap();
+
+        assert_eq!(response, "Hello");
+    }
+
+    #[test]
+    fn test_capabilities_reported() {
+        let provider = OllamaProvider::with_options(
+            "http://localhost:11434".to_string(),
+            "llama3.2".to_string(),
+            GenerationOptions::default(),
+        );
+        assert_eq!(
+            provider.capabilities(),
+            ProviderCapabilities {
+                supports_streaming: true,
+                max_context_tokens: Some(DEFAULT_CONTEXT_TOKENS),
+                supports_system_role: true,
+            }
+        );
+
+        // A configured window is requested from Ollama and reported back
+        let sized = OllamaProvider::with_options(
+            "http://localhost:11434".to_string(),
+            "llama3.2".to_string(),
+            GenerationOptions { context_tokens: Some(8192), ..GenerationOptions::default() },
+        );
+        assert_eq!(sized.capabilities().max_context_tokens, Some(8192));
+        let json = serde_json::to_value(sized.request("hi", None, true)).unwrap();
+        assert_eq!(json["options"], serde_json::json!({"num_ctx": 8192}));
+    }
+
+    #[test]
+    fn test_request_options_serialize() {
+        let provider = OllamaProvider::with_options(
+            "http://localhost:11434".to_string(),
+            "llama3.2".to_string(),
+            GenerationOptions { max_tokens: Some(256), stop: vec!["###".to_string()], ..GenerationOptions::default() },
+        );
+        let json = serde_json::to_value(provider.request("hi", None, true)).unwrap();
+        assert_eq!(json["options"], serde_json::json!({"num_predict": 256, "stop": ["###"]}));
+
+        let seeded = OllamaProvider::with_options(
+            "http://localhost:11434".to_string(),
+            "llama3.2".to_string(),
+            GenerationOptions { seed: Some(42), ..GenerationOptions::default() },
+        );
+        let json = serde_json::to_value(seeded.request("hi", None, false)).unwrap();
+        assert_eq!(json["options"], serde_json::json!({"seed": 42}));
+
+        let plain = OllamaProvider::with_options(
+            "http://localhost:11434".to_string(),
+            "llama3.2".to_string(),
+            GenerationOptions::default(),
+        );
+        let json = serde_json::to_value(plain.request("hi", None, true)).unwrap();
+        assert!(json.get("options").is_none());
+        assert!(json.get("system").is_none());
+
+        let json = serde_json::to_value(plain.request("hi", Some("Be brief."), true)).unwrap();
+        assert_eq!(json["system"], "Be brief.");
+    }
+
+    #[tokio::test]
+    async fn test_stream_truncates_at_stop_sequence() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/api/generate")
+            .with_body(concat!(
+                "{\"response\":\"Paris is the capital.\",\"done\":false}\n",
+                "{\"response\":\"\\n#\",\"done\":false}\n",
+                "{\"response\":\"## Extra\",\"done\":false}\n",
+                "{\"response\":\"\",\"done\":true}\n",
+            ))
+            .create_async()
+            .await;
+
+        let provider = OllamaProvider::with_options(
+            server.url(),
+            "llama3.2".to_string(),
+            GenerationOptions { stop: vec!["###".to_string()], ..GenerationOptions::default() },
+        );
+        let streamed = Arc::new(Mutex::new(String::new()));
+        let sink = streamed.clone();
+        let response = provider
+            .generate_stream("question", None, Box::new(move |chunk| sink.lock().unwrap().push_str(chunk)))
+            .await
+            .unwrap();
+
+        assert_eq!(response, "Paris is the capital.\n");
+        assert!(!streamed.lock().unwrap().contains("Extra"));
+    }
+}
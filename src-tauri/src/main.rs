@@ -2,165 +2,494 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod db;
+mod diagnostics;
 mod embedding;
+mod endpoint;
+mod error;
+mod fuzzy;
 mod ingest;
+mod language;
 mod llm;
+mod model_compare;
 mod outline;
 mod parser;
 mod rag;
+mod transcript;
 mod vector;
 mod watcher;
 
-use db::{Artifact, Database, ChatMessage, Embedding, Settings};
-use embedding::EmbeddingClient;
-use ingest::IngestEngine;
+use db::{Artifact, ArtifactDetails, ArtifactMerge, CachePruneReport, Database, DbError, ChatMessage, IntegrityReport, NoisyChunk, Settings, SourceUsage};
+use diagnostics::{DiagnosticsReport, EmbeddingBenchmark, EmbeddingModelInfo, MAX_BENCHMARK_SAMPLES};
+use fuzzy::FuzzyMatch;
+use error::AppError;
+use embedding::{check_dimension_mismatch, DimensionMismatch, EmbeddingClient, TextEmbedding};
+use endpoint::EndpointCheck;
+use ingest::{HashVerification, IngestEngine, SyncEstimate, SyncGuard, SyncStatus};
+use model_compare::ModelComparison;
 use outline::OutlineClient;
-use parser::MarkdownParser;
-use rag::RagEngine;
+use rag::{AnswerStyle, ChatReply, EventSink, QueryCoverage, RagEngine, WarmupReport};
+use vector::{NoteComparison, NoteHit, SearchHit, VectorStore};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::api::dialog::{MessageDialogBuilder, MessageDialogKind};
 use tauri::{Manager, State};
 use tokio::sync::Mutex as TokioMutex;
-use serde::{Deserialize, Serialize};
-use uuid::Uuid;
+use watcher::IndexingGate;
 
 // Application state
 pub struct AppState {
     pub db: Arc<Database>,
     pub ingest_engine: Arc<TokioMutex<Option<IngestEngine>>>,
     pub rag_engine: Arc<TokioMutex<RagEngine>>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct SyncStatus {
-    pub is_running: bool,
-    pub total_files: usize,
-    pub processed_files: usize,
-    pub last_sync_at: Option<i64>,
-    pub error: Option<String>,
-}
-
-impl Default for SyncStatus {
-    fn default() -> Self {
-        Self {
-            is_running: false,
-            total_files: 0,
-            processed_files: 0,
-            last_sync_at: None,
-            error: None,
-        }
-    }
+    pub indexing_gate: IndexingGate,
+    pub sync_guard: SyncGuard,
 }
 
 // === Settings Commands ===
 
 #[tauri::command]
-async fn get_settings(state: State<'_, AppState>) -> Result<Settings, String> {
-    state.db.get_settings().map_err(|e| e.to_string())
+async fn get_settings(state: State<'_, AppState>) -> Result<Settings, AppError> {
+    state.db.get_settings().map_err(AppError::from)
 }
 
 #[tauri::command]
-async fn save_settings(state: State<'_, AppState>, settings: Settings) -> Result<(), String> {
-    // Save settings to database
-    state.db.save_settings(&settings).map_err(|e| e.to_string())?;
-    
-    // Update RAG engine with new settings
+async fn save_settings(state: State<'_, AppState>, mut settings: Settings) -> Result<(), AppError> {
+    endpoint::normalize_settings_endpoints(&mut settings).map_err(AppError::InvalidInput)?;
+    apply_settings(&state, &settings).await
+}
+
+/// Go back to the default settings, keeping the configured vaults when
+/// `keep_vault_paths` is set, and return them
+#[tauri::command]
+async fn reset_settings(state: State<'_, AppState>, keep_vault_paths: bool) -> Result<Settings, AppError> {
+    let settings = state.db.default_settings(keep_vault_paths)?;
+    apply_settings(&state, &settings).await?;
+    Ok(settings)
+}
+
+/// Reconfigure the engines for `settings` and store them
+async fn apply_settings(state: &AppState, settings: &Settings) -> Result<(), AppError> {
+    // Update RAG engine with new settings, rejecting ones it can't run with
     let mut rag_engine = state.rag_engine.lock().await;
-    rag_engine.update_settings(
-        state.db.clone(),
-        settings.ollama_endpoint.clone(),
-        settings.ollama_model.clone(),
-        settings.embedding_model.clone(),
-    );
+    rag_engine.update_settings(state.db.clone(), settings)?;
+
+    // Save settings to database
+    state.db.save_settings(settings)?;
     
     // Also update ingest engine if it exists
     let mut ingest_engine_guard = state.ingest_engine.lock().await;
     if ingest_engine_guard.is_some() {
-        let engine = IngestEngine::new(
-            state.db.clone(),
-            settings.ollama_endpoint,
-            settings.embedding_model,
-        );
+        let engine = IngestEngine::new(state.db.clone(), settings);
         *ingest_engine_guard = Some(engine);
     }
+
+    spawn_dimension_probe(state.db.clone(), settings);
     
     Ok(())
 }
 
+/// Record the embedding model's output dimension in the background if it
+/// hasn't been probed before
+fn spawn_dimension_probe(db: Arc<Database>, settings: &Settings) {
+    let client = EmbeddingClient::from_settings(settings);
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = client.probe_dimension(&db).await {
+            log::warn!("Could not probe dimension of {}: {}", client.model_name(), e);
+        }
+    });
+}
+
+/// Compare the configured embedding model against the vectors already stored
+fn detect_dimension_mismatch(db: &Database) -> Result<Option<DimensionMismatch>, db::DbError> {
+    let settings = db.get_settings()?;
+    let recorded = db.get_model_dimensions()?;
+    let stored = db.get_stored_embedding_dimension()?;
+    Ok(check_dimension_mismatch(&settings.embedding_model, &recorded, stored))
+}
+
+/// Returns a mismatch when the index must be rebuilt for the current embedding model
+#[tauri::command]
+async fn get_dimension_mismatch(state: State<'_, AppState>) -> Result<Option<DimensionMismatch>, AppError> {
+    detect_dimension_mismatch(&state.db).map_err(AppError::from)
+}
+
+#[tauri::command]
+async fn run_diagnostics(state: State<'_, AppState>) -> Result<DiagnosticsReport, AppError> {
+    let settings = state.db.get_settings()?;
+    Ok(diagnostics::run_diagnostics(state.db.clone(), &settings).await)
+}
+
+/// Time `sample_count` embedding requests to see whether the endpoint is what slows syncs
+#[tauri::command]
+async fn benchmark_embedding(state: State<'_, AppState>, sample_count: usize) -> Result<EmbeddingBenchmark, AppError> {
+    if !(1..=MAX_BENCHMARK_SAMPLES).contains(&sample_count) {
+        return Err(AppError::InvalidInput(format!(
+            "Sample count must be between 1 and {}",
+            MAX_BENCHMARK_SAMPLES
+        )));
+    }
+    let settings = state.db.get_settings()?;
+    let client = EmbeddingClient::from_settings(&settings);
+    diagnostics::benchmark_embedding(&client, sample_count)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Dimension and norm of the configured embedding model's output, to check
+/// the setup before a big sync
+#[tauri::command]
+async fn get_embedding_model_info(state: State<'_, AppState>) -> Result<EmbeddingModelInfo, AppError> {
+    let settings = state.db.get_settings()?;
+    let client = EmbeddingClient::from_settings(&settings);
+    diagnostics::embedding_model_info(&client)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Whether `url` is a well-formed endpoint and something answers there
+#[tauri::command]
+async fn validate_endpoint(url: String) -> Result<EndpointCheck, AppError> {
+    Ok(endpoint::check_endpoint(&url).await)
+}
+
+/// Check the database for corruption and stray embeddings, deleting them when `repair` is set
+#[tauri::command]
+async fn check_integrity(state: State<'_, AppState>, repair: bool) -> Result<IntegrityReport, AppError> {
+    state.db.check_integrity(repair).map_err(AppError::from)
+}
+
+/// Merge artifacts that were indexed more than once with identical content
+#[tauri::command]
+async fn deduplicate_artifacts(state: State<'_, AppState>) -> Result<Vec<ArtifactMerge>, AppError> {
+    state.db.deduplicate_artifacts().map_err(AppError::from)
+}
+
 // === Chat Commands ===
 
 #[tauri::command]
-async fn get_chat_history(state: State<'_, AppState>) -> Result<Vec<ChatMessage>, String> {
-    state.db.get_chat_history().map_err(|e| e.to_string())
+async fn get_chat_history(state: State<'_, AppState>) -> Result<Vec<ChatMessage>, AppError> {
+    state.db.get_chat_history().map_err(AppError::from)
+}
+
+/// Save the chat history as a Markdown note at `path`
+#[tauri::command]
+async fn export_chat(state: State<'_, AppState>, path: String) -> Result<(), AppError> {
+    if path.trim().is_empty() {
+        return Err(AppError::InvalidInput("Choose a file to export to".to_string()));
+    }
+    let messages = state.db.get_chat_history()?;
+    transcript::export_markdown(&messages, std::path::Path::new(&path))?;
+    Ok(())
 }
 
 #[tauri::command]
-async fn clear_chat(state: State<'_, AppState>) -> Result<(), String> {
-    state.db.clear_chat_history().map_err(|e| e.to_string())
+async fn clear_chat(state: State<'_, AppState>) -> Result<(), AppError> {
+    state.db.clear_chat_history().map_err(AppError::from)
 }
 
+/// Answer a message, streaming it through events and returning the saved
+/// reply. `style` (`concise`, `detailed` or `bullet`) shapes the answer.
 #[tauri::command]
 async fn send_message(
     app_handle: tauri::AppHandle,
     state: State<'_, AppState>,
     query: String,
-) -> Result<(), String> {
-    // Get chat history BEFORE adding the new message
-    let chat_history = state.db.get_chat_history().map_err(|e| e.to_string())?;
-    
-    // Save user message
-    state.db.insert_chat_message("user", &query).map_err(|e| e.to_string())?;
-    
-    // Process through RAG engine with chat context
+    style: Option<String>,
+) -> Result<ChatReply, AppError> {
+    let style = parse_answer_style(style.as_deref())?;
     let rag_engine = state.rag_engine.lock().await;
-    
-    match rag_engine.query(&query, &chat_history, &app_handle).await {
-        Ok(response) => {
-            // Save assistant response
-            state.db.insert_chat_message("assistant", &response).map_err(|e| e.to_string())?;
-            Ok(())
-        }
-        Err(e) => {
-            let error_msg = format!("Error: {}", e);
-            state.db.insert_chat_message("assistant", &error_msg).ok();
-            Err(e.to_string())
-        }
-    }
+    let events: EventSink = Arc::new(move |name, payload| {
+        let _ = app_handle.emit_all(name, payload);
+    });
+    rag_engine.chat(&query, style, events).await.map_err(AppError::from)
+}
+
+/// Summarize one note, streaming the summary through `stream-chunk` events
+#[tauri::command]
+async fn summarize_artifact(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    id: String,
+    save: bool,
+) -> Result<String, AppError> {
+    let rag_engine = state.rag_engine.lock().await;
+    let chunk_handle = app_handle.clone();
+    let summary = rag_engine
+        .summarize_artifact(&id, save, Box::new(move |chunk| {
+            let _ = chunk_handle.emit_all("stream-chunk", serde_json::json!({
+                "content": chunk,
+                "done": false
+            }));
+        }))
+        .await
+        ?;
+
+    let _ = app_handle.emit_all("stream-chunk", serde_json::json!({
+        "content": "",
+        "done": true
+    }));
+    Ok(summary)
+}
+
+/// Semantic search over notes modified since a unix timestamp; `explain`
+/// adds the terms each hit shares with the query
+#[tauri::command]
+async fn search_recent(
+    state: State<'_, AppState>,
+    query: String,
+    since_timestamp: i64,
+    limit: usize,
+    explain: Option<bool>,
+) -> Result<Vec<SearchHit>, AppError> {
+    let rag_engine = state.rag_engine.lock().await;
+    rag_engine
+        .search_recent(&query, since_timestamp, limit, explain.unwrap_or(false))
+        .await
+        .map_err(AppError::from)
+}
+
+/// Semantic search restricted to the given artifact ids
+#[tauri::command]
+async fn search_within_ids(
+    state: State<'_, AppState>,
+    query: String,
+    artifact_ids: Vec<String>,
+    limit: usize,
+) -> Result<Vec<SearchHit>, AppError> {
+    let rag_engine = state.rag_engine.lock().await;
+    rag_engine
+        .search_within_ids(&query, &artifact_ids, limit)
+        .await
+        .map_err(AppError::from)
+}
+
+/// Semantic search returning the best notes, each with its best snippet
+#[tauri::command]
+async fn grouped_search(
+    state: State<'_, AppState>,
+    query: String,
+    limit: usize,
+) -> Result<Vec<NoteHit>, AppError> {
+    let rag_engine = state.rag_engine.lock().await;
+    rag_engine.grouped_search(&query, limit).await.map_err(AppError::from)
+}
+
+/// Compare two notes by their stored chunk embeddings
+#[tauri::command]
+async fn compare_notes(state: State<'_, AppState>, id_a: String, id_b: String) -> Result<NoteComparison, AppError> {
+    VectorStore::new(state.db.clone())
+        .compare_artifacts(&id_a, &id_b)
+        .map_err(AppError::from)
+}
+
+/// Report which expected questions the indexed notes can't support
+#[tauri::command]
+async fn coverage_report(state: State<'_, AppState>, queries: Vec<String>) -> Result<Vec<QueryCoverage>, AppError> {
+    let rag_engine = state.rag_engine.lock().await;
+    rag_engine.coverage_report(&queries).await.map_err(AppError::from)
+}
+
+/// Return the prompt a message would be answered with, without sending it
+#[tauri::command]
+async fn preview_prompt(state: State<'_, AppState>, query: String, style: Option<String>) -> Result<String, AppError> {
+    let style = parse_answer_style(style.as_deref())?;
+    let chat_history = state.db.get_chat_history()?;
+    let rag_engine = state.rag_engine.lock().await;
+    rag_engine.preview_prompt(&query, &chat_history, style).await.map_err(AppError::from)
+}
+
+/// An answer style passed to a command; unset means the default style
+fn parse_answer_style(style: Option<&str>) -> Result<Option<AnswerStyle>, AppError> {
+    style
+        .map(|name| AnswerStyle::parse(name).ok_or_else(|| AppError::InvalidInput(format!("Unknown answer style '{}'", name))))
+        .transpose()
+}
+
+/// Load the models and vector index ahead of the first query
+#[tauri::command]
+async fn warmup(state: State<'_, AppState>) -> Result<WarmupReport, AppError> {
+    let rag_engine = state.rag_engine.lock().await;
+    Ok(rag_engine.warmup().await)
+}
+
+#[tauri::command]
+async fn embed_text(state: State<'_, AppState>, text: String) -> Result<TextEmbedding, AppError> {
+    let settings = state.db.get_settings()?;
+    let client = EmbeddingClient::from_settings(&settings);
+    client.embed_text(&text).await.map_err(AppError::from)
+}
+
+/// Compare how two embedding models rank a sample of notes for test queries,
+/// without touching the index
+#[tauri::command]
+async fn compare_embedding_models(
+    state: State<'_, AppState>,
+    model_a: String,
+    model_b: String,
+    queries: Vec<String>,
+    sample_notes: Option<usize>,
+    top_k: Option<usize>,
+) -> Result<ModelComparison, AppError> {
+    let settings = state.db.get_settings()?;
+    model_compare::compare_models(
+        &state.db,
+        &settings,
+        &model_a,
+        &model_b,
+        &queries,
+        sample_notes.unwrap_or(20),
+        top_k.unwrap_or(5),
+    )
+    .await
+    .map_err(AppError::from)
 }
 
 // === Sync Commands ===
 
 #[tauri::command]
-async fn sync_vault(
+async fn sync_vaults(
     app_handle: tauri::AppHandle,
     state: State<'_, AppState>,
-    vault_path: String,
-) -> Result<SyncStatus, String> {
-    let mut ingest_engine_guard = state.ingest_engine.lock().await;
-    
-    // Create or get ingest engine
-    if ingest_engine_guard.is_none() {
-        let settings = state.db.get_settings().map_err(|e| e.to_string())?;
-        let engine = IngestEngine::new(
-            state.db.clone(),
-            settings.ollama_endpoint,
-            settings.embedding_model,
-        );
-        *ingest_engine_guard = Some(engine);
+    vault_paths: Vec<String>,
+) -> Result<SyncStatus, AppError> {
+    let sync = || async {
+        let mut ingest_engine_guard = state.ingest_engine.lock().await;
+        let engine = ensure_ingest_engine(&state.db, &mut ingest_engine_guard)?;
+
+        // Run sync, forwarding file and chunk progress to the frontend
+        let progress_handle = app_handle.clone();
+        let embedding_handle = app_handle.clone();
+        let sync_guard = state.sync_guard.clone();
+        let status = engine
+            .sync_vaults_with_embedding_progress(
+                &vault_paths,
+                move |processed, total, current_file| {
+                    sync_guard.report_progress(processed, total);
+                    let _ = progress_handle.emit_all("sync-progress", serde_json::json!({
+                        "processed": processed,
+                        "total": total,
+                        "currentFile": current_file
+                    }));
+                },
+                move |embedded, total| {
+                    let percent = if total == 0 { 100.0 } else { embedded as f64 * 100.0 / total as f64 };
+                    let _ = embedding_handle.emit_all("embedding-percent", serde_json::json!({
+                        "percent": percent,
+                        "embeddedChunks": embedded,
+                        "totalChunks": total
+                    }));
+                },
+            )
+            .await?;
+
+        let _ = app_handle.emit_all("sync-complete", &status);
+        Ok(status)
+    };
+
+    match state.sync_guard.run(sync).await {
+        Some(result) => result,
+        None => Ok(state.sync_guard.status()),
     }
-    
-    let engine = ingest_engine_guard.as_mut().unwrap();
-    
-    // Run sync
-    match engine.sync_vault(&vault_path, &app_handle).await {
-        Ok(status) => Ok(status),
-        Err(e) => Err(e.to_string()),
+}
+
+/// Create the ingest engine from stored settings if it doesn't exist yet
+fn ensure_ingest_engine<'a>(
+    db: &Arc<Database>,
+    engine: &'a mut Option<IngestEngine>,
+) -> Result<&'a mut IngestEngine, AppError> {
+    if engine.is_none() {
+        let settings = db.get_settings()?;
+        *engine = Some(IngestEngine::new(db.clone(), &settings));
     }
+    Ok(engine.as_mut().unwrap())
+}
+
+/// Delete cached embeddings no current note uses. Skipped while a sync
+/// holds the ingest engine, since it may be about to reuse them
+#[tauri::command]
+async fn prune_embedding_cache(state: State<'_, AppState>) -> Result<CachePruneReport, AppError> {
+    let Ok(_ingest_engine_guard) = state.ingest_engine.try_lock() else {
+        return Ok(CachePruneReport { skipped: true, ..Default::default() });
+    };
+    state.db.prune_embedding_cache().map_err(AppError::from)
+}
+
+/// Re-embed notes that have no embeddings, returning how many were fixed
+#[tauri::command]
+async fn repair_embeddings(state: State<'_, AppState>) -> Result<usize, AppError> {
+    let repair = || async {
+        let mut ingest_engine_guard = state.ingest_engine.lock().await;
+        let engine = ensure_ingest_engine(&state.db, &mut ingest_engine_guard)?;
+        engine.repair_embeddings().await.map_err(AppError::from)
+    };
+
+    state
+        .sync_guard
+        .run(repair)
+        .await
+        .unwrap_or(Err(AppError::SyncRunning))
+}
+
+/// Report notes whose stored content hash no longer matches their file,
+/// re-ingesting them when `repair` is set
+#[tauri::command]
+async fn verify_hashes(state: State<'_, AppState>, repair: bool) -> Result<HashVerification, AppError> {
+    let verify = || async {
+        let mut ingest_engine_guard = state.ingest_engine.lock().await;
+        let engine = ensure_ingest_engine(&state.db, &mut ingest_engine_guard)?;
+        engine.verify_hashes(repair).await.map_err(AppError::from)
+    };
+
+    state
+        .sync_guard
+        .run(verify)
+        .await
+        .unwrap_or(Err(AppError::SyncRunning))
 }
 
 #[tauri::command]
-async fn get_sync_status(state: State<'_, AppState>) -> Result<SyncStatus, String> {
+async fn pause_indexing(state: State<'_, AppState>) -> Result<(), AppError> {
+    state.indexing_gate.pause();
+    Ok(())
+}
+
+/// Resume indexing and apply whatever changed on disk while it was paused.
+/// Indexing stays paused if a sync is running.
+#[tauri::command]
+async fn resume_indexing(state: State<'_, AppState>) -> Result<(), AppError> {
+    let resume = || async {
+        let mut ingest_engine_guard = state.ingest_engine.lock().await;
+        let engine = ensure_ingest_engine(&state.db, &mut ingest_engine_guard)?;
+        engine.apply_queued_events(&state.indexing_gate).await?;
+        Ok(())
+    };
+
+    state
+        .sync_guard
+        .run(resume)
+        .await
+        .unwrap_or(Err(AppError::SyncRunning))
+}
+
+#[tauri::command]
+async fn is_indexing_paused(state: State<'_, AppState>) -> Result<bool, AppError> {
+    Ok(state.indexing_gate.is_paused())
+}
+
+/// Project the size and duration of syncing a vault without indexing it
+#[tauri::command]
+async fn estimate_sync(state: State<'_, AppState>, vault_path: String) -> Result<SyncEstimate, AppError> {
+    let settings = state.db.get_settings()?;
+    let engine = IngestEngine::new(state.db.clone(), &settings);
+    engine.estimate_sync(&vault_path).await.map_err(AppError::from)
+}
+
+#[tauri::command]
+async fn get_sync_status(state: State<'_, AppState>) -> Result<SyncStatus, AppError> {
+    // A running sync holds the engine, so report its progress from the guard
+    if state.sync_guard.is_running() {
+        return Ok(state.sync_guard.status());
+    }
+
     let ingest_engine_guard = state.ingest_engine.lock().await;
     
     if let Some(engine) = ingest_engine_guard.as_ref() {
@@ -171,41 +500,117 @@ async fn get_sync_status(state: State<'_, AppState>) -> Result<SyncStatus, Strin
 }
 
 #[tauri::command]
-async fn get_artifacts(state: State<'_, AppState>) -> Result<Vec<Artifact>, String> {
-    state.db.get_all_artifacts().map_err(|e| e.to_string())
+async fn get_artifacts(state: State<'_, AppState>) -> Result<Vec<Artifact>, AppError> {
+    state.db.get_all_artifacts().map_err(AppError::from)
+}
+
+/// One artifact with its chunk count
+#[tauri::command]
+async fn get_artifact(state: State<'_, AppState>, id: String) -> Result<ArtifactDetails, AppError> {
+    state.db.get_artifact_details(&id).map_err(AppError::from)
+}
+
+/// Notes indexed or modified after a unix timestamp, newest first
+#[tauri::command]
+async fn get_artifacts_changed_since(
+    state: State<'_, AppState>,
+    timestamp: i64,
+) -> Result<Vec<ArtifactDetails>, AppError> {
+    state.db.get_artifacts_changed_since(timestamp).map_err(AppError::from)
 }
 
+/// Fuzzy-match note titles and file names for quick navigation
 #[tauri::command]
-async fn delete_artifact(state: State<'_, AppState>, id: String) -> Result<(), String> {
+async fn fuzzy_find_artifacts(
+    state: State<'_, AppState>,
+    query: String,
+    limit: usize,
+) -> Result<Vec<FuzzyMatch>, AppError> {
+    let artifacts = state.db.get_all_artifacts()?;
+    Ok(fuzzy::rank_artifacts(&query, artifacts, limit))
+}
+
+/// Notes most often used as chat context, optionally since a unix timestamp
+#[tauri::command]
+async fn get_top_sources(
+    state: State<'_, AppState>,
+    limit: usize,
+    since: Option<i64>,
+) -> Result<Vec<SourceUsage>, AppError> {
+    state.db.get_top_sources(limit, since).map_err(AppError::from)
+}
+
+/// Rate an assistant answer as helpful (thumbs up) or not (thumbs down)
+#[tauri::command]
+async fn rate_message(
+    state: State<'_, AppState>,
+    message_id: i64,
+    helpful: bool,
+) -> Result<(), AppError> {
+    state.db.rate_message(message_id, helpful).map_err(AppError::from)
+}
+
+/// Chunks that keep turning up in downvoted answers. Needs retrieval
+/// tracking on while the rated answers were generated
+#[tauri::command]
+async fn get_noisy_chunks(
+    state: State<'_, AppState>,
+    limit: usize,
+) -> Result<Vec<NoisyChunk>, AppError> {
+    state.db.get_noisy_chunks(limit).map_err(AppError::from)
+}
+
+/// Every tag in the vault with how many notes use it
+#[tauri::command]
+async fn get_tags(state: State<'_, AppState>) -> Result<Vec<(String, usize)>, AppError> {
+    state.db.get_tag_counts().map_err(AppError::from)
+}
+
+/// Pin a note so it is always included in chat context
+#[tauri::command]
+async fn set_artifact_pinned(state: State<'_, AppState>, id: String, pinned: bool) -> Result<(), AppError> {
+    state.db.set_artifact_pinned(&id, pinned).map_err(AppError::from)
+}
+
+#[tauri::command]
+async fn delete_artifact(state: State<'_, AppState>, id: String) -> Result<(), AppError> {
     // Delete embeddings first (foreign key constraint)
-    state.db.delete_embeddings_by_artifact(&id).map_err(|e| e.to_string())?;
+    state.db.delete_embeddings_by_artifact(&id)?;
     // Delete the artifact
-    state.db.delete_artifact(&id).map_err(|e| e.to_string())?;
+    state.db.delete_artifact(&id)?;
     Ok(())
 }
 
+/// Remove all artifacts from one source namespace, e.g. `outline://`
+#[tauri::command]
+async fn delete_artifacts_by_prefix(state: State<'_, AppState>, prefix: String) -> Result<usize, AppError> {
+    if prefix.trim().is_empty() {
+        return Err(AppError::InvalidInput("Prefix must not be empty".to_string()));
+    }
+    state.db.delete_artifacts_by_prefix(&prefix).map_err(AppError::from)
+}
+
 // === Outline Sync Command ===
 
 #[tauri::command]
 async fn sync_outline(
     app_handle: tauri::AppHandle,
     state: State<'_, AppState>,
-) -> Result<SyncStatus, String> {
-    let settings = state.db.get_settings().map_err(|e| e.to_string())?;
+) -> Result<SyncStatus, AppError> {
+    match state.sync_guard.run(|| run_outline_sync(&app_handle, &state)).await {
+        Some(result) => result,
+        None => Ok(state.sync_guard.status()),
+    }
+}
+
+async fn run_outline_sync(app_handle: &tauri::AppHandle, state: &AppState) -> Result<SyncStatus, AppError> {
+    let settings = state.db.get_settings()?;
     
     // Create Outline client
     let client = OutlineClient::new(
         settings.outline_base_url.clone(),
         settings.outline_api_key.clone(),
-    ).map_err(|e| e.to_string())?;
-    
-    // Create embedding client
-    let embedding_client = EmbeddingClient::new(
-        settings.ollama_endpoint.clone(),
-        settings.embedding_model.clone(),
-    );
-    
-    let parser = MarkdownParser::new();
+    )?;
     
     // Emit initial progress
     let _ = app_handle.emit_all("outline-sync-progress", serde_json::json!({
@@ -215,115 +620,28 @@ async fn sync_outline(
     }));
     
     // Fetch all documents from Outline
-    let documents = client.list_all_documents().await.map_err(|e| e.to_string())?;
-    let total = documents.len();
-    
-    log::info!("Found {} documents in Outline", total);
-    
-    let mut processed = 0;
-    let mut errors = Vec::new();
-    
-    for doc in documents {
-        // Emit progress
-        let _ = app_handle.emit_all("outline-sync-progress", serde_json::json!({
-            "processed": processed,
-            "total": total,
-            "currentDocument": &doc.title
-        }));
-        
-        // Fetch full document content
-        match client.get_document(&doc.id).await {
-            Ok(full_doc) => {
-                let path = format!("outline://{}", doc.id);
-                
-                // Parse the markdown content
-                match parser.parse_content(&full_doc.text) {
-                    Ok(parsed) => {
-                        // Check if document has changed
-                        let should_update = match state.db.get_artifact_by_path(&path) {
-                            Ok(Some(existing)) => existing.content_hash != parsed.content_hash,
-                            Ok(None) => true,
-                            Err(_) => true,
-                        };
-                        
-                        if should_update {
-                            // Delete old embeddings if exists
-                            if let Ok(Some(existing)) = state.db.get_artifact_by_path(&path) {
-                                let _ = state.db.delete_embeddings_by_artifact(&existing.id);
-                            }
-                            
-                            // Create artifact
-                            let artifact_id = Uuid::new_v4().to_string();
-                            let now = SystemTime::now()
-                                .duration_since(UNIX_EPOCH)
-                                .unwrap()
-                                .as_secs() as i64;
-                            
-                            let artifact = Artifact {
-                                id: artifact_id.clone(),
-                                path: path.clone(),
-                                last_modified: now,
-                                content_hash: parsed.content_hash,
-                                indexed_at: now,
-                            };
-                            
-                            if let Err(e) = state.db.upsert_artifact(&artifact) {
-                                errors.push(format!("Failed to save artifact {}: {}", doc.title, e));
-                                continue;
-                            }
-                            
-                            // Generate embeddings for each chunk
-                            for (chunk_index, chunk_content) in parsed.chunks.iter().enumerate() {
-                                match embedding_client.embed(chunk_content).await {
-                                    Ok(embedding_vec) => {
-                                        let embedding = Embedding {
-                                            id: format!("{}#{}", artifact_id, chunk_index),
-                                            artifact_id: artifact_id.clone(),
-                                            chunk_index: chunk_index as i32,
-                                            content: chunk_content.clone(),
-                                            embedding: embedding_vec,
-                                        };
-                                        
-                                        if let Err(e) = state.db.insert_embedding(&embedding) {
-                                            log::warn!("Failed to save embedding for {}: {}", doc.title, e);
-                                        }
-                                    }
-                                    Err(e) => {
-                                        log::warn!("Failed to generate embedding for {}: {}", doc.title, e);
-                                    }
-                                }
-                            }
-                            
-                            log::info!("Indexed Outline document: {}", doc.title);
-                        } else {
-                            log::debug!("Skipping unchanged document: {}", doc.title);
-                        }
-                    }
-                    Err(e) => {
-                        errors.push(format!("Failed to parse {}: {}", doc.title, e));
-                    }
-                }
-            }
-            Err(e) => {
-                errors.push(format!("Failed to fetch {}: {}", doc.title, e));
-            }
-        }
-        
-        processed += 1;
-    }
-    
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as i64;
+    let documents = client
+        .list_all_documents(|found| {
+            let _ = app_handle.emit_all("outline-sync-progress", serde_json::json!({
+                "processed": 0,
+                "total": 0,
+                "currentDocument": format!("Fetching document list... {} found", found)
+            }));
+        })
+        .await?;
+    log::info!("Found {} documents in Outline", documents.len());
     
-    let status = SyncStatus {
-        is_running: false,
-        total_files: total,
-        processed_files: processed,
-        last_sync_at: Some(now),
-        error: if errors.is_empty() { None } else { Some(errors.join("; ")) },
-    };
+    let mut engine = IngestEngine::new(state.db.clone(), &settings);
+    let status = engine
+        .sync_outline_documents(&client, documents, |processed, total, title| {
+            state.sync_guard.report_progress(processed, total);
+            let _ = app_handle.emit_all("outline-sync-progress", serde_json::json!({
+                "processed": processed,
+                "total": total,
+                "currentDocument": title
+            }));
+        })
+        .await;
     
     // Emit completion
     let _ = app_handle.emit_all("outline-sync-complete", &status);
@@ -341,28 +659,58 @@ fn main() {
                 .app_data_dir()
                 .expect("Failed to get app data directory");
             
-            // Initialize database
-            let db = Arc::new(
-                Database::new(app_data_dir.clone())
-                    .expect("Failed to initialize database")
-            );
+            // Initialize database, encrypted when a passphrase is provided;
+            // without it there's nothing to run, so tell the user why and
+            // quit once they've read it instead of panicking
+            let passphrase = std::env::var("METABRAIN_DB_PASSPHRASE").ok().filter(|p| !p.is_empty());
+            let db = match Database::with_passphrase(app_data_dir.clone(), passphrase.as_deref()) {
+                Ok(db) => Arc::new(db),
+                Err(e) => {
+                    log::error!("Failed to initialize database: {}", e);
+                    let hint = match e {
+                        DbError::DataDir { .. } | DbError::Open { .. } => {
+                            "\n\nCheck that the folder exists and that you can write to it."
+                        }
+                        _ => "",
+                    };
+                    MessageDialogBuilder::new("Metabrain can't start", format!("{}{}", e, hint))
+                        .kind(MessageDialogKind::Error)
+                        .show(|_| std::process::exit(1));
+                    return Ok(());
+                }
+            };
             
             // Get settings for RAG engine initialization
             let settings = db.get_settings().unwrap_or_default();
+
+            if let Ok(Some(mismatch)) = detect_dimension_mismatch(&db) {
+                log::warn!(
+                    "Embedding model {} produces {}-dim vectors but the index holds {}-dim vectors; a rebuild is needed",
+                    mismatch.model,
+                    mismatch.expected_dimension,
+                    mismatch.stored_dimension
+                );
+            }
+            
+            spawn_dimension_probe(db.clone(), &settings);
             
             // Initialize RAG engine
-            let rag_engine = RagEngine::new(
-                db.clone(),
-                settings.ollama_endpoint,
-                settings.ollama_model,
-                settings.embedding_model,
-            );
+            let rag_engine = RagEngine::new(db.clone(), &settings).or_else(|e| {
+                log::error!("Invalid stored settings ({}), falling back to Ollama", e);
+                let fallback = Settings {
+                    llm_provider: Settings::default().llm_provider,
+                    ..settings.clone()
+                };
+                RagEngine::new(db.clone(), &fallback)
+            }).expect("Failed to initialize RAG engine");
             
             // Create app state
             let state = AppState {
                 db,
                 ingest_engine: Arc::new(TokioMutex::new(None)),
                 rag_engine: Arc::new(TokioMutex::new(rag_engine)),
+                indexing_gate: IndexingGate::new(),
+                sync_guard: SyncGuard::new(),
             };
             
             app.manage(state);
@@ -372,13 +720,48 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             get_settings,
             save_settings,
+            reset_settings,
+            get_dimension_mismatch,
+            run_diagnostics,
+            benchmark_embedding,
+            get_embedding_model_info,
+            validate_endpoint,
+            check_integrity,
+            deduplicate_artifacts,
             get_chat_history,
             clear_chat,
+            export_chat,
             send_message,
-            sync_vault,
+            preview_prompt,
+            search_recent,
+            search_within_ids,
+            grouped_search,
+            compare_notes,
+            coverage_report,
+            summarize_artifact,
+            warmup,
+            embed_text,
+            compare_embedding_models,
+            sync_vaults,
+            estimate_sync,
             get_sync_status,
+            repair_embeddings,
+            verify_hashes,
+            prune_embedding_cache,
+            pause_indexing,
+            resume_indexing,
+            is_indexing_paused,
             get_artifacts,
+            get_artifact,
+            get_artifacts_changed_since,
+            fuzzy_find_artifacts,
+            get_top_sources,
+            rate_message,
+            get_noisy_chunks,
+            get_tags,
+            set_artifact_pinned,
             delete_artifact,
+            delete_artifacts_by_prefix,
             sync_outline,
         ])
         .run(tauri::generate_context!())
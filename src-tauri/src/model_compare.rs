@@ -0,0 +1,167 @@
+//! Side-by-side retrieval with two embedding models over a sample of notes,
+//! to judge whether switching models is worth a full reindex.
+
+use crate::db::{Database, Settings};
+use crate::embedding::{EmbeddingClient, EmbeddingResult};
+use crate::vector::SimilarityMetric;
+use serde::Serialize;
+
+/// Cap on chunks embedded per model, so a comparison stays quick
+const MAX_SAMPLE_CHUNKS: usize = 200;
+
+/// How the two models ranked the sample for one query
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryAgreement {
+    pub query: String,
+    /// Best chunks under each model, as `path#chunk`
+    pub top_a: Vec<String>,
+    pub top_b: Vec<String>,
+    /// Share of the top results both models returned (0-1)
+    pub agreement: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelComparison {
+    pub model_a: String,
+    pub model_b: String,
+    pub sampled_chunks: usize,
+    pub queries: Vec<QueryAgreement>,
+    pub mean_agreement: f32,
+}
+
+/// Embed the chunks of the `sample_notes` most recently modified notes with
+/// both models and compare the `top_k` chunks each retrieves per query
+pub async fn compare_models(
+    db: &Database,
+    settings: &Settings,
+    model_a: &str,
+    model_b: &str,
+    queries: &[String],
+    sample_notes: usize,
+    top_k: usize,
+) -> EmbeddingResult<ModelComparison> {
+    let mut artifacts = db.get_all_artifacts()?;
+    artifacts.sort_by_key(|a| std::cmp::Reverse(a.last_modified));
+
+    let mut labels = Vec::new();
+    let mut contents = Vec::new();
+    for artifact in artifacts.iter().take(sample_notes) {
+        for chunk in db.get_artifact_chunks(&artifact.id)? {
+            labels.push(format!("{}#{}", artifact.path, chunk.chunk_index));
+            contents.push(chunk.content);
+        }
+    }
+    labels.truncate(MAX_SAMPLE_CHUNKS);
+    contents.truncate(MAX_SAMPLE_CHUNKS);
+
+    let client_for = |model: &str| {
+        EmbeddingClient::from_settings(&Settings {
+            embedding_model: model.to_string(),
+            ..settings.clone()
+        })
+    };
+    let (client_a, client_b) = (client_for(model_a), client_for(model_b));
+
+    let mut chunks_a = Vec::with_capacity(contents.len());
+    let mut chunks_b = Vec::with_capacity(contents.len());
+    for content in &contents {
+        chunks_a.push(client_a.embed_document(content).await?);
+        chunks_b.push(client_b.embed_document(content).await?);
+    }
+
+    let mut results = Vec::new();
+    for query in queries {
+        let top_a = top_chunks(&client_a.embed_query(query).await?, &chunks_a, &labels, top_k);
+        let top_b = top_chunks(&client_b.embed_query(query).await?, &chunks_b, &labels, top_k);
+        let shared = top_a.iter().filter(|label| top_b.contains(label)).count();
+        results.push(QueryAgreement {
+            query: query.clone(),
+            agreement: shared as f32 / top_a.len().max(1) as f32,
+            top_a,
+            top_b,
+        });
+    }
+
+    let mean_agreement = if results.is_empty() {
+        0.0
+    } else {
+        results.iter().map(|r| r.agreement).sum::<f32>() / results.len() as f32
+    };
+
+    Ok(ModelComparison {
+        model_a: model_a.to_string(),
+        model_b: model_b.to_string(),
+        sampled_chunks: contents.len(),
+        queries: results,
+        mean_agreement,
+    })
+}
+
+/// Labels of the `k` chunks most cosine-similar to `query`, best first
+fn top_chunks(query: &[f32], chunks: &[Vec<f32>], labels: &[String], k: usize) -> Vec<String> {
+    let mut scored: Vec<(usize, f32)> = chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| (i, SimilarityMetric::Cosine.score(query, chunk)))
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.into_iter().take(k).map(|(i, _)| labels[i].clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{Artifact, Embedding};
+
+    #[tokio::test]
+    async fn test_agreement_between_two_models() {
+        let mut server = mockito::Server::new_async().await;
+        // Both models agree on the notes but read query "q" differently
+        for (model, prompt, vector) in [
+            ("model-a", "alpha", [1.0, 0.0]),
+            ("model-a", "beta", [0.0, 1.0]),
+            ("model-a", "q", [1.0, 0.0]),
+            ("model-a", "r", [1.0, 0.0]),
+            ("model-b", "alpha", [1.0, 0.0]),
+            ("model-b", "beta", [0.0, 1.0]),
+            ("model-b", "q", [0.0, 1.0]),
+            ("model-b", "r", [1.0, 0.0]),
+        ] {
+            server
+                .mock("POST", "/api/embeddings")
+                .match_body(mockito::Matcher::PartialJson(serde_json::json!({ "model": model, "prompt": prompt })))
+                .with_body(serde_json::json!({ "embedding": vector }).to_string())
+                .create_async()
+                .await;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        for id in ["alpha", "beta"] {
+            db.upsert_artifact(&Artifact { id: id.to_string(), path: format!("{}.md", id), ..Default::default() })
+                .unwrap();
+            db.insert_embedding(&Embedding {
+                id: format!("{}#0", id),
+                artifact_id: id.to_string(),
+                chunk_index: 0,
+                content: id.to_string(),
+                embedding: vec![0.0, 0.0],
+            }).unwrap();
+        }
+        let settings = Settings { ollama_endpoint: server.url(), ..Settings::default() };
+
+        let queries = vec!["q".to_string(), "r".to_string()];
+        let comparison = compare_models(&db, &settings, "model-a", "model-b", &queries, 10, 1)
+            .await
+            .unwrap();
+
+        assert_eq!(comparison.sampled_chunks, 2);
+        assert_eq!(comparison.queries[0].top_a, vec!["alpha.md#0"]);
+        assert_eq!(comparison.queries[0].top_b, vec!["beta.md#0"]);
+        assert_eq!(comparison.queries[0].agreement, 0.0);
+        assert_eq!(comparison.queries[1].agreement, 1.0);
+        assert_eq!(comparison.mean_agreement, 0.5);
+    }
+}
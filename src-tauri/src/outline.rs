@@ -1,5 +1,7 @@
 //! Outline Wiki API client for fetching documents.
 
+use crate::endpoint::trim_endpoint;
+use chrono::{DateTime, Utc};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -12,6 +14,10 @@ pub enum OutlineError {
     Api(String),
     #[error("Missing API key")]
     MissingApiKey,
+    #[error("Outline rejected the API key")]
+    Unauthorized,
+    #[error("Invalid timestamp: {0}")]
+    Timestamp(String),
 }
 
 pub type OutlineResult<T> = Result<T, OutlineError>;
@@ -28,6 +34,37 @@ pub struct OutlineDocument {
     pub updated_at: String,
     #[serde(default)]
     pub archived_at: Option<String>,
+    /// Collection the document lives in
+    #[serde(default)]
+    pub collection_id: Option<String>,
+}
+
+/// Outline collection metadata from collections.info
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutlineCollection {
+    pub name: String,
+}
+
+/// Parse an Outline ISO 8601 timestamp (`2024-03-05T10:15:30.123Z`, or with an offset)
+pub fn parse_timestamp(value: &str) -> OutlineResult<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| OutlineError::Timestamp(format!("{:?}: {}", value, e)))
+}
+
+impl OutlineDocument {
+    pub fn updated(&self) -> OutlineResult<DateTime<Utc>> {
+        parse_timestamp(&self.updated_at)
+    }
+
+    pub fn archived(&self) -> OutlineResult<Option<DateTime<Utc>>> {
+        self.archived_at.as_deref().map(parse_timestamp).transpose()
+    }
+}
+
+/// Order documents most recently updated first, unparseable timestamps last
+pub fn sort_by_recency(documents: &mut [OutlineDocument]) {
+    documents.sort_by_key(|doc| std::cmp::Reverse(doc.updated().ok()));
 }
 
 /// Response wrapper for Outline API
@@ -42,6 +79,11 @@ pub struct OutlineDocumentResponse {
     pub data: OutlineDocument,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct OutlineCollectionResponse {
+    pub data: OutlineCollection,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct OutlinePagination {
@@ -56,7 +98,7 @@ pub struct ListDocumentsRequest {
     pub limit: usize,
 }
 
-/// Request body for documents.info
+/// Request body for documents.info and collections.info
 #[derive(Debug, Serialize)]
 pub struct GetDocumentRequest {
     pub id: String,
@@ -78,7 +120,7 @@ impl OutlineClient {
 
         Ok(Self {
             client: Client::new(),
-            base_url: base_url.trim_end_matches('/').to_string(),
+            base_url: trim_endpoint(&base_url).to_string(),
             api_key,
         })
     }
@@ -97,6 +139,9 @@ impl OutlineClient {
 
         if !response.status().is_success() {
             let status = response.status();
+            if status == reqwest::StatusCode::UNAUTHORIZED {
+                return Err(OutlineError::Unauthorized);
+            }
             let error_text = response.text().await.unwrap_or_default();
             return Err(OutlineError::Api(format!("HTTP {}: {}", status, error_text)));
         }
@@ -105,8 +150,12 @@ impl OutlineClient {
         Ok(result)
     }
 
-    /// Fetch all documents (handles pagination automatically)
-    pub async fn list_all_documents(&self) -> OutlineResult<Vec<OutlineDocument>> {
+    /// Fetch all documents (handles pagination automatically), calling
+    /// `on_page` with the number of documents found so far after each page
+    pub async fn list_all_documents<F>(&self, mut on_page: F) -> OutlineResult<Vec<OutlineDocument>>
+    where
+        F: FnMut(usize),
+    {
         let mut all_documents = Vec::new();
         let mut offset = 0;
         let limit = 100; // Max per page
@@ -115,6 +164,7 @@ impl OutlineClient {
             let response = self.list_documents(offset, limit).await?;
             let count = response.data.len();
             all_documents.extend(response.data);
+            on_page(all_documents.len());
 
             if count < limit {
                 break;
@@ -122,8 +172,9 @@ impl OutlineClient {
             offset += limit;
         }
 
-        // Filter out archived documents
-        all_documents.retain(|doc| doc.archived_at.is_none());
+        // Filter out archived documents (an unreadable archive date still means archived)
+        all_documents.retain(|doc| matches!(doc.archived(), Ok(None)));
+        sort_by_recency(&mut all_documents);
 
         Ok(all_documents)
     }
@@ -142,6 +193,9 @@ impl OutlineClient {
 
         if !response.status().is_success() {
             let status = response.status();
+            if status == reqwest::StatusCode::UNAUTHORIZED {
+                return Err(OutlineError::Unauthorized);
+            }
             let error_text = response.text().await.unwrap_or_default();
             return Err(OutlineError::Api(format!("HTTP {}: {}", status, error_text)));
         }
@@ -149,6 +203,31 @@ impl OutlineClient {
         let result: OutlineDocumentResponse = response.json().await?;
         Ok(result.data)
     }
+
+    /// Get a collection's metadata
+    pub async fn get_collection(&self, id: &str) -> OutlineResult<OutlineCollection> {
+        let url = format!("{}/collections.info", self.base_url);
+
+        let response = self.client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json")
+            .json(&GetDocumentRequest { id: id.to_string() })
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            if status == reqwest::StatusCode::UNAUTHORIZED {
+                return Err(OutlineError::Unauthorized);
+            }
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(OutlineError::Api(format!("HTTP {}: {}", status, error_text)));
+        }
+
+        let result: OutlineCollectionResponse = response.json().await?;
+        Ok(result.data)
+    }
 }
 
 #[cfg(test)]
@@ -169,5 +248,115 @@ mod tests {
         );
         assert!(result.is_ok());
     }
-}
 
+    fn document(id: &str, updated_at: &str) -> OutlineDocument {
+        OutlineDocument {
+            id: id.to_string(),
+            title: id.to_string(),
+            url_id: id.to_string(),
+            text: String::new(),
+            updated_at: updated_at.to_string(),
+            archived_at: None,
+            collection_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_document_listing_reports_each_page() {
+        let mut server = mockito::Server::new_async().await;
+        for (offset, count) in [(0, 100), (100, 100), (200, 30)] {
+            let data: Vec<serde_json::Value> = (offset..offset + count)
+                .map(|i| serde_json::json!({
+                    "id": format!("doc-{}", i),
+                    "title": "Doc",
+                    "urlId": format!("doc-{}", i),
+                    "updatedAt": "2024-03-05T10:15:30.123Z"
+                }))
+                .collect();
+            server
+                .mock("POST", "/documents.list")
+                .match_body(mockito::Matcher::PartialJson(serde_json::json!({ "offset": offset })))
+                .with_body(serde_json::json!({ "data": data }).to_string())
+                .create_async()
+                .await;
+        }
+
+        let client = OutlineClient::new(server.url(), "test_key".to_string()).unwrap();
+        let mut pages = Vec::new();
+        let documents = client.list_all_documents(|found| pages.push(found)).await.unwrap();
+
+        assert_eq!(documents.len(), 230);
+        assert_eq!(pages, vec![100, 200, 230]);
+    }
+
+    #[test]
+    fn test_parse_outline_timestamps() {
+        let utc = parse_timestamp("2024-03-05T10:15:30.123Z").unwrap();
+        assert_eq!(utc.timestamp(), 1_709_633_730);
+        assert_eq!(utc.timestamp_subsec_millis(), 123);
+
+        // The same instant written with an offset
+        let offset = parse_timestamp("2024-03-05T12:15:30.123+02:00").unwrap();
+        assert_eq!(offset, utc);
+        assert_eq!(parse_timestamp("2024-03-05T05:15:30-05:00").unwrap().timestamp(), 1_709_633_730);
+
+        assert!(matches!(parse_timestamp("yesterday"), Err(OutlineError::Timestamp(_))));
+        assert!(matches!(parse_timestamp("2024-03-05"), Err(OutlineError::Timestamp(_))));
+    }
+
+    #[test]
+    fn test_sort_and_filter_by_update_time() {
+        let mut documents = vec![
+            document("old", "2023-01-01T00:00:00Z"),
+            document("broken", "not a date"),
+            document("new", "2024-06-01T08:00:00+02:00"),
+        ];
+        sort_by_recency(&mut documents);
+        let ids: Vec<&str> = documents.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["new", "old", "broken"]);
+
+        let since = parse_timestamp("2024-01-01T00:00:00Z").unwrap();
+        let recent: Vec<&str> = documents
+            .iter()
+            .filter(|d| d.updated().is_ok_and(|updated| updated >= since))
+            .map(|d| d.id.as_str())
+            .collect();
+        assert_eq!(recent, vec!["new"]);
+
+        let mut archived = document("gone", "2024-01-01T00:00:00Z");
+        archived.archived_at = Some("2024-02-01T00:00:00.000Z".to_string());
+        assert_eq!(archived.archived().unwrap().map(|t| t.timestamp()), Some(1_706_745_600));
+    }
+
+    #[test]
+    fn test_collection_id_is_parsed_and_stored() {
+        use crate::db::{Artifact, Database};
+
+        let response: OutlineDocumentResponse = serde_json::from_str(r#"{
+            "data": {
+                "id": "doc-1",
+                "title": "Runbook",
+                "urlId": "runbook-abc",
+                "text": "Restart the service",
+                "updatedAt": "2024-03-05T10:15:30.123Z",
+                "collectionId": "col-eng"
+            }
+        }"#).unwrap();
+        let doc = response.data;
+        assert_eq!(doc.collection_id.as_deref(), Some("col-eng"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().to_path_buf()).unwrap();
+        db.upsert_artifact(&Artifact {
+            id: doc.id.clone(),
+            path: format!("outline://{}", doc.id),
+            collection_id: doc.collection_id.clone(),
+            collection: Some("Engineering".to_string()),
+            ..Default::default()
+        }).unwrap();
+
+        let stored = db.get_artifact_by_path("outline://doc-1").unwrap().unwrap();
+        assert_eq!(stored.collection_id.as_deref(), Some("col-eng"));
+        assert_eq!(stored.collection.as_deref(), Some("Engineering"));
+    }
+}
@@ -1,9 +1,14 @@
+use crate::db::Settings;
 use pulldown_cmark::{Event, Parser, Tag, TagEnd};
 use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Digest};
+use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
+use std::sync::OnceLock;
 use thiserror::Error;
+use tiktoken_rs::CoreBPE;
+use unicode_normalization::UnicodeNormalization;
 
 #[derive(Error, Debug)]
 pub enum ParseError {
@@ -11,6 +16,8 @@ pub enum ParseError {
     Io(#[from] std::io::Error),
     #[error("YAML parsing error: {0}")]
     Yaml(#[from] serde_yaml::Error),
+    #[error("JSON parsing error: {0}")]
+    Json(#[from] serde_json::Error),
     #[error("Invalid markdown")]
     InvalidMarkdown,
 }
@@ -22,6 +29,9 @@ pub struct Frontmatter {
     pub title: Option<String>,
     pub tags: Vec<String>,
     pub aliases: Vec<String>,
+    /// Opted out of indexing with `noindex: true` or `metabrain: false`
+    #[serde(default)]
+    pub noindex: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -32,22 +42,160 @@ pub struct ParsedDocument {
     pub content_hash: String,
 }
 
+impl ParsedDocument {
+    /// Number of words in the plain-text content
+    pub fn word_count(&self) -> usize {
+        self.content.split_whitespace().count()
+    }
+}
+
 const CHUNK_SIZE: usize = 500;  // Target chunk size in words
 const CHUNK_OVERLAP: usize = 50;  // Overlap between chunks in words
+const OVERLAP_SNAP_TOLERANCE: usize = 25;  // Max words the overlap may shift to hit a sentence start
+const MAX_CHUNK_CHARS: usize = 4000;  // Hard cap so a chunk stays inside the embedding model's context
+const TOKEN_OVERLAP_DIVISOR: usize = 10;  // Token chunks overlap by a tenth of their size
+
+/// How text is split into chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkStrategy {
+    /// Fixed word counts, with overlaps snapped to sentences
+    Words,
+    /// At most `max_tokens` cl100k tokens, the budget models actually count
+    Tokens { max_tokens: usize },
+}
+
+impl ChunkStrategy {
+    /// `words` or `tokens`, falling back to words
+    pub fn parse(name: &str, max_tokens: usize) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "tokens" => ChunkStrategy::Tokens { max_tokens: max_tokens.max(1) },
+            _ => ChunkStrategy::Words,
+        }
+    }
+}
+
+/// The cl100k tokenizer, loaded on first use; `None` if it failed to load
+fn tokenizer() -> Option<&'static CoreBPE> {
+    static TOKENIZER: OnceLock<Option<CoreBPE>> = OnceLock::new();
+    TOKENIZER
+        .get_or_init(|| match tiktoken_rs::cl100k_base() {
+            Ok(bpe) => Some(bpe),
+            Err(e) => {
+                log::warn!("Cannot load tokenizer, chunking by words: {}", e);
+                None
+            }
+        })
+        .as_ref()
+}
+
+/// Options controlling how documents are parsed and chunked
+#[derive(Debug, Clone)]
+pub struct ParserOptions {
+    pub chunk_strategy: ChunkStrategy,
+    /// Snap the start of each overlap to the nearest sentence start
+    pub snap_overlap: bool,
+    /// Append link URLs after their text so notes are searchable by URL
+    pub include_link_urls: bool,
+    /// Append image sources after their alt text
+    pub include_image_sources: bool,
+    /// Lowercase frontmatter tags so `Work` and `work` are one tag
+    pub lowercase_tags: bool,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            chunk_strategy: ChunkStrategy::Words,
+            snap_overlap: true,
+            include_link_urls: false,
+            include_image_sources: false,
+            lowercase_tags: false,
+        }
+    }
+}
+
+impl ParserOptions {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            chunk_strategy: ChunkStrategy::parse(&settings.chunk_strategy, settings.chunk_max_tokens),
+            snap_overlap: settings.snap_chunk_overlap,
+            include_link_urls: settings.include_link_urls,
+            include_image_sources: settings.include_image_sources,
+            lowercase_tags: settings.lowercase_tags,
+        }
+    }
+}
 
-pub struct MarkdownParser;
+pub struct MarkdownParser {
+    options: ParserOptions,
+}
 
 impl MarkdownParser {
     pub fn new() -> Self {
-        Self
+        Self::with_options(ParserOptions::default())
+    }
+
+    pub fn with_options(options: ParserOptions) -> Self {
+        Self { options }
     }
 
     pub fn parse_file(&self, path: &Path) -> ParseResult<ParsedDocument> {
         let content = fs::read_to_string(path)?;
+        if is_canvas_path(path) {
+            return self.parse_canvas(&content);
+        }
         self.parse_content(&content)
     }
 
+    /// Parse an Obsidian Canvas (JSON) file into a document built from its nodes.
+    /// Text nodes contribute their markdown, file and link cards their target.
+    pub fn parse_canvas(&self, content: &str) -> ParseResult<ParsedDocument> {
+        let content = &normalize_text(content);
+        #[derive(Deserialize)]
+        struct Canvas {
+            #[serde(default)]
+            nodes: Vec<CanvasNode>,
+        }
+
+        #[derive(Deserialize)]
+        struct CanvasNode {
+            #[serde(rename = "type", default)]
+            node_type: String,
+            text: Option<String>,
+            file: Option<String>,
+            url: Option<String>,
+            label: Option<String>,
+        }
+
+        let canvas: Canvas = serde_json::from_str(content)?;
+
+        let parts: Vec<String> = canvas
+            .nodes
+            .into_iter()
+            .filter_map(|node| match node.node_type.as_str() {
+                "text" => node.text.map(|t| self.markdown_to_plain_text(&t)),
+                "file" => node.file,
+                "link" => node.url,
+                "group" => node.label,
+                _ => None,
+            })
+            .filter(|part| !part.trim().is_empty())
+            .collect();
+
+        let plain_text = parts.join("\n\n");
+        let chunks = self.chunk_text(&plain_text);
+        let content_hash = self.compute_hash(content);
+
+        Ok(ParsedDocument {
+            frontmatter: Frontmatter::default(),
+            content: plain_text,
+            chunks,
+            content_hash,
+        })
+    }
+
     pub fn parse_content(&self, content: &str) -> ParseResult<ParsedDocument> {
+        let content = &normalize_text(content);
         let (frontmatter, body) = self.extract_frontmatter(content)?;
         let plain_text = self.markdown_to_plain_text(&body);
         let chunks = self.chunk_text(&plain_text);
@@ -89,6 +237,9 @@ impl MarkdownParser {
             tags: TagsField,
             #[serde(default)]
             aliases: AliasesField,
+            // Loosely typed so a malformed flag doesn't discard the title and tags
+            noindex: Option<serde_yaml::Value>,
+            metabrain: Option<serde_yaml::Value>,
         }
 
         #[derive(Deserialize, Default)]
@@ -109,17 +260,21 @@ impl MarkdownParser {
             List(Vec<String>),
         }
 
-        let raw: RawFrontmatter = serde_yaml::from_str(yaml).unwrap_or(RawFrontmatter {
+        let yaml = quote_hashtag_values(yaml);
+        let raw: RawFrontmatter = serde_yaml::from_str(&yaml).unwrap_or(RawFrontmatter {
             title: None,
             tags: TagsField::None,
             aliases: AliasesField::None,
+            noindex: None,
+            metabrain: None,
         });
 
         let tags = match raw.tags {
             TagsField::None => vec![],
-            TagsField::Single(s) => s.split(',').map(|s| s.trim().to_string()).collect(),
+            TagsField::Single(s) => vec![s],
             TagsField::List(v) => v,
         };
+        let tags = normalize_tags(tags, self.options.lowercase_tags);
 
         let aliases = match raw.aliases {
             AliasesField::None => vec![],
@@ -127,10 +282,18 @@ impl MarkdownParser {
             AliasesField::List(v) => v,
         };
 
+        let flag = |value: Option<serde_yaml::Value>| match value {
+            Some(serde_yaml::Value::Bool(b)) => Some(b),
+            Some(serde_yaml::Value::String(s)) => s.trim().to_lowercase().parse().ok(),
+            _ => None,
+        };
+        let noindex = flag(raw.noindex) == Some(true) || flag(raw.metabrain) == Some(false);
+
         Ok(Frontmatter {
             title: raw.title,
             tags,
             aliases,
+            noindex,
         })
     }
 
@@ -138,9 +301,23 @@ impl MarkdownParser {
         let parser = Parser::new(markdown);
         let mut text = String::new();
         let mut in_code_block = false;
+        // Destinations to append when the enclosing link/image closes
+        let mut destinations: Vec<Option<String>> = Vec::new();
 
         for event in parser {
             match event {
+                Event::Start(Tag::Link { dest_url, .. }) => {
+                    destinations.push(self.options.include_link_urls.then(|| dest_url.to_string()));
+                }
+                Event::Start(Tag::Image { dest_url, .. }) => {
+                    destinations.push(self.options.include_image_sources.then(|| dest_url.to_string()));
+                }
+                Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => {
+                    if let Some(Some(url)) = destinations.pop() {
+                        text.push_str(&url);
+                        text.push(' ');
+                    }
+                }
                 Event::Text(t) => {
                     if !in_code_block {
                         text.push_str(&t);
@@ -177,10 +354,17 @@ impl MarkdownParser {
     }
 
     fn chunk_text(&self, text: &str) -> Vec<String> {
+        match (self.options.chunk_strategy, tokenizer()) {
+            (ChunkStrategy::Tokens { max_tokens }, Some(bpe)) => chunk_by_tokens(bpe, text, max_tokens),
+            _ => self.chunk_by_words(text),
+        }
+    }
+
+    fn chunk_by_words(&self, text: &str) -> Vec<String> {
         let words: Vec<&str> = text.split_whitespace().collect();
         
         if words.len() <= CHUNK_SIZE {
-            return vec![text.to_string()];
+            return split_oversized(text, MAX_CHUNK_CHARS);
         }
 
         let mut chunks = Vec::new();
@@ -189,11 +373,11 @@ impl MarkdownParser {
         while start < words.len() {
             let end = (start + CHUNK_SIZE).min(words.len());
             let chunk: String = words[start..end].join(" ");
-            chunks.push(chunk);
+            chunks.extend(split_oversized(&chunk, MAX_CHUNK_CHARS));
 
             // Move start forward, accounting for overlap
             start = if end < words.len() {
-                end - CHUNK_OVERLAP
+                self.overlap_start(&words, start, end)
             } else {
                 end
             };
@@ -202,12 +386,157 @@ impl MarkdownParser {
         chunks
     }
 
+    /// Index where the next chunk starts: CHUNK_OVERLAP words before `end`,
+    /// moved to the nearest sentence start within tolerance when snapping is on
+    fn overlap_start(&self, words: &[&str], start: usize, end: usize) -> usize {
+        let target = end - CHUNK_OVERLAP;
+        if !self.options.snap_overlap {
+            return target;
+        }
+
+        let lo = target.saturating_sub(OVERLAP_SNAP_TOLERANCE).max(start + 1);
+        let hi = (target + OVERLAP_SNAP_TOLERANCE).min(end - 1);
+
+        (lo..=hi)
+            .filter(|&i| is_sentence_end(words[i - 1]))
+            .min_by_key(|&i| i.abs_diff(target))
+            .unwrap_or(target)
+    }
+
     fn compute_hash(&self, content: &str) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(content.as_bytes());
-        let result = hasher.finalize();
-        hex::encode(result)
+        content_hash(content)
+    }
+}
+
+/// Text in Unicode NFC form, so decomposed accents (as macOS often saves
+/// them) hash, embed and match the same as precomposed ones
+pub fn normalize_text(text: &str) -> String {
+    text.nfc().collect()
+}
+
+/// SHA-256 hex digest identifying a note's or chunk's text
+pub fn content_hash(content: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content.as_bytes());
+    let result = hasher.finalize();
+    hex::encode(result)
+}
+
+fn is_sentence_end(word: &str) -> bool {
+    word.trim_end_matches(['"', '\'', ')', ']'])
+        .ends_with(['.', '!', '?'])
+}
+
+/// A local link target in a note, by how it is resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocalLink {
+    /// A markdown link or image, relative to the note's folder
+    Relative(String),
+    /// A `[[wiki]]` link or `![[embed]]`, relative to the vault root
+    Wiki(String),
+}
+
+/// Local link targets in a note: markdown links and images plus `[[wiki]]`
+/// links and `![[embeds]]`, without anchors or aliases. URLs are skipped.
+pub fn local_links(markdown: &str) -> Vec<LocalLink> {
+    let mut links: Vec<(String, bool)> = Vec::new();
+
+    for event in Parser::new(markdown) {
+        if let Event::Start(Tag::Link { dest_url, .. }) | Event::Start(Tag::Image { dest_url, .. }) = event {
+            links.push((dest_url.to_string(), false));
+        }
+    }
+
+    // Wiki links aren't CommonMark; they may also appear in frontmatter
+    let mut rest = markdown;
+    while let Some(start) = rest.find("[[") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("]]") else { break };
+        links.push((after[..end].split('|').next().unwrap_or_default().to_string(), true));
+        rest = &after[end + 2..];
+    }
+
+    let mut targets: Vec<LocalLink> = Vec::new();
+    for (link, wiki) in links {
+        let target = link.split('#').next().unwrap_or_default().replace("%20", " ");
+        let target = target.trim();
+        if target.is_empty() || target.contains("://") || target.starts_with("mailto:") {
+            continue;
+        }
+        let target = if wiki {
+            LocalLink::Wiki(target.to_string())
+        } else {
+            LocalLink::Relative(target.to_string())
+        };
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    targets
+}
+
+/// Split text into windows of at most `max_tokens` tokens overlapping by a
+/// tenth. A window ending inside a multi-byte character is shortened until
+/// it decodes.
+fn chunk_by_tokens(bpe: &CoreBPE, text: &str, max_tokens: usize) -> Vec<String> {
+    let tokens = bpe.encode_ordinary(text);
+    let overlap = max_tokens / TOKEN_OVERLAP_DIVISOR;
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    while start < tokens.len() {
+        let mut end = (start + max_tokens).min(tokens.len());
+        let decoded = loop {
+            match bpe.decode(tokens[start..end].to_vec()) {
+                Ok(chunk) => break Some(chunk),
+                Err(_) if end > start + 1 => end -= 1,
+                // Starts inside a character; the next window picks it up
+                Err(_) => break None,
+            }
+        };
+        if let Some(chunk) = decoded.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+            chunks.push(chunk.to_string());
+        }
+
+        if end == tokens.len() {
+            break;
+        }
+        start = (end - overlap).max(start + 1);
+    }
+
+    if chunks.is_empty() {
+        chunks.push(text.trim().to_string());
     }
+    chunks
+}
+
+/// Split text longer than `max_chars` characters, preferring whitespace but
+/// cutting mid-word when a piece has none
+fn split_oversized(text: &str, max_chars: usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut rest = text.trim();
+
+    while rest.chars().count() > max_chars {
+        let limit = rest.char_indices().nth(max_chars).map(|(i, _)| i).unwrap_or(rest.len());
+        let cut = match rest[..limit].rfind(char::is_whitespace) {
+            Some(i) if i > 0 => i,
+            _ => limit,
+        };
+        pieces.push(rest[..cut].trim_end().to_string());
+        rest = rest[cut..].trim_start();
+    }
+
+    if !rest.is_empty() || pieces.is_empty() {
+        pieces.push(rest.to_string());
+    }
+    pieces
+}
+/// Whether the path points to an Obsidian Canvas file
+pub fn is_canvas_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("canvas"))
+        .unwrap_or(false)
 }
 
 // Add hex encoding dependency alternative
@@ -217,6 +546,41 @@ mod hex {
     }
 }
 
+/// Quote plain `tags:` values containing `#` and list items starting with
+/// one, which YAML would otherwise read as comments (`tags: #work #idea`)
+fn quote_hashtag_values(yaml: &str) -> String {
+    yaml.lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            let value = match (trimmed.strip_prefix("- "), trimmed.strip_prefix("tags:")) {
+                (Some(item), _) if item.trim_start().starts_with('#') => item.trim_start(),
+                (_, Some(tags)) if tags.contains('#') => tags.trim_start(),
+                _ => return line.to_string(),
+            };
+            // Flow lists and quoted strings are already safe
+            if value.starts_with(['[', '\'', '"']) {
+                return line.to_string();
+            }
+            let start = line.len() - value.len();
+            format!("{}'{}'", &line[..start], value.trim_end().replace('\'', "''"))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Flatten every frontmatter tag format (comma or space separated strings,
+/// lists, `#hashtags`) into clean, deduplicated tag names
+fn normalize_tags(raw: Vec<String>, lowercase: bool) -> Vec<String> {
+    let mut seen = HashSet::new();
+    raw.iter()
+        .flat_map(|tags| tags.split(|c: char| c == ',' || c.is_whitespace()))
+        .map(|tag| tag.trim().trim_start_matches('#'))
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| if lowercase { tag.to_lowercase() } else { tag.to_string() })
+        .filter(|tag| seen.insert(tag.clone()))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -238,6 +602,34 @@ This is a test document."#;
         assert_eq!(result.frontmatter.tags, vec!["tag1", "tag2"]);
     }
 
+    #[test]
+    fn test_inline_hashtag_tags() {
+        let parser = MarkdownParser::new();
+        let result = parser.parse_content("---\ntitle: Plan\ntags: #work #idea\n---\nBody").unwrap();
+        assert_eq!(result.frontmatter.title.as_deref(), Some("Plan"));
+        assert_eq!(result.frontmatter.tags, vec!["work", "idea"]);
+    }
+
+    #[test]
+    fn test_block_list_hashtag_tags() {
+        let parser = MarkdownParser::new();
+        let content = "---\ntags:\n  - #work\n  - idea\n  - \"#work\"\naliases:\n  - Plan\n---\nBody";
+        let result = parser.parse_content(content).unwrap();
+        assert_eq!(result.frontmatter.tags, vec!["work", "idea"]);
+        assert_eq!(result.frontmatter.aliases, vec!["Plan"]);
+    }
+
+    #[test]
+    fn test_mixed_tag_formats_are_normalized() {
+        let parser = MarkdownParser::new();
+        let result = parser.parse_content("---\ntags: [\"#Work\", \"idea, later\", work]\n---\nBody").unwrap();
+        assert_eq!(result.frontmatter.tags, vec!["Work", "idea", "later", "work"]);
+
+        let parser = MarkdownParser::with_options(ParserOptions { lowercase_tags: true, ..ParserOptions::default() });
+        let result = parser.parse_content("---\ntags: Work, #work, idea\n---\nBody").unwrap();
+        assert_eq!(result.frontmatter.tags, vec!["work", "idea"]);
+    }
+
     #[test]
     fn test_chunking() {
         let parser = MarkdownParser::new();
@@ -247,5 +639,144 @@ This is a test document."#;
         let chunks = parser.chunk_text(&text);
         assert!(chunks.len() > 1);
     }
-}
 
+    #[test]
+    fn test_token_chunks_stay_within_token_cap() {
+        let parser = MarkdownParser::with_options(ParserOptions {
+            chunk_strategy: ChunkStrategy::parse("tokens", 64),
+            ..ParserOptions::default()
+        });
+        let text = (0..150)
+            .map(|i| format!("Zeile {} über Größenordnungen: fn parse_{}(x: &str) -> Option<u32>;", i, i))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let chunks = parser.chunk_text(&text);
+
+        let bpe = tokenizer().unwrap();
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(bpe.encode_ordinary(chunk).len() <= 64, "{} tokens", bpe.encode_ordinary(chunk).len());
+        }
+        assert!(chunks[0].starts_with("Zeile 0"));
+        assert!(chunks.last().unwrap().ends_with("parse_149(x: &str) -> Option<u32>;"));
+    }
+
+    #[test]
+    fn test_overlap_snaps_to_sentence_start() {
+        let parser = MarkdownParser::new();
+        let text = (0..200)
+            .map(|i| format!("Sentence{} has a handful of filler words here.", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let chunks = parser.chunk_text(&text);
+        assert!(chunks.len() > 1);
+        for chunk in &chunks[1..] {
+            assert!(chunk.starts_with("Sentence"), "chunk starts mid-sentence: {}", &chunk[..40]);
+        }
+
+        let unsnapped = MarkdownParser::with_options(ParserOptions {
+            snap_overlap: false,
+            ..ParserOptions::default()
+        });
+        let chunks = unsnapped.chunk_text(&text);
+        assert!(chunks[1..].iter().any(|c| !c.starts_with("Sentence")));
+    }
+
+    #[test]
+    fn test_parse_canvas() {
+        let parser = MarkdownParser::new();
+        let canvas = r##"{
+            "nodes": [
+                {"id": "1", "type": "text", "text": "# Ideas\n\nShip the **beta** in March", "x": 0, "y": 0},
+                {"id": "2", "type": "file", "file": "Projects/Roadmap.md", "x": 10, "y": 0},
+                {"id": "3", "type": "text", "text": "Hire a designer", "x": 20, "y": 0}
+            ],
+            "edges": [{"id": "e1", "fromNode": "1", "toNode": "3"}]
+        }"##;
+
+        let result = parser.parse_canvas(canvas).unwrap();
+        assert!(result.content.contains("Ship the beta in March"));
+        assert!(result.content.contains("Projects/Roadmap.md"));
+        assert!(result.content.contains("Hire a designer"));
+        assert_eq!(result.chunks.len(), 1);
+        assert!(result.chunks[0].contains("Hire a designer"));
+    }
+
+    #[test]
+    fn test_parse_canvas_malformed() {
+        let parser = MarkdownParser::new();
+        assert!(matches!(parser.parse_canvas("{\"nodes\": [}"), Err(ParseError::Json(_))));
+    }
+
+    #[test]
+    fn test_chunks_respect_hard_length_cap() {
+        let parser = MarkdownParser::new();
+        let blob = "incomprehensibilities ".repeat(10_000);
+        let chunks = parser.chunk_text(&blob);
+
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.chars().count() <= MAX_CHUNK_CHARS));
+
+        // A single whitespace-free token is still cut
+        let token = "x".repeat(MAX_CHUNK_CHARS * 2 + 10);
+        let chunks = parser.chunk_text(&token);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|c| c.chars().count() <= MAX_CHUNK_CHARS));
+        assert_eq!(chunks.concat(), token);
+    }
+
+    #[test]
+    fn test_link_urls_included_when_enabled() {
+        let markdown = "See the [release notes](https://example.com/releases) and [docs][ref].\n\n\
+                        ![diagram](images/arch.png)\n\n[ref]: https://docs.example.com";
+
+        let default = MarkdownParser::new().parse_content(markdown).unwrap();
+        assert!(!default.content.contains("https://example.com/releases"));
+        assert!(default.content.contains("release notes"));
+
+        let parser = MarkdownParser::with_options(ParserOptions {
+            include_link_urls: true,
+            include_image_sources: true,
+            ..ParserOptions::default()
+        });
+        let parsed = parser.parse_content(markdown).unwrap();
+        assert!(parsed.content.contains("release notes https://example.com/releases"));
+        assert!(parsed.content.contains("docs https://docs.example.com"));
+        assert!(parsed.content.contains("diagram images/arch.png"));
+    }
+
+    #[test]
+    fn test_local_links() {
+        let markdown = "---\nsource: \"[[raw/interview.txt]]\"\n---\n\
+                        See [facts](data/facts%20v2.txt#top), [site](https://example.com), \
+                        ![[diagram.png]] and [[notes/plan|the plan]].";
+
+        assert_eq!(
+            local_links(markdown),
+            vec![
+                LocalLink::Relative("data/facts v2.txt".to_string()),
+                LocalLink::Wiki("raw/interview.txt".to_string()),
+                LocalLink::Wiki("diagram.png".to_string()),
+                LocalLink::Wiki("notes/plan".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nfd_and_nfc_text_hash_and_match_alike() {
+        let nfc = "Café crème brûlée";
+        let nfd = "Cafe\u{301} cre\u{300}me bru\u{302}le\u{301}e";
+        assert_ne!(nfc, nfd);
+
+        let parser = MarkdownParser::new();
+        let (a, b) = (parser.parse_content(nfc).unwrap(), parser.parse_content(nfd).unwrap());
+        assert_eq!(a.content_hash, b.content_hash);
+        assert_eq!(a.chunks, b.chunks);
+
+        // A precomposed query finds the decomposed chunk text
+        assert_eq!(crate::fuzzy::keyword_score("crème brûlée", &b.chunks[0]), 1.0);
+        assert_eq!(crate::fuzzy::shared_terms("café", nfd), vec!["café"]);
+    }
+}
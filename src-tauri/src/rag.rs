@@ -1,14 +1,20 @@
-use crate::db::{ChatMessage, Database};
+use crate::db::{Artifact, ChatMessage, Database, Embedding, Settings, SourceCitation};
 use crate::embedding::EmbeddingClient;
-use crate::llm::{create_provider, LLMProvider};
-use crate::vector::{SearchResult, VectorStore};
-use std::collections::HashSet;
+use crate::fuzzy::{keyword_score, shared_terms};
+use crate::language::ResponseLanguage;
+use crate::llm::{create_provider, GenerationOptions, LLMProvider, StreamCallback};
+use crate::vector::{disabled_sources, group_hits_by_note, sort_results, NoteHit, SearchHit, SearchResult, SimilarityMetric, VectorStore};
+use futures::future::try_join_all;
+use std::collections::{HashMap, HashSet};
+use serde::Serialize;
 use std::sync::Arc;
-use tauri::Manager;
+use std::time::Instant;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum RagError {
+    #[error("Database error: {0}")]
+    Database(#[from] crate::db::DbError),
     #[error("Embedding error: {0}")]
     Embedding(#[from] crate::embedding::EmbeddingError),
     #[error("Vector error: {0}")]
@@ -29,6 +35,14 @@ When citing information, mention which note it comes from if possible.
 
 Be concise but thorough in your answers."#;
 
+const STRICT_SYSTEM_PROMPT: &str = r#"You are Metabrain, an assistant that answers ONLY from the user's personal knowledge base.
+
+Answer strictly from the provided context. Do not use general knowledge, and do not guess. If the context does not contain the answer, reply that your notes don't cover it.
+
+Cite the note each statement comes from.
+
+Be concise."#;
+
 const QUERY_EXPANSION_PROMPT: &str = r#"Given the following conversation and the latest user query, generate 2-3 alternative search queries that would help find relevant information in a knowledge base. The queries should:
 1. Capture the core intent of the question
 2. Include relevant synonyms or related terms
@@ -43,97 +57,569 @@ Latest Query: {query}
 
 Alternative search queries:"#;
 
+const SUMMARY_PROMPT: &str = r#"Summarize the following note from the user's knowledge base. Capture its main points, decisions and open questions in a few short paragraphs or bullet points. Use only what the note says.
+
+Note: {title}
+
+{content}
+
+Summary:"#;
+
+const PARTIAL_SUMMARY_PROMPT: &str = r#"The following is one part of a longer note titled "{title}". Summarize the key points of this part in a few bullet points, using only what it says.
+
+{content}
+
+Key points:"#;
+
+/// Notes longer than this many characters are summarized part by part first
+const MAX_SUMMARY_CONTEXT_CHARS: usize = 12_000;
+
+const EMPTY_KNOWLEDGE_BASE_MESSAGE: &str =
+    "Your knowledge base is empty — sync your vault first, then ask again.";
+
 const MAX_CONTEXT_CHUNKS: usize = 5;
-const MIN_SIMILARITY_THRESHOLD: f32 = 0.25;
 const MAX_CHAT_HISTORY: usize = 10;
+/// Chunks fetched per requested note in a grouped search, so notes with
+/// several matching chunks are still filled out to the limit
+const GROUPED_SEARCH_CHUNKS_PER_NOTE: usize = 5;
+/// In guardrail mode, answers backed by fewer chunks than this are flagged
+const MIN_GROUNDING_CHUNKS: usize = 2;
+/// Rough size of a token, for fitting context into a model's window
+const CHARS_PER_TOKEN: usize = 4;
+/// Share of the context window retrieved chunks may fill; the rest is left
+/// for instructions, history and the answer
+const CONTEXT_WINDOW_SHARE: f32 = 0.5;
+
+/// Answer length and format requested for one message, on top of the
+/// system prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnswerStyle {
+    Concise,
+    Detailed,
+    Bullet,
+}
+
+impl AnswerStyle {
+    /// `concise`, `detailed` or `bullet`; `None` for anything else
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "concise" => Some(AnswerStyle::Concise),
+            "detailed" => Some(AnswerStyle::Detailed),
+            "bullet" => Some(AnswerStyle::Bullet),
+            _ => None,
+        }
+    }
+
+    /// Instruction appended to the system prompt
+    fn instruction(self) -> &'static str {
+        match self {
+            AnswerStyle::Concise => "Answer concisely, in one to three sentences.",
+            AnswerStyle::Detailed => {
+                "Give a detailed answer, explaining the reasoning and the relevant details from the notes."
+            }
+            AnswerStyle::Bullet => "Format the answer as a bulleted list of short points.",
+        }
+    }
+}
+
+/// A chat prompt with its system instructions kept apart from the context
+/// and question, for providers that take a separate system prompt
+struct Prompt {
+    system: String,
+    body: String,
+}
+
+impl Prompt {
+    /// Both parts as one text, as sent to providers without a system role
+    fn full_text(&self) -> String {
+        format!("{}\n\n{}", self.system, self.body)
+    }
+}
+
+/// Retrieval filters given inline in a query, e.g. `tag:work path:projects/`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryFilters {
+    pub tags: Vec<String>,
+    pub paths: Vec<String>,
+    pub titles: Vec<String>,
+    /// Outline collection names or ids
+    pub collections: Vec<String>,
+}
+
+impl QueryFilters {
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.paths.is_empty() && self.titles.is_empty() && self.collections.is_empty()
+    }
+
+    /// Whether the artifact satisfies every filter (case-insensitive)
+    pub fn matches(&self, artifact: &Artifact) -> bool {
+        let path = artifact.path.to_lowercase();
+        let title = artifact.title.as_deref().unwrap_or_default().to_lowercase();
+        let collection = artifact.collection.as_deref().unwrap_or_default().to_lowercase();
+        let collection_id = artifact.collection_id.as_deref().unwrap_or_default().to_lowercase();
+
+        self.tags.iter().all(|tag| {
+            artifact.tags.iter().any(|t| t.trim_start_matches('#').eq_ignore_ascii_case(tag))
+        }) && self.paths.iter().all(|p| path.contains(p))
+            && self.titles.iter().all(|t| title.contains(t))
+            && self.collections.iter().all(|c| collection.contains(c) || collection_id == *c)
+    }
+}
+
+/// Split `tag:`, `path:`, `title:` and `collection:` tokens out of a query,
+/// returning the filters and the remaining question
+pub fn parse_query_filters(query: &str) -> (QueryFilters, String) {
+    let mut filters = QueryFilters::default();
+    let mut words = Vec::new();
+
+    for token in query.split_whitespace() {
+        let Some((key, value)) = token.split_once(':') else {
+            words.push(token);
+            continue;
+        };
+
+        let value = value.to_lowercase();
+        match key.to_lowercase().as_str() {
+            "tag" if !value.is_empty() => filters.tags.push(value.trim_start_matches('#').to_string()),
+            "path" if !value.is_empty() => filters.paths.push(value),
+            "title" if !value.is_empty() => filters.titles.push(value),
+            "collection" if !value.is_empty() => filters.collections.push(value),
+            _ => words.push(token),
+        }
+    }
+
+    (filters, words.join(" "))
+}
+
+/// Join consecutive chunks into groups of at most `max_chars` (a single
+/// larger chunk forms its own group)
+fn group_chunks(chunks: &[Embedding], max_chars: usize) -> Vec<String> {
+    let mut groups = Vec::new();
+    let mut current = String::new();
+
+    for chunk in chunks {
+        if !current.is_empty() && current.len() + chunk.content.len() + 2 > max_chars {
+            groups.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(&chunk.content);
+    }
+    if !current.is_empty() {
+        groups.push(current);
+    }
+
+    groups
+}
+
+/// Score multipliers for notes matching a tag or a vault path prefix
+#[derive(Debug, Clone, Default)]
+pub struct RetrievalBoosts {
+    vault_paths: Vec<String>,
+    tags: Vec<(String, f32)>,
+    paths: Vec<(String, f32)>,
+}
+
+impl RetrievalBoosts {
+    /// Keys starting with `#` name a tag, anything else is a path prefix
+    /// relative to a vault. Boosts of 1.0 are dropped.
+    pub fn from_settings(settings: &Settings) -> Self {
+        let mut boosts = Self {
+            vault_paths: settings.vault_paths.clone(),
+            ..Self::default()
+        };
+        for (key, &multiplier) in &settings.retrieval_boosts {
+            if multiplier == 1.0 || !multiplier.is_finite() || multiplier < 0.0 {
+                continue;
+            }
+            match key.strip_prefix('#') {
+                Some(tag) => boosts.tags.push((tag.to_lowercase(), multiplier)),
+                None => boosts.paths.push((key.trim_start_matches('/').to_lowercase(), multiplier)),
+            }
+        }
+        boosts
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.paths.is_empty()
+    }
+
+    /// Combined multiplier for every boost the artifact matches
+    pub fn factor(&self, artifact: &Artifact) -> f32 {
+        let path = self
+            .vault_paths
+            .iter()
+            .filter(|vault| !vault.is_empty())
+            .find_map(|vault| artifact.path.strip_prefix(vault.as_str()))
+            .unwrap_or(&artifact.path)
+            .trim_start_matches(['/', '\\'])
+            .to_lowercase();
+
+        let tag_factor: f32 = self
+            .tags
+            .iter()
+            .filter(|(tag, _)| artifact.tags.iter().any(|t| t.trim_start_matches('#').eq_ignore_ascii_case(tag)))
+            .map(|(_, m)| m)
+            .product();
+        let path_factor: f32 = self
+            .paths
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .map(|(_, m)| m)
+            .product();
+
+        tag_factor * path_factor
+    }
+
+    /// Scale result scores by their note's boost and re-sort
+    pub fn apply(
+        &self,
+        results: &mut [SearchResult],
+        artifacts: &HashMap<String, Artifact>,
+        metric: SimilarityMetric,
+    ) {
+        for result in results.iter_mut() {
+            if let Some(artifact) = artifacts.get(&result.embedding.artifact_id) {
+                result.similarity = metric.boost(result.similarity, self.factor(artifact));
+            }
+        }
+        sort_results(results, metric);
+    }
+}
+
+/// Timing for one warmup step
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WarmupStep {
+    pub name: String,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WarmupReport {
+    pub steps: Vec<WarmupStep>,
+    pub total_ms: u64,
+}
+
+/// How well the indexed notes support one expected question
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryCoverage {
+    pub query: String,
+    /// Score of the best matching chunk (a distance for Euclidean), if any
+    pub top_score: Option<f32>,
+    pub best_match: Option<SearchHit>,
+    /// Whether the best match clears the similarity threshold
+    pub covered: bool,
+}
+
+/// Forwards a named event and its JSON payload to the frontend
+pub type EventSink = Arc<dyn Fn(&str, serde_json::Value) + Send + Sync>;
+
+/// The outcome of a chat turn, returned once its answer has finished streaming
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatReply {
+    /// ID of the saved assistant message
+    pub message_id: i64,
+    pub content: String,
+    pub sources: Vec<SearchHit>,
+}
 
 pub struct RagEngine {
+    db: Arc<Database>,
     vector_store: VectorStore,
     embedding_client: EmbeddingClient,
     llm_provider: Box<dyn LLMProvider>,
+    boosts: RetrievalBoosts,
+    metric: SimilarityMetric,
+    similarity_threshold: f32,
+    /// Results of each expanded query must pass this before merging
+    subquery_floor: f32,
+    near_duplicate_threshold: f32,
+    /// Preferred-first source prefixes when collapsing identical notes, `None` when disabled
+    source_priority: Option<Vec<String>>,
+    grounded_only: bool,
+    track_retrieval: bool,
+    /// Minimum keyword score for the typo-tolerant fallback, `None` when disabled
+    fuzzy_threshold: Option<f32>,
+    response_language: ResponseLanguage,
+    /// Feed earlier messages into query expansion and the prompt
+    use_chat_context: bool,
+    max_query_expansions: usize,
+    expansion_min_embeddings: usize,
 }
 
 impl RagEngine {
-    pub fn new(
-        db: Arc<Database>,
-        ollama_endpoint: String,
-        llm_model: String,
-        embedding_model: String,
-    ) -> Self {
-        Self {
-            vector_store: VectorStore::new(db),
-            embedding_client: EmbeddingClient::new(ollama_endpoint.clone(), embedding_model),
-            llm_provider: create_provider("ollama", &ollama_endpoint, &llm_model),
-        }
-    }
-
-    pub fn update_settings(
-        &mut self,
-        db: Arc<Database>,
-        ollama_endpoint: String,
-        llm_model: String,
-        embedding_model: String,
-    ) {
-        self.vector_store = VectorStore::new(db);
-        self.embedding_client = EmbeddingClient::new(ollama_endpoint.clone(), embedding_model);
-        self.llm_provider = create_provider("ollama", &ollama_endpoint, &llm_model);
+    pub fn new(db: Arc<Database>, settings: &Settings) -> RagResult<Self> {
+        let metric = SimilarityMetric::parse(&settings.similarity_metric);
+        let similarity_threshold = settings
+            .similarity_threshold
+            .unwrap_or_else(|| metric.default_threshold());
+        Ok(Self {
+            db: db.clone(),
+            vector_store: VectorStore::with_metric(db, metric)
+                .with_disabled_sources(disabled_sources(settings))
+                .with_cache_size(settings.search_cache_size),
+            embedding_client: EmbeddingClient::from_settings(settings),
+            llm_provider: create_provider(
+                &settings.llm_provider,
+                &settings.ollama_endpoint,
+                &settings.ollama_model,
+                GenerationOptions::from_settings(settings),
+            )?,
+            boosts: RetrievalBoosts::from_settings(settings),
+            metric,
+            similarity_threshold,
+            subquery_floor: settings.subquery_similarity_floor.unwrap_or(similarity_threshold),
+            near_duplicate_threshold: settings.near_duplicate_threshold,
+            source_priority: settings.cross_source_dedup.then(|| settings.source_priority.clone()),
+            grounded_only: settings.grounded_answers_only,
+            track_retrieval: settings.track_retrieval,
+            fuzzy_threshold: settings.fuzzy_keyword_fallback.then_some(settings.fuzzy_match_threshold),
+            response_language: ResponseLanguage::from_settings(settings),
+            use_chat_context: settings.use_chat_context,
+            max_query_expansions: settings.max_query_expansions,
+            expansion_min_embeddings: settings.expansion_min_embeddings,
+        })
     }
 
-    /// Main query method with chat context and query expansion
-    pub async fn query(
-        &self,
-        query: &str,
-        chat_history: &[ChatMessage],
-        app_handle: &tauri::AppHandle,
-    ) -> RagResult<String> {
-        log::info!("Processing query: {}", query);
+    /// Rebuild the engine for new settings, leaving it unchanged if they are invalid
+    pub fn update_settings(&mut self, db: Arc<Database>, settings: &Settings) -> RagResult<()> {
+        *self = Self::new(db, settings)?;
+        Ok(())
+    }
 
-        // 1. Expand the query using chat context
-        let expanded_queries = self.expand_query(query, chat_history).await?;
-        log::info!("Expanded queries: {:?}", expanded_queries);
+    /// Load the embedding model, the chat model and the vector index so the
+    /// first real query doesn't pay for it. Safe to call repeatedly.
+    pub async fn warmup(&self) -> WarmupReport {
+        let started = Instant::now();
+        let mut steps = Vec::new();
 
-        // 2. Search with all queries and deduplicate results
-        let mut all_results: Vec<SearchResult> = Vec::new();
-        let mut seen_ids: HashSet<String> = HashSet::new();
+        let step_started = Instant::now();
+        let result = self.embedding_client.embed_query("warmup").await.map(|_| ());
+        steps.push(warmup_step("embedding_model", step_started, result.map_err(|e| e.to_string())));
 
-        for search_query in &expanded_queries {
-            let query_embedding = self.embedding_client.embed(search_query).await?;
-            let results = self.vector_store.search(&query_embedding, MAX_CONTEXT_CHUNKS)?;
-            
-            for result in results {
-                if !seen_ids.contains(&result.embedding.id) {
-                    seen_ids.insert(result.embedding.id.clone());
-                    all_results.push(result);
-                }
+        let step_started = Instant::now();
+        let result = self.llm_provider.generate("Reply with OK.", None).await.map(|_| ());
+        steps.push(warmup_step("chat_model", step_started, result.map_err(|e| e.to_string())));
+
+        let step_started = Instant::now();
+        let result = self.vector_store.warm().map(|_| ());
+        steps.push(warmup_step("vector_index", step_started, result.map_err(|e| e.to_string())));
+
+        WarmupReport {
+            steps,
+            total_ms: started.elapsed().as_millis() as u64,
+        }
+    }
+
+    /// Semantic search limited to notes modified at or after `since`
+    /// With `explain`, each hit lists the significant words it shares with the query
+    pub async fn search_recent(&self, query: &str, since: i64, limit: usize, explain: bool) -> RagResult<Vec<SearchHit>> {
+        let query_embedding = self.embedding_client.embed_query(query).await?;
+        let results = self.vector_store.search_modified_since(&query_embedding, limit, since)?;
+        let mut hits = self.to_search_hits(results)?;
+        if explain {
+            for hit in &mut hits {
+                hit.matched_terms = shared_terms(query, &hit.content);
             }
         }
+        Ok(hits)
+    }
+
+    /// Semantic search ranking only the chunks of the given notes, e.g. the
+    /// candidates left by a keyword filter in the UI
+    pub async fn search_within_ids(&self, query: &str, artifact_ids: &[String], limit: usize) -> RagResult<Vec<SearchHit>> {
+        let query_embedding = self.embedding_client.embed_query(query).await?;
+        let results = self.vector_store.search_within_ids(&query_embedding, artifact_ids, limit)?;
+        self.to_search_hits(results)
+    }
+
+    /// Semantic search returning the `limit` best notes rather than chunks,
+    /// each with its best snippet and how many of its chunks matched
+    pub async fn grouped_search(&self, query: &str, limit: usize) -> RagResult<Vec<NoteHit>> {
+        let query_embedding = self.embedding_client.embed_query(query).await?;
+        let results = self.vector_store.search(&query_embedding, limit * GROUPED_SEARCH_CHUNKS_PER_NOTE)?;
+        let mut notes = group_hits_by_note(self.to_search_hits(results)?, self.metric);
+        notes.truncate(limit);
+        Ok(notes)
+    }
+
+    /// For each expected question, find the best supporting chunk and whether
+    /// it is relevant enough to answer from; uncovered queries are gaps
+    pub async fn coverage_report(&self, queries: &[String]) -> RagResult<Vec<QueryCoverage>> {
+        let mut report = Vec::with_capacity(queries.len());
+
+        for query in queries {
+            let query_embedding = self.embedding_client.embed_query(query).await?;
+            let best = self.vector_store.search(&query_embedding, 1)?.into_iter().next();
+            let covered = best.as_ref().is_some_and(|r| self.is_relevant(r));
+            let top_score = best.as_ref().map(|r| r.similarity);
+
+            report.push(QueryCoverage {
+                query: query.clone(),
+                top_score,
+                best_match: self.to_search_hits(best.into_iter().collect())?.pop(),
+                covered,
+            });
+        }
 
-        // Sort all results by similarity and take top N
-        all_results.sort_by(|a, b| {
-            b.similarity.partial_cmp(&a.similarity).unwrap_or(std::cmp::Ordering::Equal)
-        });
-        all_results.truncate(MAX_CONTEXT_CHUNKS);
+        Ok(report)
+    }
 
-        // Filter by similarity threshold
-        let relevant_results: Vec<&SearchResult> = all_results
-            .iter()
-            .filter(|r| r.similarity >= MIN_SIMILARITY_THRESHOLD)
+    /// Summarize one note, streaming the final summary through `on_chunk`.
+    /// Long notes are summarized part by part and the parts combined; a saved
+    /// summary of unchanged content is reused, and `save` stores a new one.
+    pub async fn summarize_artifact(&self, artifact_id: &str, save: bool, on_chunk: StreamCallback) -> RagResult<String> {
+        let artifact = self.db.get_artifact_by_id(artifact_id)?.ok_or(RagError::NoContext)?;
+
+        if let Some(summary) = self.db.get_artifact_summary(artifact_id, &artifact.content_hash)? {
+            on_chunk(&summary);
+            return Ok(summary);
+        }
+
+        let chunks = self.db.get_artifact_chunks(artifact_id)?;
+        if chunks.is_empty() {
+            return Err(RagError::NoContext);
+        }
+        let title = artifact.title.clone().unwrap_or_else(|| artifact.path.clone());
+
+        // Map: summarize context-sized groups of chunks when the note is too long
+        let mut content = chunks.iter().map(|c| c.content.as_str()).collect::<Vec<_>>().join("\n\n");
+        if content.len() > MAX_SUMMARY_CONTEXT_CHARS {
+            let mut partials = Vec::new();
+            for group in group_chunks(&chunks, MAX_SUMMARY_CONTEXT_CHARS) {
+                let prompt = PARTIAL_SUMMARY_PROMPT
+                    .replace("{title}", &title)
+                    .replace("{content}", &group);
+                partials.push(self.llm_provider.generate(&prompt, None).await?.trim().to_string());
+            }
+            log::info!("Summarizing {} in {} parts", artifact.path, partials.len());
+            content = partials.join("\n\n");
+        }
+
+        // Reduce: stream the final summary
+        let prompt = SUMMARY_PROMPT
+            .replace("{title}", &title)
+            .replace("{content}", &content);
+        let summary = self.generate_answer(&prompt, None, on_chunk).await?;
+
+        if save {
+            self.db.save_artifact_summary(artifact_id, &artifact.content_hash, &summary)?;
+        }
+
+        Ok(summary)
+    }
+
+    /// Attach artifact paths and titles to raw results
+    fn to_search_hits(&self, results: Vec<SearchResult>) -> RagResult<Vec<SearchHit>> {
+        let artifacts: HashMap<String, Artifact> = self.db.get_all_artifacts()?
+            .into_iter()
+            .map(|a| (a.id.clone(), a))
             .collect();
 
-        log::info!("Found {} relevant chunks", relevant_results.len());
+        Ok(results
+            .into_iter()
+            .map(|r| {
+                let artifact = artifacts.get(&r.embedding.artifact_id);
+                SearchHit {
+                    path: artifact.map(|a| a.path.clone()).unwrap_or_default(),
+                    title: artifact.and_then(|a| a.title.clone()),
+                    artifact_id: r.embedding.artifact_id,
+                    chunk_index: r.embedding.chunk_index,
+                    content: r.embedding.content,
+                    similarity: r.similarity,
+                    matched_terms: Vec::new(),
+                }
+            })
+            .collect())
+    }
+
+    /// Answer a chat message, saving it and the reply to the chat history.
+    /// The answer streams through `stream-chunk` events as it's generated; a
+    /// failed answer is saved as an error message.
+    pub async fn chat(&self, query: &str, style: Option<AnswerStyle>, events: EventSink) -> RagResult<ChatReply> {
+        // Get chat history BEFORE adding the new message
+        let chat_history = self.db.get_chat_history()?;
+        self.db.insert_chat_message("user", query)?;
 
-        // 3. Build context from search results
-        let kb_context = self.build_context(&relevant_results);
+        match self.query(query, &chat_history, style, events).await {
+            Ok((content, sources)) => {
+                let message_id = self.db.insert_chat_message_with_sources(
+                    "assistant",
+                    &content,
+                    &cite_sources(&sources),
+                )?;
+                if let Err(e) = self.record_answer_chunks(message_id, &sources) {
+                    log::warn!("Failed to log answer chunks: {}", e);
+                }
+                Ok(ChatReply {
+                    message_id,
+                    content,
+                    sources,
+                })
+            }
+            Err(e) => {
+                self.db.insert_chat_message("assistant", &format!("Error: {}", e)).ok();
+                Err(e)
+            }
+        }
+    }
+
+    /// Main query method with chat context and query expansion, returning
+    /// the answer and the chunks it was given as context
+    pub async fn query(
+        &self,
+        query: &str,
+        chat_history: &[ChatMessage],
+        style: Option<AnswerStyle>,
+        events: EventSink,
+    ) -> RagResult<(String, Vec<SearchHit>)> {
+        log::info!("Processing query: {}", query);
+
+        // Nothing has been indexed yet: say so instead of answering from nothing
+        if let Some(reply) = self.empty_knowledge_base_reply()? {
+            log::info!("Knowledge base is empty, skipping retrieval and generation");
+            events("stream-chunk", serde_json::json!({
+                "content": reply,
+                "done": false
+            }));
+            events("stream-chunk", serde_json::json!({
+                "content": "",
+                "done": true
+            }));
+            return Ok((reply.to_string(), Vec::new()));
+        }
 
-        // 4. Build the full prompt with chat history
-        let prompt = self.build_prompt_with_history(query, &kb_context, chat_history);
+        let (question, relevant) = self.retrieve(query, chat_history).await?;
+        let query = question.as_str();
+        let relevant_results: Vec<&SearchResult> = relevant.iter().collect();
 
-        // 5. Stream response from LLM
-        let app_handle_clone = app_handle.clone();
-        let response = self.llm_provider.generate_stream(
-            &prompt,
+        if self.grounded_only && relevant_results.len() < MIN_GROUNDING_CHUNKS {
+            log::warn!("Low grounding: only {} relevant chunks", relevant_results.len());
+            events("grounding-warning", serde_json::json!({
+                "relevantChunks": relevant_results.len()
+            }));
+        }
+
+        if let Err(e) = self.record_retrieval(&relevant_results) {
+            log::warn!("Failed to log retrieval: {}", e);
+        }
+
+        // 3. Build the full prompt from the search results and chat history
+        let prompt = self.assemble_prompt(query, relevant_results, chat_history, style);
+
+        // 4. Stream response from LLM
+        let chunk_events = events.clone();
+        let response = self.generate_answer(
+            &prompt.body,
+            Some(&prompt.system),
             Box::new(move |chunk| {
-                let _ = app_handle_clone.emit_all("stream-chunk", serde_json::json!({
+                chunk_events("stream-chunk", serde_json::json!({
                     "content": chunk,
                     "done": false
                 }));
@@ -141,12 +627,308 @@ impl RagEngine {
         ).await?;
 
         // Emit completion
-        let _ = app_handle.emit_all("stream-chunk", serde_json::json!({
+        events("stream-chunk", serde_json::json!({
             "content": "",
             "done": true
         }));
 
-        Ok(response)
+        if let Some(stats) = self.llm_provider.last_generation_stats() {
+            events("generation-stats", serde_json::to_value(&stats).unwrap_or_default());
+        }
+
+        let sources = self.to_search_hits(relevant)?;
+        Ok((response, sources))
+    }
+
+    /// Strip inline filters, expand the question and return it with the
+    /// relevant chunks, best first
+    async fn retrieve(
+        &self,
+        query: &str,
+        chat_history: &[ChatMessage],
+    ) -> RagResult<(String, Vec<SearchResult>)> {
+        // Pull inline filters (tag:, path:, title:) out of the question
+        let (filters, question) = parse_query_filters(query);
+        let query = if question.is_empty() { query.to_string() } else { question };
+        let allowed_ids: Option<HashSet<String>> = if filters.is_empty() {
+            None
+        } else {
+            let ids: HashSet<String> = self.db.get_all_artifacts()?
+                .into_iter()
+                .filter(|a| filters.matches(a))
+                .map(|a| a.id)
+                .collect();
+            log::info!("Query filters {:?} matched {} notes", filters, ids.len());
+            Some(ids)
+        };
+
+        // 1. Expand the query using chat context
+        let expanded_queries = self.expand_query(&query, chat_history).await?;
+        log::info!("Expanded queries: {:?}", expanded_queries);
+
+        // 2. Embed all queries concurrently, then search with each in order
+        // and deduplicate results
+        let query_embeddings = try_join_all(
+            expanded_queries.iter().map(|search_query| self.embedding_client.embed_query(search_query)),
+        )
+        .await?;
+        let original_embedding = query_embeddings.first().cloned().unwrap_or_default();
+        let mut per_query_results: Vec<Vec<SearchResult>> = Vec::with_capacity(query_embeddings.len());
+
+        for query_embedding in &query_embeddings {
+            let results = match &allowed_ids {
+                Some(ids) => self.vector_store.search_within(query_embedding, MAX_CONTEXT_CHUNKS, ids)?,
+                None => self.vector_store.search(query_embedding, MAX_CONTEXT_CHUNKS)?,
+            };
+            per_query_results.push(results);
+        }
+
+        let mut all_results = self.merge_subquery_results(per_query_results);
+
+        // Sort all results by (boosted) score and take top N
+        if self.boosts.is_empty() {
+            sort_results(&mut all_results, self.metric);
+        } else {
+            let artifacts: HashMap<String, Artifact> = self.db.get_all_artifacts()?
+                .into_iter()
+                .map(|a| (a.id.clone(), a))
+                .collect();
+            self.boosts.apply(&mut all_results, &artifacts, self.metric);
+        }
+        let all_results = self.dedupe_across_sources(all_results)?;
+        let mut all_results = self.drop_near_duplicates(all_results);
+        all_results.truncate(MAX_CONTEXT_CHUNKS);
+
+        // Filter by similarity threshold
+        all_results.retain(|r| self.is_relevant(r));
+
+        // Too little got through: a misspelled rare term may be to blame
+        if all_results.len() < MIN_GROUNDING_CHUNKS {
+            if let Some(threshold) = self.fuzzy_threshold {
+                let found = self.fuzzy_keyword_results(&query, threshold, allowed_ids.as_ref(), &original_embedding)?;
+                let seen: HashSet<String> = all_results.iter().map(|r| r.embedding.id.clone()).collect();
+                all_results.extend(found.into_iter().filter(|r| !seen.contains(&r.embedding.id)));
+                all_results.truncate(MAX_CONTEXT_CHUNKS);
+            }
+        }
+
+        // Pinned notes lead the context whatever their score
+        let disabled = self.vector_store.disabled_artifact_ids()?;
+        let pinned: Vec<Embedding> = self.db.get_pinned_chunks()?
+            .into_iter()
+            .filter(|e| !disabled.contains(&e.artifact_id))
+            .collect();
+        if !pinned.is_empty() {
+            let mut results: Vec<SearchResult> = pinned
+                .into_iter()
+                .take(MAX_CONTEXT_CHUNKS)
+                .map(|embedding| SearchResult {
+                    similarity: self.metric.score(&original_embedding, &embedding.embedding),
+                    embedding,
+                })
+                .collect();
+            let pinned_ids: HashSet<String> = results.iter().map(|r| r.embedding.id.clone()).collect();
+            results.extend(all_results.into_iter().filter(|r| !pinned_ids.contains(&r.embedding.id)));
+            results.truncate(MAX_CONTEXT_CHUNKS);
+            all_results = results;
+        }
+
+        log::info!("Found {} relevant chunks", all_results.len());
+
+        Ok((query, all_results))
+    }
+
+    /// Chunks whose words match the query's keywords despite typos, best
+    /// first, scored against the query embedding like any other result
+    fn fuzzy_keyword_results(
+        &self,
+        query: &str,
+        threshold: f32,
+        allowed_ids: Option<&HashSet<String>>,
+        query_embedding: &[f32],
+    ) -> RagResult<Vec<SearchResult>> {
+        let disabled = self.vector_store.disabled_artifact_ids()?;
+        let mut matches: Vec<(f32, Embedding)> = self.db.get_all_embeddings()?
+            .into_iter()
+            .filter(|e| allowed_ids.map(|ids| ids.contains(&e.artifact_id)).unwrap_or(true))
+            .filter(|e| !disabled.contains(&e.artifact_id))
+            .filter_map(|e| {
+                let score = keyword_score(query, &e.content);
+                (score >= threshold).then_some((score, e))
+            })
+            .collect();
+        matches.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        log::info!("Fuzzy keyword fallback matched {} chunks", matches.len());
+
+        Ok(matches
+            .into_iter()
+            .take(MAX_CONTEXT_CHUNKS)
+            .map(|(_, embedding)| SearchResult {
+                similarity: self.metric.score(query_embedding, &embedding.embedding),
+                embedding,
+            })
+            .collect())
+    }
+
+    /// Run expansion and retrieval and return the exact prompt `query` would
+    /// send, without generating or recording anything
+    pub async fn preview_prompt(
+        &self,
+        query: &str,
+        chat_history: &[ChatMessage],
+        style: Option<AnswerStyle>,
+    ) -> RagResult<String> {
+        let (question, relevant) = self.retrieve(query, chat_history).await?;
+        Ok(self.assemble_prompt(&question, relevant.iter().collect(), chat_history, style).full_text())
+    }
+
+    /// The prompt for a question and its retrieved chunks, shared by `query`
+    /// and `preview_prompt` so the preview is exactly what gets sent
+    fn assemble_prompt(
+        &self,
+        question: &str,
+        relevant: Vec<&SearchResult>,
+        chat_history: &[ChatMessage],
+        style: Option<AnswerStyle>,
+    ) -> Prompt {
+        let kb_context = self.build_context(&self.fit_to_context_window(relevant));
+        self.build_prompt_with_history(question, &kb_context, chat_history, style)
+    }
+
+    /// Drop each subquery's results below the per-subquery floor, so an
+    /// off-target expansion can't inject weak chunks, then deduplicate
+    fn merge_subquery_results(&self, per_query_results: Vec<Vec<SearchResult>>) -> Vec<SearchResult> {
+        let mut all_results: Vec<SearchResult> = Vec::new();
+        let mut seen_ids: HashSet<String> = HashSet::new();
+
+        for (i, results) in per_query_results.into_iter().enumerate() {
+            let total = results.len();
+            let kept: Vec<SearchResult> = results
+                .into_iter()
+                .filter(|r| self.metric.is_relevant(r.similarity, self.subquery_floor))
+                .collect();
+            if kept.is_empty() && total > 0 {
+                log::debug!("Dropping all {} results of subquery {}: below the floor", total, i);
+            }
+
+            for result in kept {
+                if seen_ids.insert(result.embedding.id.clone()) {
+                    all_results.push(result);
+                }
+            }
+        }
+
+        all_results
+    }
+
+    /// Collapse chunks of notes sharing a content hash (e.g. a file also
+    /// imported into Outline) into the one from the most preferred source.
+    /// Runs before near-duplicate removal, which would keep whichever ranked
+    /// first. Expects results sorted best first.
+    fn dedupe_across_sources(&self, results: Vec<SearchResult>) -> RagResult<Vec<SearchResult>> {
+        let Some(priority) = &self.source_priority else {
+            return Ok(results);
+        };
+
+        let mut artifacts: HashMap<String, Option<Artifact>> = HashMap::new();
+        let mut kept: Vec<(SearchResult, usize)> = Vec::new();
+        let mut positions: HashMap<(String, i32), usize> = HashMap::new();
+        for result in results {
+            let artifact_id = &result.embedding.artifact_id;
+            if !artifacts.contains_key(artifact_id) {
+                artifacts.insert(artifact_id.clone(), self.db.get_artifact_by_id(artifact_id)?);
+            }
+            let Some(artifact) = artifacts[artifact_id].as_ref().filter(|a| !a.content_hash.is_empty()) else {
+                kept.push((result, priority.len()));
+                continue;
+            };
+
+            let rank = priority
+                .iter()
+                .position(|prefix| artifact.path.starts_with(prefix.as_str()))
+                .unwrap_or(priority.len());
+            let key = (artifact.content_hash.clone(), result.embedding.chunk_index);
+            match positions.get(&key) {
+                Some(&pos) if rank < kept[pos].1 => {
+                    log::debug!("Preferring chunk {} over {}", result.embedding.id, kept[pos].0.embedding.id);
+                    kept[pos] = (result, rank);
+                }
+                Some(_) => log::debug!("Dropping chunk {} duplicated in another source", result.embedding.id),
+                None => {
+                    positions.insert(key, kept.len());
+                    kept.push((result, rank));
+                }
+            }
+        }
+        Ok(kept.into_iter().map(|(result, _)| result).collect())
+    }
+
+    /// Drop results whose embedding is nearly identical to a better-ranked one
+    /// (e.g. edited copies of a note). Expects results sorted best first.
+    fn drop_near_duplicates(&self, results: Vec<SearchResult>) -> Vec<SearchResult> {
+        if self.near_duplicate_threshold >= 1.0 {
+            return results;
+        }
+
+        let mut kept: Vec<SearchResult> = Vec::new();
+        for result in results {
+            let duplicate = kept.iter().any(|k| {
+                SimilarityMetric::Cosine.score(&k.embedding.embedding, &result.embedding.embedding)
+                    > self.near_duplicate_threshold
+            });
+            if duplicate {
+                log::debug!("Dropping near-duplicate chunk {}", result.embedding.id);
+            } else {
+                kept.push(result);
+            }
+        }
+        kept
+    }
+
+    /// Whether a result passes the similarity threshold for the active metric
+    fn is_relevant(&self, result: &SearchResult) -> bool {
+        self.metric.is_relevant(result.similarity, self.similarity_threshold)
+    }
+
+    /// Log which artifacts supplied context, when retrieval tracking is on
+    fn record_retrieval(&self, results: &[&SearchResult]) -> RagResult<()> {
+        if !self.track_retrieval || results.is_empty() {
+            return Ok(());
+        }
+
+        let mut artifact_ids: Vec<String> = Vec::new();
+        for result in results {
+            if !artifact_ids.contains(&result.embedding.artifact_id) {
+                artifact_ids.push(result.embedding.artifact_id.clone());
+            }
+        }
+        self.db.log_retrieval(&uuid::Uuid::new_v4().to_string(), &artifact_ids)?;
+        Ok(())
+    }
+
+    /// Log which chunks an answer was given, so ratings on it can be traced
+    /// back to them, when retrieval tracking is on
+    fn record_answer_chunks(&self, message_id: i64, sources: &[SearchHit]) -> RagResult<()> {
+        if !self.track_retrieval || sources.is_empty() {
+            return Ok(());
+        }
+
+        let chunks: Vec<(String, i32)> = sources
+            .iter()
+            .map(|hit| (hit.artifact_id.clone(), hit.chunk_index))
+            .collect();
+        self.db.log_answer_chunks(message_id, &chunks)?;
+        Ok(())
+    }
+
+    /// The reply to give when no embeddings exist at all, as opposed to none
+    /// passing the similarity threshold
+    fn empty_knowledge_base_reply(&self) -> RagResult<Option<&'static str>> {
+        if self.db.count_embeddings()? == 0 {
+            Ok(Some(EMPTY_KNOWLEDGE_BASE_MESSAGE))
+        } else {
+            Ok(None)
+        }
     }
 
     /// Expand the query using the LLM to generate alternative search queries
@@ -158,8 +940,20 @@ impl RagEngine {
         // Always include the original query
         let mut queries = vec![query.to_string()];
 
+        // Each expansion costs an embed and a search; on a tiny index one
+        // search already covers everything
+        if self.max_query_expansions == 0 {
+            return Ok(queries);
+        }
+        let indexed = self.db.count_embeddings()?;
+        if indexed < self.expansion_min_embeddings {
+            log::debug!("Skipping query expansion: only {} chunks indexed", indexed);
+            return Ok(queries);
+        }
+
         // Build conversation context (last few messages)
-        let recent_history: Vec<&ChatMessage> = chat_history
+        let recent_history: Vec<&ChatMessage> = self
+            .conversation(chat_history)
             .iter()
             .rev()
             .take(MAX_CHAT_HISTORY)
@@ -184,7 +978,7 @@ impl RagEngine {
             .replace("{conversation}", &conversation)
             .replace("{query}", query);
 
-        match self.llm_provider.generate(&expansion_prompt).await {
+        match self.llm_provider.generate(&expansion_prompt, None).await {
             Ok(response) => {
                 // Parse the response - each line is a query
                 for line in response.lines() {
@@ -210,11 +1004,54 @@ impl RagEngine {
         }
 
         // Limit total queries to avoid too many API calls
-        queries.truncate(4);
+        queries.truncate(1 + self.max_query_expansions);
         
         Ok(queries)
     }
 
+    /// Stream the answer when the provider can, otherwise pass it on in one
+    /// piece. The system prompt goes in the provider's system role when it
+    /// has one and leads the prompt otherwise.
+    async fn generate_answer(&self, prompt: &str, system: Option<&str>, on_chunk: StreamCallback) -> RagResult<String> {
+        let capabilities = self.llm_provider.capabilities();
+        let inline;
+        let (prompt, system) = match system {
+            Some(system) if !capabilities.supports_system_role => {
+                inline = format!("{}\n\n{}", system, prompt);
+                (inline.as_str(), None)
+            }
+            system => (prompt, system),
+        };
+
+        if capabilities.supports_streaming {
+            return Ok(self.llm_provider.generate_stream(prompt, system, on_chunk).await?);
+        }
+        let response = self.llm_provider.generate(prompt, system).await?;
+        on_chunk(&response);
+        Ok(response)
+    }
+
+    /// Drop the lowest-ranked chunks that would overflow the model's context
+    /// window, always keeping the best one
+    fn fit_to_context_window<'a>(&self, results: Vec<&'a SearchResult>) -> Vec<&'a SearchResult> {
+        let Some(max_tokens) = self.llm_provider.capabilities().max_context_tokens else {
+            return results;
+        };
+        let budget = (max_tokens as f32 * CONTEXT_WINDOW_SHARE) as usize * CHARS_PER_TOKEN;
+
+        let mut used = 0;
+        let mut fitted = Vec::new();
+        for result in results {
+            used += result.embedding.content.len();
+            if used > budget && !fitted.is_empty() {
+                log::info!("Dropping lower-ranked chunks that don't fit the model's context window");
+                break;
+            }
+            fitted.push(result);
+        }
+        fitted
+    }
+
     fn build_context(&self, results: &[&SearchResult]) -> String {
         if results.is_empty() {
             return "No relevant context found in your knowledge base.".to_string();
@@ -225,13 +1062,17 @@ impl RagEngine {
         for (i, result) in results.iter().enumerate() {
             let source = &result.embedding.artifact_id;
             let content = &result.embedding.content;
-            let similarity = result.similarity;
+            let score = if self.metric.higher_is_better() {
+                format!("relevance: {:.0}%", result.similarity * 100.0)
+            } else {
+                format!("distance: {:.2}", result.similarity)
+            };
 
             context_parts.push(format!(
-                "[Source {}: {} (relevance: {:.0}%)]\n{}",
+                "[Source {}: {} ({})]\n{}",
                 i + 1,
                 source,
-                similarity * 100.0,
+                score,
                 content
             ));
         }
@@ -239,14 +1080,35 @@ impl RagEngine {
         context_parts.join("\n\n---\n\n")
     }
 
+    /// The strict prompt in guardrail mode, the default one otherwise
+    fn system_prompt(&self) -> &'static str {
+        if self.grounded_only {
+            STRICT_SYSTEM_PROMPT
+        } else {
+            SYSTEM_PROMPT
+        }
+    }
+
+    /// The history to use as context: none when chat context is turned off,
+    /// so each question is answered on its own
+    fn conversation<'a>(&self, chat_history: &'a [ChatMessage]) -> &'a [ChatMessage] {
+        if self.use_chat_context {
+            chat_history
+        } else {
+            &[]
+        }
+    }
+
     fn build_prompt_with_history(
         &self,
         query: &str,
         kb_context: &str,
         chat_history: &[ChatMessage],
-    ) -> String {
+        style: Option<AnswerStyle>,
+    ) -> Prompt {
         // Include recent chat history for context
-        let recent_history: Vec<&ChatMessage> = chat_history
+        let recent_history: Vec<&ChatMessage> = self
+            .conversation(chat_history)
             .iter()
             .rev()
             .take(MAX_CHAT_HISTORY)
@@ -270,18 +1132,870 @@ impl RagEngine {
             format!("\n\n## Previous Conversation:\n\n{}", history_str)
         };
 
-        format!(
-            "{}\n\n## Context from your knowledge base:\n\n{}{}
+        // Ask for the question's (or the configured) language so the English
+        // system prompt doesn't pull the answer into English
+        let language_instruction = self
+            .response_language
+            .for_query(query)
+            .map(|language| format!("\n\nRespond in {}.", language))
+            .unwrap_or_default();
+        let style_instruction = style
+            .map(|style| format!("\n\n{}", style.instruction()))
+            .unwrap_or_default();
+
+        Prompt {
+            system: format!("{}{}{}", self.system_prompt(), language_instruction, style_instruction),
+            body: format!(
+                "## Context from your knowledge base:\n\n{}{}
 
 ## Current User Question:
 
 {}
 
 ## Your Answer:",
-            SYSTEM_PROMPT,
-            kb_context,
-            chat_context,
-            query
-        )
+                kb_context,
+                chat_context,
+                query
+            ),
+        }
+    }
+}
+
+/// One citation per note that fed the context, in context order, with the
+/// note's best chunk
+fn cite_sources(hits: &[SearchHit]) -> Vec<SourceCitation> {
+    let mut cited = HashSet::new();
+    hits.iter()
+        .filter(|hit| cited.insert(hit.artifact_id.as_str()))
+        .map(|hit| SourceCitation {
+            artifact_id: hit.artifact_id.clone(),
+            path: hit.path.clone(),
+            title: hit.title.clone().unwrap_or_else(|| hit.path.clone()),
+            chunk: hit.content.clone(),
+            similarity: hit.similarity,
+        })
+        .collect()
+}
+
+fn warmup_step(name: &str, started: Instant, result: Result<(), String>) -> WarmupStep {
+    let duration_ms = started.elapsed().as_millis() as u64;
+    if let Err(e) = &result {
+        log::warn!("Warmup step {} failed: {}", name, e);
+    }
+    WarmupStep {
+        name: name.to_string(),
+        duration_ms,
+        error: result.err(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> (tempfile::TempDir, Arc<Database>) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(dir.path().to_path_buf()).unwrap());
+        (dir, db)
+    }
+
+    fn note(id: &str, path: &str) -> Artifact {
+        Artifact { id: id.to_string(), path: path.to_string(), ..Default::default() }
+    }
+
+    /// Stores `artifact` with one embedding per `(content, vector)` chunk
+    fn seed_note(db: &Database, artifact: Artifact, chunks: &[(&str, &[f32])]) {
+        db.upsert_artifact(&artifact).unwrap();
+        for (index, (content, vector)) in chunks.iter().enumerate() {
+            db.insert_embedding(&Embedding {
+                id: format!("{}#{}", artifact.id, index),
+                artifact_id: artifact.id.clone(),
+                chunk_index: index as i32,
+                content: content.to_string(),
+                embedding: vector.to_vec(),
+            }).unwrap();
+        }
+    }
+
+    fn search_result(artifact_id: &str, chunk_index: i32, similarity: f32) -> SearchResult {
+        SearchResult {
+            embedding: Embedding {
+                id: format!("{}#{}", artifact_id, chunk_index),
+                artifact_id: artifact_id.to_string(),
+                chunk_index,
+                content: String::new(),
+                embedding: Vec::new(),
+            },
+            similarity,
+        }
+    }
+
+    async fn mock_ollama(server: &mut mockito::ServerGuard, embedding: &[f32], response: &str) {
+        server
+            .mock("POST", "/api/embeddings")
+            .with_body(serde_json::json!({ "embedding": embedding }).to_string())
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/api/generate")
+            .with_body(serde_json::json!({ "response": response, "done": true }).to_string())
+            .create_async()
+            .await;
+    }
+
+    #[test]
+    fn test_parse_query_filters() {
+        let (filters, question) =
+            parse_query_filters("tag:work path:Projects/ how should I tag:#Urgent prioritize? title:Roadmap");
+
+        assert_eq!(filters.tags, vec!["work", "urgent"]);
+        assert_eq!(filters.paths, vec!["projects/"]);
+        assert_eq!(filters.titles, vec!["roadmap"]);
+        assert_eq!(question, "how should I prioritize?");
+    }
+
+    #[test]
+    fn test_query_filters_match_artifact() {
+        let (filters, _) = parse_query_filters("tag:work path:projects what's next?");
+        let artifact = Artifact {
+            path: "/vault/Projects/plan.md".to_string(),
+            tags: vec!["Work".to_string()],
+            ..Default::default()
+        };
+        assert!(filters.matches(&artifact));
+
+        let untagged = Artifact { tags: vec![], ..artifact };
+        assert!(!filters.matches(&untagged));
+
+        let (by_collection, _) = parse_query_filters("collection:engineering runbooks");
+        let doc = Artifact { collection: Some("Engineering".to_string()), ..Default::default() };
+        assert!(by_collection.matches(&doc));
+        assert!(!by_collection.matches(&Artifact::default()));
+
+        let (none, question) = parse_query_filters("see https://example.com");
+        assert!(none.is_empty());
+        assert_eq!(question, "see https://example.com");
+    }
+
+    #[test]
+    fn test_boosted_note_outranks_equal_match() {
+        let mut settings = Settings {
+            vault_paths: vec!["/vault".to_string()],
+            ..Settings::default()
+        };
+        settings.retrieval_boosts.insert("Summaries/".to_string(), 1.5);
+        settings.retrieval_boosts.insert("#ignored".to_string(), 1.0);
+        let boosts = RetrievalBoosts::from_settings(&settings);
+        assert_eq!(boosts.tags.len(), 0);
+
+        let artifacts: HashMap<String, Artifact> = [
+            ("plain", "/vault/Inbox/draft.md"),
+            ("curated", "/vault/Summaries/q3.md"),
+        ]
+        .into_iter()
+        .map(|(id, path)| (id.to_string(), note(id, path)))
+        .collect();
+
+        let mut results = vec![search_result("plain", 0, 0.6), search_result("curated", 0, 0.6)];
+
+        boosts.apply(&mut results, &artifacts, SimilarityMetric::Cosine);
+
+        assert_eq!(results[0].embedding.artifact_id, "curated");
+        assert!((results[0].similarity - 0.9).abs() < 1e-6);
+        assert_eq!(results[1].similarity, 0.6);
+    }
+
+    #[test]
+    fn test_empty_knowledge_base_is_detected() {
+        let (_dir, db) = test_db();
+        let engine = RagEngine::new(db.clone(), &Settings::default()).unwrap();
+
+        assert_eq!(engine.empty_knowledge_base_reply().unwrap(), Some(EMPTY_KNOWLEDGE_BASE_MESSAGE));
+
+        seed_note(&db, note("a1", "note.md"), &[("indexed", &[0.1, 0.2])]);
+
+        assert_eq!(engine.empty_knowledge_base_reply().unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_chat_returns_saved_reply() {
+        let (_dir, db) = test_db();
+        let engine = RagEngine::new(db.clone(), &Settings::default()).unwrap();
+
+        let streamed = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = streamed.clone();
+        let reply = engine
+            .chat("What did I plan?", None, Arc::new(move |name, payload| {
+                sink.lock().unwrap().push((name.to_string(), payload));
+            }))
+            .await
+            .unwrap();
+
+        let history = db.get_chat_history().unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(reply.message_id, history[1].id);
+        assert_eq!(history[1].role, "assistant");
+        assert_eq!(reply.content, history[1].content);
+        assert!(reply.sources.is_empty());
+        assert_eq!(streamed.lock().unwrap().last().unwrap().1["done"], true);
+    }
+
+    /// Answers in one piece with a tiny context window
+    struct NonStreamingProvider;
+
+    #[async_trait::async_trait]
+    impl LLMProvider for NonStreamingProvider {
+        async fn generate(&self, _prompt: &str, _system: Option<&str>) -> crate::llm::LLMResult<String> {
+            Ok("whole answer".to_string())
+        }
+
+        async fn generate_stream(
+            &self,
+            _prompt: &str,
+            _system: Option<&str>,
+            _on_chunk: StreamCallback,
+        ) -> crate::llm::LLMResult<String> {
+            panic!("streamed from a provider without streaming support");
+        }
+
+        fn capabilities(&self) -> crate::llm::ProviderCapabilities {
+            crate::llm::ProviderCapabilities {
+                supports_streaming: false,
+                max_context_tokens: Some(100),
+                supports_system_role: false,
+            }
+        }
+
+        async fn list_models(&self) -> crate::llm::LLMResult<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        fn model_name(&self) -> &str {
+            "stub"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generation_adapts_to_provider_capabilities() {
+        let (_dir, db) = test_db();
+        let mut engine = RagEngine::new(db, &Settings::default()).unwrap();
+        let results: Vec<SearchResult> = (0..3)
+            .map(|i| {
+                let mut result = search_result("a", i, 0.9);
+                result.embedding.content = "x".repeat(150);
+                result
+            })
+            .collect();
+
+        // Ollama streams and has room for every chunk
+        assert!(engine.llm_provider.capabilities().supports_streaming);
+        assert_eq!(engine.fit_to_context_window(results.iter().collect()).len(), 3);
+
+        // 100 tokens leave room for only one 150-character chunk
+        engine.llm_provider = Box::new(NonStreamingProvider);
+        assert_eq!(engine.fit_to_context_window(results.iter().collect()).len(), 1);
+
+        let streamed = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = streamed.clone();
+        let answer = engine
+            .generate_answer("prompt", None, Box::new(move |chunk| sink.lock().unwrap().push(chunk.to_string())))
+            .await
+            .unwrap();
+        assert_eq!(answer, "whole answer");
+        assert_eq!(*streamed.lock().unwrap(), vec!["whole answer".to_string()]);
+    }
+
+    #[test]
+    fn test_default_context_window_fits_default_sized_chunks() {
+        let (_dir, db) = test_db();
+        let engine = RagEngine::new(db, &Settings::default()).unwrap();
+        // A default 500-word chunk runs to about 3000 characters
+        let results: Vec<SearchResult> = (0..MAX_CONTEXT_CHUNKS as i32)
+            .map(|i| {
+                let mut result = search_result("a", i, 0.9);
+                result.embedding.content = "word ".repeat(600);
+                result
+            })
+            .collect();
+
+        assert_eq!(engine.fit_to_context_window(results.iter().collect()).len(), MAX_CONTEXT_CHUNKS);
+    }
+
+    /// Each prompt a provider was sent, with its separate system prompt
+    type SentPrompts = Arc<std::sync::Mutex<Vec<(String, Option<String>)>>>;
+
+    /// Streams an empty answer and records what it was sent
+    struct RecordingProvider {
+        supports_system_role: bool,
+        sent: SentPrompts,
+    }
+
+    #[async_trait::async_trait]
+    impl LLMProvider for RecordingProvider {
+        async fn generate(&self, _prompt: &str, _system: Option<&str>) -> crate::llm::LLMResult<String> {
+            Ok(String::new())
+        }
+
+        async fn generate_stream(
+            &self,
+            prompt: &str,
+            system: Option<&str>,
+            _on_chunk: StreamCallback,
+        ) -> crate::llm::LLMResult<String> {
+            self.sent.lock().unwrap().push((prompt.to_string(), system.map(String::from)));
+            Ok(String::new())
+        }
+
+        fn capabilities(&self) -> crate::llm::ProviderCapabilities {
+            crate::llm::ProviderCapabilities {
+                supports_streaming: true,
+                max_context_tokens: None,
+                supports_system_role: self.supports_system_role,
+            }
+        }
+
+        async fn list_models(&self) -> crate::llm::LLMResult<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        fn model_name(&self) -> &str {
+            "recorder"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_system_prompt_is_sent_separately_when_supported() {
+        let mut server = mockito::Server::new_async().await;
+        mock_ollama(&mut server, &[1.0, 0.0], "").await;
+        let (_dir, db) = test_db();
+        seed_note(&db, note("plan", "plan.md"), &[("Ship it in May", &[1.0, 0.0])]);
+        let settings = Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        };
+        let mut engine = RagEngine::new(db, &settings).unwrap();
+
+        for supports_system_role in [true, false] {
+            let sent = SentPrompts::default();
+            engine.llm_provider = Box::new(RecordingProvider { supports_system_role, sent: sent.clone() });
+            engine
+                .query("When do we ship?", &[], Some(AnswerStyle::Concise), Arc::new(|_, _| {}))
+                .await
+                .unwrap();
+
+            let (prompt, system) = sent.lock().unwrap().pop().unwrap();
+            assert!(prompt.contains("Ship it in May"));
+            if supports_system_role {
+                let system = system.unwrap();
+                assert!(system.starts_with(SYSTEM_PROMPT));
+                assert!(system.contains(AnswerStyle::Concise.instruction()));
+                assert!(prompt.starts_with("## Context from your knowledge base:"));
+            } else {
+                assert_eq!(system, None);
+                assert!(prompt.starts_with(SYSTEM_PROMPT));
+                assert!(prompt.contains(AnswerStyle::Concise.instruction()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_guardrail_mode_uses_strict_prompt() {
+        let (_dir, db) = test_db();
+
+        let default_engine = RagEngine::new(db.clone(), &Settings::default()).unwrap();
+        let prompt = default_engine.build_prompt_with_history("question", "context", &[], None).full_text();
+        assert!(prompt.starts_with(SYSTEM_PROMPT));
+
+        let settings = Settings {
+            grounded_answers_only: true,
+            ..Settings::default()
+        };
+        let strict_engine = RagEngine::new(db, &settings).unwrap();
+        let prompt = strict_engine.build_prompt_with_history("question", "context", &[], None).full_text();
+        assert!(prompt.starts_with(STRICT_SYSTEM_PROMPT));
+    }
+
+    #[test]
+    fn test_prompt_without_chat_context_has_no_conversation() {
+        let (_dir, db) = test_db();
+        let history = vec![ChatMessage {
+            id: 1,
+            role: "user".to_string(),
+            content: "Tell me about the old project".to_string(),
+            timestamp: 0,
+            sources: Vec::new(),
+        }];
+
+        let engine = RagEngine::new(db.clone(), &Settings::default()).unwrap();
+        let prompt = engine.build_prompt_with_history("question", "context", &history, None).full_text();
+        assert!(prompt.contains("## Previous Conversation"));
+
+        let settings = Settings {
+            use_chat_context: false,
+            ..Settings::default()
+        };
+        let engine = RagEngine::new(db, &settings).unwrap();
+        let prompt = engine.build_prompt_with_history("question", "context", &history, None).full_text();
+        assert!(!prompt.contains("Previous Conversation"));
+        assert!(!prompt.contains("the old project"));
+    }
+
+    #[test]
+    fn test_german_query_asks_for_german_answer() {
+        let (_dir, db) = test_db();
+        let question = "Was habe ich über das Projekt geschrieben?";
+
+        let settings = Settings {
+            response_language: "auto".to_string(),
+            ..Settings::default()
+        };
+        let engine = RagEngine::new(db.clone(), &settings).unwrap();
+        let prompt = engine.build_prompt_with_history(question, "context", &[], None).full_text();
+        assert!(prompt.starts_with(SYSTEM_PROMPT));
+        assert!(prompt.contains("Respond in German."));
+
+        let off_engine = RagEngine::new(db, &Settings::default()).unwrap();
+        let prompt = off_engine.build_prompt_with_history(question, "context", &[], None).full_text();
+        assert!(!prompt.contains("Respond in"));
+    }
+
+    #[test]
+    fn test_answer_styles_add_their_instruction() {
+        let (_dir, db) = test_db();
+        let engine = RagEngine::new(db, &Settings::default()).unwrap();
+
+        let plain = engine.build_prompt_with_history("question", "context", &[], None).full_text();
+        for name in ["concise", "detailed", "bullet"] {
+            let style = AnswerStyle::parse(name).unwrap();
+            let prompt = engine.build_prompt_with_history("question", "context", &[], Some(style)).full_text();
+            assert!(prompt.starts_with(SYSTEM_PROMPT));
+            assert!(prompt.contains(style.instruction()), "{} instruction missing", name);
+            assert!(!plain.contains(style.instruction()));
+        }
+        assert_eq!(AnswerStyle::parse(" Bullet "), Some(AnswerStyle::Bullet));
+        assert_eq!(AnswerStyle::parse("poem"), None);
+    }
+
+    #[test]
+    fn test_retrieval_log_counts_cited_artifacts() {
+        let (_dir, db) = test_db();
+        seed_note(&db, note("a", "a.md"), &[]);
+        seed_note(&db, note("b", "b.md"), &[]);
+        let result = |artifact_id, chunk| search_result(artifact_id, chunk, 0.8);
+
+        // Tracking is opt-in
+        let engine = RagEngine::new(db.clone(), &Settings::default()).unwrap();
+        engine.record_retrieval(&[&result("a", 0)]).unwrap();
+        assert!(db.get_top_sources(10, None).unwrap().is_empty());
+
+        let settings = Settings {
+            track_retrieval: true,
+            ..Settings::default()
+        };
+        let engine = RagEngine::new(db.clone(), &settings).unwrap();
+        // Two chunks of the same note count once per query
+        let first = [result("a", 0), result("a", 1), result("b", 0)];
+        engine.record_retrieval(&first.iter().collect::<Vec<_>>()).unwrap();
+        engine.record_retrieval(&[&result("a", 2)]).unwrap();
+
+        let top = db.get_top_sources(10, None).unwrap();
+        let counts: Vec<(&str, usize)> = top.iter().map(|s| (s.artifact.id.as_str(), s.retrievals)).collect();
+        assert_eq!(counts, vec![("a", 2), ("b", 1)]);
+    }
+
+    #[test]
+    fn test_low_scoring_subquery_is_excluded_from_merge() {
+        let (_dir, db) = test_db();
+        let settings = Settings {
+            subquery_similarity_floor: Some(0.4),
+            ..Settings::default()
+        };
+        let engine = RagEngine::new(db, &settings).unwrap();
+
+        let merged = engine.merge_subquery_results(vec![
+            vec![search_result("good", 0, 0.8), search_result("shared", 0, 0.5)],
+            // An off-target expansion that only found weak matches
+            vec![search_result("noise-1", 0, 0.3), search_result("noise-2", 0, 0.28)],
+            vec![search_result("shared", 0, 0.6), search_result("other", 0, 0.45)],
+        ]);
+
+        let ids: Vec<&str> = merged.iter().map(|r| r.embedding.artifact_id.as_str()).collect();
+        assert_eq!(ids, vec!["good", "shared", "other"]);
+    }
+
+    #[tokio::test]
+    async fn test_preview_prompt_assembles_full_prompt() {
+        let mut server = mockito::Server::new_async().await;
+        mock_ollama(&mut server, &[0.1, 0.2, 0.3], "garden planting schedule").await;
+
+        let (_dir, db) = test_db();
+        seed_note(
+            &db,
+            Artifact { tags: vec!["garden".to_string()], ..note("a1", "garden.md") },
+            &[("Plant tomatoes after the last frost", &[0.1, 0.2, 0.3])],
+        );
+        let settings = Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        };
+        let engine = RagEngine::new(db.clone(), &settings).unwrap();
+
+        let prompt = engine.preview_prompt("tag:garden When should I plant tomatoes?", &[], None).await.unwrap();
+
+        assert!(prompt.starts_with(SYSTEM_PROMPT));
+        assert!(prompt.contains("## Context from your knowledge base:"));
+        assert!(prompt.contains("Plant tomatoes after the last frost"));
+        assert!(prompt.contains("When should I plant tomatoes?"));
+        assert!(!prompt.contains("tag:garden"));
+        assert!(db.get_chat_history().unwrap().is_empty());
+
+        let concise = engine
+            .preview_prompt("When should I plant tomatoes?", &[], Some(AnswerStyle::Concise))
+            .await
+            .unwrap();
+        assert!(concise.contains(AnswerStyle::Concise.instruction()));
+    }
+
+    #[test]
+    fn test_near_duplicate_chunks_are_collapsed() {
+        let (_dir, db) = test_db();
+        let engine = RagEngine::new(db, &Settings::default()).unwrap();
+
+        let results = [
+            ("v2", vec![1.0, 0.0, 0.01], 0.9),
+            ("v1", vec![1.0, 0.0, 0.02], 0.88),
+            ("other", vec![0.0, 1.0, 0.0], 0.7),
+        ]
+        .into_iter()
+        .map(|(id, vector, similarity)| {
+            let mut result = search_result(id, 0, similarity);
+            result.embedding.embedding = vector;
+            result
+        })
+        .collect();
+
+        let kept = engine.drop_near_duplicates(results);
+
+        let ids: Vec<&str> = kept.iter().map(|r| r.embedding.artifact_id.as_str()).collect();
+        assert_eq!(ids, vec!["v2", "other"]);
+    }
+
+    #[test]
+    fn test_identical_notes_from_two_sources_collapse_to_the_preferred_one() {
+        let (_dir, db) = test_db();
+        for (id, path, hash) in [
+            ("file", "/vault/garden.md", "same-hash"),
+            ("outline", "outline://doc-1", "same-hash"),
+            ("other", "/vault/recipes.md", "other-hash"),
+        ] {
+            seed_note(&db, Artifact { content_hash: hash.to_string(), ..note(id, path) }, &[]);
+        }
+        let settings = Settings {
+            cross_source_dedup: true,
+            source_priority: vec!["outline://".to_string()],
+            ..Settings::default()
+        };
+        let engine = RagEngine::new(db, &settings).unwrap();
+
+        let results = vec![
+            search_result("file", 0, 0.9),
+            search_result("outline", 0, 0.9),
+            search_result("other", 0, 0.6),
+        ];
+
+        let kept = engine.dedupe_across_sources(results).unwrap();
+
+        let ids: Vec<&str> = kept.iter().map(|r| r.embedding.artifact_id.as_str()).collect();
+        assert_eq!(ids, vec!["outline", "other"]);
+    }
+
+    #[tokio::test]
+    async fn test_saved_answer_cites_the_notes_in_its_context() {
+        let mut server = mockito::Server::new_async().await;
+        mock_ollama(&mut server, &[1.0, 0.0], "Ship it in May.").await;
+
+        let (_dir, db) = test_db();
+        let titled = |id: &str, title: Option<&str>| Artifact {
+            title: title.map(String::from),
+            ..note(id, &format!("/vault/{}.md", id))
+        };
+        seed_note(&db, titled("plan", Some("Launch plan")), &[("plan chunk 0", &[1.0, 0.0]), ("plan chunk 1", &[0.9, 0.1])]);
+        seed_note(&db, titled("roadmap", None), &[("roadmap chunk 0", &[0.6, 0.8])]);
+        seed_note(&db, titled("recipes", Some("Recipes")), &[("recipes chunk 0", &[0.0, 1.0])]);
+        let settings = Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        };
+        let engine = RagEngine::new(db.clone(), &settings).unwrap();
+
+        engine.chat("When do we launch?", None, Arc::new(|_, _| {})).await.unwrap();
+
+        // Read back as after a restart
+        let answer = db.get_chat_history().unwrap().pop().unwrap();
+        let cited: Vec<(&str, &str, &str)> = answer
+            .sources
+            .iter()
+            .map(|s| (s.artifact_id.as_str(), s.title.as_str(), s.path.as_str()))
+            .collect();
+        assert_eq!(cited, vec![
+            ("plan", "Launch plan", "/vault/plan.md"),
+            ("roadmap", "/vault/roadmap.md", "/vault/roadmap.md"),
+        ]);
+        assert_eq!(answer.sources[0].chunk, "plan chunk 0");
+    }
+
+    #[tokio::test]
+    async fn test_expanded_queries_are_embedded_concurrently() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/api/generate")
+            .with_body(r#"{"response": "launch date\nrelease timeline", "done": true}"#)
+            .create_async()
+            .await;
+        let mut embedding_mocks = Vec::new();
+        for (prompt, vector) in [
+            ("When do we launch?", [1.0, 0.0]),
+            ("launch date", [0.6, 0.8]),
+            ("release timeline", [0.0, 1.0]),
+        ] {
+            // Once for the concurrent retrieval, once for the sequential reference
+            embedding_mocks.push(
+                server
+                    .mock("POST", "/api/embeddings")
+                    .match_body(mockito::Matcher::PartialJson(serde_json::json!({ "prompt": prompt })))
+                    .with_body(serde_json::json!({ "embedding": vector }).to_string())
+                    .expect(2)
+                    .create_async()
+                    .await,
+            );
+        }
+
+        let (_dir, db) = test_db();
+        seed_note(&db, note("plan", "plan.md"), &[("", &[1.0, 0.0])]);
+        seed_note(&db, note("roadmap", "roadmap.md"), &[("", &[0.6, 0.8])]);
+        seed_note(&db, note("recipes", "recipes.md"), &[("", &[0.0, 1.0])]);
+        let settings = Settings {
+            ollama_endpoint: server.url(),
+            expansion_min_embeddings: 0,
+            ..Settings::default()
+        };
+        let engine = RagEngine::new(db, &settings).unwrap();
+
+        let (_, results) = engine.retrieve("When do we launch?", &[]).await.unwrap();
+
+        // The same retrieval, one expansion at a time
+        let mut per_query = Vec::new();
+        for query in ["When do we launch?", "launch date", "release timeline"] {
+            let embedding = engine.embedding_client.embed_query(query).await.unwrap();
+            per_query.push(engine.vector_store.search(&embedding, MAX_CONTEXT_CHUNKS).unwrap());
+        }
+        let mut sequential = engine.merge_subquery_results(per_query);
+        sort_results(&mut sequential, engine.metric);
+        sequential.retain(|r| engine.is_relevant(r));
+
+        let ids = |results: &[SearchResult]| -> Vec<String> {
+            results.iter().map(|r| r.embedding.id.clone()).collect()
+        };
+        assert_eq!(ids(&results), ids(&sequential));
+        assert!(!results.is_empty());
+        for mock in embedding_mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expansion_skipped_on_tiny_index_and_capped_otherwise() {
+        let mut server = mockito::Server::new_async().await;
+        let generate = server
+            .mock("POST", "/api/generate")
+            .with_body(r#"{"response": "first one\nsecond one\nthird one\nfourth one", "done": true}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let (_dir, db) = test_db();
+        seed_note(&db, note("a", "a.md"), &[("", &[1.0, 0.0][..]); 3]);
+        let settings = Settings {
+            ollama_endpoint: server.url(),
+            max_query_expansions: 2,
+            expansion_min_embeddings: 4,
+            ..Settings::default()
+        };
+
+        // Three chunks: below the floor, so the LLM isn't asked
+        let engine = RagEngine::new(db.clone(), &settings).unwrap();
+        assert_eq!(engine.expand_query("original", &[]).await.unwrap(), vec!["original"]);
+
+        // A fourth chunk reaches the floor
+        seed_note(&db, note("a", "a.md"), &[("", &[1.0, 0.0][..]); 4]);
+        assert_eq!(
+            engine.expand_query("original", &[]).await.unwrap(),
+            vec!["original", "first one", "second one"]
+        );
+        generate.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_pinned_note_is_always_in_context() {
+        let mut server = mockito::Server::new_async().await;
+        mock_ollama(&mut server, &[1.0, 0.0], "").await;
+
+        let (_dir, db) = test_db();
+        // Orthogonal to every query, so it never passes the threshold on its own
+        seed_note(&db, note("style", "style.md"), &[("Write in short, active sentences", &[0.0, 1.0])]);
+        let settings = Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        };
+        let engine = RagEngine::new(db.clone(), &settings).unwrap();
+
+        let prompt = engine.preview_prompt("Draft a project update", &[], None).await.unwrap();
+        assert!(!prompt.contains("Write in short, active sentences"));
+
+        db.set_artifact_pinned("style", true).unwrap();
+        let prompt = engine.preview_prompt("Draft a project update", &[], None).await.unwrap();
+        assert!(prompt.contains("Write in short, active sentences"));
+    }
+
+    #[tokio::test]
+    async fn test_warmup_calls_each_client_once() {
+        let mut server = mockito::Server::new_async().await;
+        let embed_mock = server
+            .mock("POST", "/api/embeddings")
+            .with_body(r#"{"embedding": [0.1, 0.2, 0.3]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let generate_mock = server
+            .mock("POST", "/api/generate")
+            .with_body(r#"{"response": "OK", "done": true}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let (_dir, db) = test_db();
+        let settings = Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        };
+        let engine = RagEngine::new(db, &settings).unwrap();
+
+        let report = engine.warmup().await;
+
+        let names: Vec<&str> = report.steps.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["embedding_model", "chat_model", "vector_index"]);
+        assert!(report.steps.iter().all(|s| s.error.is_none()));
+        embed_mock.assert_async().await;
+        generate_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_fuzzy_fallback_finds_misspelled_term() {
+        let mut server = mockito::Server::new_async().await;
+        mock_ollama(&mut server, &[1.0, 0.0], "").await;
+
+        let (_dir, db) = test_db();
+        // Embedded far from the query, as a rare misspelled token would be
+        seed_note(&db, note("k8s", "k8s.md"), &[("Kubernetes cluster upgrade checklist", &[0.0, 1.0])]);
+
+        let settings = Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        };
+        let engine = RagEngine::new(db.clone(), &settings).unwrap();
+        let prompt = engine.preview_prompt("kubernets upgrade", &[], None).await.unwrap();
+        assert!(!prompt.contains("Kubernetes cluster upgrade checklist"));
+
+        let settings = Settings {
+            fuzzy_keyword_fallback: true,
+            ..settings
+        };
+        let engine = RagEngine::new(db, &settings).unwrap();
+        let prompt = engine.preview_prompt("kubernets upgrade", &[], None).await.unwrap();
+        assert!(prompt.contains("Kubernetes cluster upgrade checklist"));
+    }
+
+    #[tokio::test]
+    async fn test_coverage_report_flags_unanswerable_queries() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/api/embeddings")
+            .match_body(mockito::Matcher::Regex("passport".to_string()))
+            .with_body(r#"{"embedding": [1.0, 0.0]}"#)
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/api/embeddings")
+            .match_body(mockito::Matcher::Regex("tax".to_string()))
+            .with_body(r#"{"embedding": [0.0, 1.0]}"#)
+            .create_async()
+            .await;
+
+        let (_dir, db) = test_db();
+        seed_note(&db, note("travel", "travel.md"), &[("Passport renewal takes six weeks", &[0.9, 0.1])]);
+        let settings = Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        };
+        let engine = RagEngine::new(db, &settings).unwrap();
+
+        let queries = vec!["When should I renew my passport?".to_string(), "What is my tax ID?".to_string()];
+        let report = engine.coverage_report(&queries).await.unwrap();
+
+        assert_eq!(report.len(), 2);
+        assert!(report[0].covered);
+        assert_eq!(report[0].best_match.as_ref().unwrap().path, "travel.md");
+        assert!(!report[1].covered);
+        assert!(report[1].top_score.unwrap() < report[0].top_score.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_summarize_long_note_streams_and_saves() {
+        let mut server = mockito::Server::new_async().await;
+        let partial = server
+            .mock("POST", "/api/generate")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"stream": false})))
+            .with_body(r#"{"response": "- a key point", "done": true}"#)
+            .expect(2)
+            .create_async()
+            .await;
+        let final_summary = server
+            .mock("POST", "/api/generate")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"stream": true})))
+            .with_body("{\"response\":\"The note \",\"done\":false}\n{\"response\":\"in short.\",\"done\":true}\n")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let (_dir, db) = test_db();
+        // Three 4000-char chunks overflow the context and split into two parts
+        let content = "x".repeat(4000);
+        seed_note(
+            &db,
+            Artifact { content_hash: "h1".to_string(), ..note("long", "long.md") },
+            &[(content.as_str(), &[1.0][..]); 3],
+        );
+        let settings = Settings {
+            ollama_endpoint: server.url(),
+            ..Settings::default()
+        };
+        let engine = RagEngine::new(db.clone(), &settings).unwrap();
+
+        let streamed = Arc::new(std::sync::Mutex::new(String::new()));
+        let sink = streamed.clone();
+        let summary = engine
+            .summarize_artifact("long", true, Box::new(move |chunk| sink.lock().unwrap().push_str(chunk)))
+            .await
+            .unwrap();
+
+        assert_eq!(summary, "The note in short.");
+        assert_eq!(*streamed.lock().unwrap(), summary);
+        assert_eq!(db.get_artifact_summary("long", "h1").unwrap().as_deref(), Some("The note in short."));
+
+        // The saved summary is reused without calling the model again
+        let again = engine.summarize_artifact("long", false, Box::new(|_| {})).await.unwrap();
+        assert_eq!(again, summary);
+        partial.assert_async().await;
+        final_summary.assert_async().await;
     }
 }
@@ -0,0 +1,73 @@
+//! Chat history rendered as a Markdown note, clean enough to drop back into
+//! the vault.
+
+use crate::db::ChatMessage;
+use chrono::{TimeZone, Utc};
+use std::path::Path;
+
+/// Render the messages in order, each under a heading with its role and
+/// time, followed by the notes an answer cited
+pub fn render_markdown(messages: &[ChatMessage]) -> String {
+    let mut markdown = String::from("# Chat transcript\n");
+    if messages.is_empty() {
+        markdown.push_str("\n_No messages yet._\n");
+        return markdown;
+    }
+
+    for message in messages {
+        let role = if message.role == "user" { "User" } else { "Assistant" };
+        let time = Utc
+            .timestamp_opt(message.timestamp, 0)
+            .single()
+            .map(|t| t.format(" (%Y-%m-%d %H:%M UTC)").to_string())
+            .unwrap_or_default();
+        markdown.push_str(&format!("\n## {}{}\n\n{}\n", role, time, message.content.trim()));
+
+        if !message.sources.is_empty() {
+            markdown.push_str("\nSources:\n");
+            for source in &message.sources {
+                markdown.push_str(&format!("- [{}](<{}>)\n", source.title, source.path));
+            }
+        }
+    }
+
+    markdown
+}
+
+/// Write the rendered transcript to `path`, replacing any existing file
+pub fn export_markdown(messages: &[ChatMessage], path: &Path) -> std::io::Result<()> {
+    std::fs::write(path, render_markdown(messages))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{Database, SourceCitation};
+
+    #[test]
+    fn test_exported_transcript_keeps_turns_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().join("data")).unwrap();
+        db.insert_chat_message("user", "When do we launch?").unwrap();
+        db.insert_chat_message_with_sources("assistant", "In May.", &[SourceCitation {
+            artifact_id: "plan".to_string(),
+            path: "/vault/Launch plan.md".to_string(),
+            title: "Launch plan".to_string(),
+            chunk: "Ship in May".to_string(),
+            similarity: 0.9,
+        }]).unwrap();
+
+        let path = dir.path().join("chat.md");
+        export_markdown(&db.get_chat_history().unwrap(), &path).unwrap();
+        let markdown = std::fs::read_to_string(&path).unwrap();
+
+        let user = markdown.find("## User").unwrap();
+        let question = markdown.find("When do we launch?").unwrap();
+        let assistant = markdown.find("## Assistant").unwrap();
+        let answer = markdown.find("In May.").unwrap();
+        assert!(user < question && question < assistant && assistant < answer);
+        assert!(markdown.contains("- [Launch plan](</vault/Launch plan.md>)"));
+
+        assert!(render_markdown(&[]).contains("No messages yet"));
+    }
+}
@@ -1,5 +1,10 @@
-use crate::db::{Database, Embedding};
-use std::sync::Arc;
+use crate::db::{Database, Embedding, Settings};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -12,47 +17,398 @@ pub enum VectorError {
 
 pub type VectorResult<T> = Result<T, VectorError>;
 
+/// How query and chunk embeddings are compared
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimilarityMetric {
+    #[default]
+    Cosine,
+    DotProduct,
+    Euclidean,
+}
+
+impl SimilarityMetric {
+    /// Parse a settings value, falling back to cosine for unknown names
+    pub fn parse(name: &str) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "dot" | "dot_product" => SimilarityMetric::DotProduct,
+            "euclidean" | "l2" => SimilarityMetric::Euclidean,
+            _ => SimilarityMetric::Cosine,
+        }
+    }
+
+    /// Cosine and dot product are similarities; Euclidean is a distance
+    pub fn higher_is_better(self) -> bool {
+        !matches!(self, SimilarityMetric::Euclidean)
+    }
+
+    /// Minimum score (or maximum distance) used when no threshold is configured
+    pub fn default_threshold(self) -> f32 {
+        match self {
+            SimilarityMetric::Cosine => 0.25,
+            SimilarityMetric::DotProduct => 0.25,
+            // Distance between unit vectors with a cosine similarity of ~0.25
+            SimilarityMetric::Euclidean => 1.2,
+        }
+    }
+
+    pub fn score(self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            SimilarityMetric::Cosine => cosine_similarity(a, b),
+            SimilarityMetric::DotProduct => dot_product(a, b),
+            SimilarityMetric::Euclidean => euclidean_distance(a, b),
+        }
+    }
+
+    /// Whether a score passes the threshold: a minimum for similarities, a
+    /// maximum for distances
+    pub fn is_relevant(self, score: f32, threshold: f32) -> bool {
+        if self.higher_is_better() {
+            score >= threshold
+        } else {
+            score <= threshold
+        }
+    }
+
+    /// Order two scores best-first
+    pub fn compare(self, a: f32, b: f32) -> Ordering {
+        let ordering = b.partial_cmp(&a).unwrap_or(Ordering::Equal);
+        if self.higher_is_better() { ordering } else { ordering.reverse() }
+    }
+
+    /// Scale a score by a boost factor so that a factor above 1 always helps
+    pub fn boost(self, score: f32, factor: f32) -> f32 {
+        if self.higher_is_better() || factor == 0.0 {
+            score * factor
+        } else {
+            score / factor
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SearchResult {
     pub embedding: Embedding,
+    /// Score under the store's metric (a distance for Euclidean)
+    pub similarity: f32,
+}
+
+/// A search result as returned to the frontend
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub artifact_id: String,
+    pub path: String,
+    pub title: Option<String>,
+    pub chunk_index: i32,
+    pub content: String,
+    pub similarity: f32,
+    /// Significant words shared with the query, when an explanation was asked for
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub matched_terms: Vec<String>,
+}
+
+/// The search hits of one note, collapsed to its best-matching chunk
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteHit {
+    pub artifact_id: String,
+    pub path: String,
+    pub title: Option<String>,
+    /// The best-matching chunk and its score
+    pub chunk_index: i32,
+    pub snippet: String,
+    pub similarity: f32,
+    /// How many of the note's chunks were among the hits
+    pub matching_chunks: usize,
+}
+
+/// Collapse hits to one per note, best note first
+pub fn group_hits_by_note(hits: Vec<SearchHit>, metric: SimilarityMetric) -> Vec<NoteHit> {
+    let mut notes: Vec<NoteHit> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for hit in hits {
+        match positions.get(&hit.artifact_id) {
+            Some(&position) => {
+                let note = &mut notes[position];
+                note.matching_chunks += 1;
+                if metric.compare(hit.similarity, note.similarity) == Ordering::Less {
+                    note.chunk_index = hit.chunk_index;
+                    note.snippet = hit.content;
+                    note.similarity = hit.similarity;
+                }
+            }
+            None => {
+                positions.insert(hit.artifact_id.clone(), notes.len());
+                notes.push(NoteHit {
+                    artifact_id: hit.artifact_id,
+                    path: hit.path,
+                    title: hit.title,
+                    chunk_index: hit.chunk_index,
+                    snippet: hit.content,
+                    similarity: hit.similarity,
+                    matching_chunks: 1,
+                });
+            }
+        }
+    }
+    notes.sort_by(|a, b| metric.compare(a.similarity, b.similarity));
+    notes
+}
+
+/// How semantically close two notes are
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteComparison {
+    /// Cosine similarity of the two notes' mean chunk embeddings
     pub similarity: f32,
+    /// The closest pair of chunks, one from each note
+    pub best_pair: ChunkPair,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkPair {
+    pub chunk_index_a: i32,
+    pub content_a: String,
+    pub chunk_index_b: i32,
+    pub content_b: String,
+    pub similarity: f32,
+}
+
+/// Sort results best-first under the given metric
+pub fn sort_results(results: &mut [SearchResult], metric: SimilarityMetric) {
+    results.sort_by(|a, b| metric.compare(a.similarity, b.similarity));
+}
+
+/// Path prefixes of the sources switched off in settings
+pub fn disabled_sources(settings: &Settings) -> Vec<String> {
+    settings
+        .enabled_sources
+        .iter()
+        .filter(|(prefix, enabled)| !**enabled && !prefix.is_empty())
+        .map(|(prefix, _)| prefix.clone())
+        .collect()
+}
+
+/// Rows read from the database at a time by a full search
+const SEARCH_BATCH_SIZE: usize = 1024;
+
+/// Query embeddings are rounded to this many steps per unit before hashing,
+/// so float noise from re-embedding the same text still hits the cache
+const CACHE_KEY_PRECISION: f32 = 10_000.0;
+
+/// Least-recently-used cache of search results, dropped wholesale when the
+/// stored embeddings change
+#[derive(Debug, Default)]
+struct SearchCache {
+    capacity: usize,
+    /// Embeddings version the cached results were computed against
+    version: u64,
+    /// Most recently used last
+    entries: VecDeque<(u64, Vec<SearchResult>)>,
+    hits: usize,
+}
+
+impl SearchCache {
+    fn key(query_embedding: &[f32], limit: usize) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        limit.hash(&mut hasher);
+        for value in query_embedding {
+            ((value * CACHE_KEY_PRECISION).round() as i64).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    fn get(&mut self, key: u64, version: u64) -> Option<Vec<SearchResult>> {
+        if version != self.version {
+            self.entries.clear();
+            self.version = version;
+            return None;
+        }
+        let position = self.entries.iter().position(|(k, _)| *k == key)?;
+        let entry = self.entries.remove(position)?;
+        let results = entry.1.clone();
+        self.entries.push_back(entry);
+        self.hits += 1;
+        Some(results)
+    }
+
+    fn put(&mut self, key: u64, version: u64, results: Vec<SearchResult>) {
+        if version != self.version {
+            self.entries.clear();
+            self.version = version;
+        }
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((key, results));
+    }
 }
 
+/// Similarity search over the stored embeddings. There is no separate
+/// in-memory index to rebuild or patch: searches read the vectors from the
+/// database in batches, so chunks added or removed by ingest are seen by the
+/// next search, and only the result cache is reset when they change.
 pub struct VectorStore {
     db: Arc<Database>,
+    metric: SimilarityMetric,
+    /// Artifacts under these path prefixes are kept but never returned
+    disabled_sources: Vec<String>,
+    /// Recent `search` results; `None` when caching is disabled
+    cache: Option<Mutex<SearchCache>>,
 }
 
 impl VectorStore {
     pub fn new(db: Arc<Database>) -> Self {
-        Self { db }
+        Self::with_metric(db, SimilarityMetric::default())
+    }
+
+    pub fn with_metric(db: Arc<Database>, metric: SimilarityMetric) -> Self {
+        Self { db, metric, disabled_sources: Vec::new(), cache: None }
+    }
+
+    /// Cache the results of the last `size` distinct searches (0 disables)
+    pub fn with_cache_size(mut self, size: usize) -> Self {
+        self.cache = (size > 0).then(|| Mutex::new(SearchCache { capacity: size, ..SearchCache::default() }));
+        self
+    }
+
+    /// Exclude artifacts whose path starts with any of `prefixes` (e.g. `outline://`)
+    pub fn with_disabled_sources(mut self, prefixes: Vec<String>) -> Self {
+        self.disabled_sources = prefixes;
+        self
+    }
+
+    /// Ids of artifacts belonging to a disabled source
+    pub fn disabled_artifact_ids(&self) -> VectorResult<HashSet<String>> {
+        if self.disabled_sources.is_empty() {
+            return Ok(HashSet::new());
+        }
+        Ok(self.db.get_all_artifacts()?
+            .into_iter()
+            .filter(|a| self.disabled_sources.iter().any(|prefix| a.path.starts_with(prefix.as_str())))
+            .map(|a| a.id)
+            .collect())
+    }
+
+    fn enabled(&self, embeddings: Vec<Embedding>) -> VectorResult<Vec<Embedding>> {
+        let disabled = self.disabled_artifact_ids()?;
+        if disabled.is_empty() {
+            return Ok(embeddings);
+        }
+        Ok(embeddings.into_iter().filter(|e| !disabled.contains(&e.artifact_id)).collect())
     }
 
     /// Search for similar embeddings using cosine similarity
     pub fn search(&self, query_embedding: &[f32], limit: usize) -> VectorResult<Vec<SearchResult>> {
-        let embeddings = self.db.get_all_embeddings()?;
-        
-        if embeddings.is_empty() {
-            return Ok(Vec::new());
+        let Some(cache) = &self.cache else {
+            return self.search_batched(query_embedding, limit, SEARCH_BATCH_SIZE);
+        };
+
+        let key = SearchCache::key(query_embedding, limit);
+        let version = self.db.embeddings_version();
+        if let Some(results) = cache.lock().ok().and_then(|mut cache| cache.get(key, version)) {
+            log::debug!("Search served from cache");
+            return Ok(results);
         }
 
-        let mut results: Vec<SearchResult> = embeddings
+        let results = self.search_batched(query_embedding, limit, SEARCH_BATCH_SIZE)?;
+        if let Ok(mut cache) = cache.lock() {
+            cache.put(key, version, results.clone());
+        }
+        Ok(results)
+    }
+
+    /// Score stored embeddings batch by batch, keeping only the best `limit`,
+    /// so memory stays flat however large the index is
+    fn search_batched(&self, query_embedding: &[f32], limit: usize, batch_size: usize) -> VectorResult<Vec<SearchResult>> {
+        let disabled = self.disabled_artifact_ids()?;
+        let mut top = TopResults::new(limit, self.metric);
+        self.db.for_each_embedding(batch_size, |embedding| {
+            if !disabled.contains(&embedding.artifact_id) {
+                let similarity = self.metric.score(query_embedding, &embedding.embedding);
+                top.push(SearchResult { embedding, similarity });
+            }
+        })?;
+        Ok(top.into_sorted())
+    }
+
+    /// Search only the chunks belonging to the given artifacts
+    pub fn search_within(
+        &self,
+        query_embedding: &[f32],
+        limit: usize,
+        artifact_ids: &HashSet<String>,
+    ) -> VectorResult<Vec<SearchResult>> {
+        let embeddings: Vec<Embedding> = self.db.get_all_embeddings()?
             .into_iter()
-            .map(|emb| {
-                let similarity = cosine_similarity(query_embedding, &emb.embedding);
-                SearchResult {
-                    embedding: emb,
-                    similarity,
-                }
-            })
+            .filter(|emb| artifact_ids.contains(&emb.artifact_id))
             .collect();
+        let embeddings = self.enabled(embeddings)?;
+        Ok(rank_embeddings(embeddings, query_embedding, limit, self.metric))
+    }
 
-        // Sort by similarity (descending)
-        results.sort_by(|a, b| b.similarity.partial_cmp(&a.similarity).unwrap_or(std::cmp::Ordering::Equal));
+    /// Search only the chunks of `artifact_ids`, loading just those from the database
+    pub fn search_within_ids(
+        &self,
+        query_embedding: &[f32],
+        artifact_ids: &[String],
+        limit: usize,
+    ) -> VectorResult<Vec<SearchResult>> {
+        let embeddings = self.enabled(self.db.get_embeddings_for_artifacts(artifact_ids)?)?;
+        Ok(rank_embeddings(embeddings, query_embedding, limit, self.metric))
+    }
 
-        // Take top N results
-        results.truncate(limit);
+    /// Search only chunks of notes modified at or after `since` (unix seconds)
+    pub fn search_modified_since(
+        &self,
+        query_embedding: &[f32],
+        limit: usize,
+        since: i64,
+    ) -> VectorResult<Vec<SearchResult>> {
+        let embeddings = self.enabled(self.db.get_embeddings_modified_since(since)?)?;
+        Ok(rank_embeddings(embeddings, query_embedding, limit, self.metric))
+    }
 
-        Ok(results)
+    /// Read every embedding once so the first search hits a warm cache,
+    /// returning how many are indexed
+    pub fn warm(&self) -> VectorResult<usize> {
+        let mut count = 0;
+        self.db.for_each_embedding(SEARCH_BATCH_SIZE, |_| count += 1)?;
+        Ok(count)
+    }
+
+    /// Compare two notes using their stored chunk embeddings. Always uses
+    /// cosine similarity so scores are comparable whatever the search metric
+    pub fn compare_artifacts(&self, id_a: &str, id_b: &str) -> VectorResult<NoteComparison> {
+        let chunks_a = self.db.get_artifact_chunks(id_a)?;
+        let chunks_b = self.db.get_artifact_chunks(id_b)?;
+        if chunks_a.is_empty() || chunks_b.is_empty() {
+            return Err(VectorError::NoEmbeddings);
+        }
+
+        let mut best: Option<(&Embedding, &Embedding, f32)> = None;
+        for a in &chunks_a {
+            for b in &chunks_b {
+                let similarity = cosine_similarity(&a.embedding, &b.embedding);
+                if best.map(|(_, _, s)| similarity > s).unwrap_or(true) {
+                    best = Some((a, b, similarity));
+                }
+            }
+        }
+        let (a, b, similarity) = best.ok_or(VectorError::NoEmbeddings)?;
+
+        Ok(NoteComparison {
+            similarity: cosine_similarity(&centroid(&chunks_a), &centroid(&chunks_b)),
+            best_pair: ChunkPair {
+                chunk_index_a: a.chunk_index,
+                content_a: a.content.clone(),
+                chunk_index_b: b.chunk_index,
+                content_b: b.content.clone(),
+                similarity,
+            },
+        })
     }
 
     /// Insert a new embedding
@@ -68,6 +424,106 @@ impl VectorStore {
     }
 }
 
+/// A result ranked for `TopResults`: better scores order first, and ties
+/// keep the earlier result so the outcome matches a stable sort
+struct Ranked {
+    result: SearchResult,
+    seq: usize,
+    metric: SimilarityMetric,
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.metric
+            .compare(self.result.similarity, other.result.similarity)
+            .then(self.seq.cmp(&other.seq))
+    }
+}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked {}
+
+/// The best `limit` results pushed so far; the worst sits on top of the
+/// heap, ready to be evicted
+struct TopResults {
+    limit: usize,
+    metric: SimilarityMetric,
+    seen: usize,
+    heap: BinaryHeap<Ranked>,
+}
+
+impl TopResults {
+    fn new(limit: usize, metric: SimilarityMetric) -> Self {
+        Self { limit, metric, seen: 0, heap: BinaryHeap::with_capacity(limit + 1) }
+    }
+
+    fn push(&mut self, result: SearchResult) {
+        if self.limit == 0 {
+            return;
+        }
+        self.heap.push(Ranked { result, seq: self.seen, metric: self.metric });
+        self.seen += 1;
+        if self.heap.len() > self.limit {
+            self.heap.pop();
+        }
+    }
+
+    /// Best first
+    fn into_sorted(self) -> Vec<SearchResult> {
+        self.heap.into_sorted_vec().into_iter().map(|r| r.result).collect()
+    }
+}
+
+/// Score embeddings against the query and return the best `limit` under the metric
+fn rank_embeddings(
+    embeddings: Vec<Embedding>,
+    query_embedding: &[f32],
+    limit: usize,
+    metric: SimilarityMetric,
+) -> Vec<SearchResult> {
+    let mut results: Vec<SearchResult> = embeddings
+        .into_iter()
+        .map(|emb| {
+            let similarity = metric.score(query_embedding, &emb.embedding);
+            SearchResult {
+                embedding: emb,
+                similarity,
+            }
+        })
+        .collect();
+
+    // Sort best-first
+    sort_results(&mut results, metric);
+
+    // Take top N results
+    results.truncate(limit);
+
+    results
+}
+
+/// Element-wise mean of the chunks' embeddings
+fn centroid(chunks: &[Embedding]) -> Vec<f32> {
+    let dimension = chunks.first().map(|c| c.embedding.len()).unwrap_or(0);
+    let mut sum = vec![0.0; dimension];
+    for chunk in chunks.iter().filter(|c| c.embedding.len() == dimension) {
+        for (total, value) in sum.iter_mut().zip(&chunk.embedding) {
+            *total += value;
+        }
+    }
+    sum.iter().map(|total| total / chunks.len() as f32).collect()
+}
+
 /// Calculate cosine similarity between two vectors
 fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     if a.len() != b.len() || a.is_empty() {
@@ -85,10 +541,54 @@ fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     dot_product / (magnitude_a * magnitude_b)
 }
 
+/// Calculate the dot product between two vectors
+fn dot_product(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+}
+
+/// Calculate the Euclidean distance between two vectors
+fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return f32::INFINITY;
+    }
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_chunks_of_one_note_group_into_one_hit() {
+        let hit = |artifact_id: &str, chunk_index: i32, similarity: f32| SearchHit {
+            artifact_id: artifact_id.to_string(),
+            path: format!("/vault/{}.md", artifact_id),
+            title: None,
+            chunk_index,
+            content: format!("{} chunk {}", artifact_id, chunk_index),
+            similarity,
+            matched_terms: Vec::new(),
+        };
+
+        let notes = group_hits_by_note(
+            vec![hit("a", 0, 0.6), hit("b", 0, 0.7), hit("a", 3, 0.9)],
+            SimilarityMetric::Cosine,
+        );
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0].artifact_id, "a");
+        assert_eq!(notes[0].similarity, 0.9);
+        assert_eq!((notes[0].chunk_index, notes[0].snippet.as_str()), (3, "a chunk 3"));
+        assert_eq!(notes[0].matching_chunks, 2);
+        assert_eq!((notes[1].artifact_id.as_str(), notes[1].matching_chunks), ("b", 1));
+
+        // A smaller distance is the better match under Euclidean
+        let notes = group_hits_by_note(vec![hit("a", 0, 0.6), hit("a", 1, 0.2)], SimilarityMetric::Euclidean);
+        assert_eq!((notes[0].chunk_index, notes[0].similarity), (1, 0.2));
+    }
+
     #[test]
     fn test_cosine_similarity_identical() {
         let a = vec![1.0, 2.0, 3.0];
@@ -112,5 +612,238 @@ mod tests {
         let sim = cosine_similarity(&a, &b);
         assert!((sim + 1.0).abs() < 0.0001);
     }
-}
 
+    #[test]
+    fn test_cosine_relevance_is_a_minimum_score() {
+        let metric = SimilarityMetric::Cosine;
+        let threshold = metric.default_threshold();
+        assert!(metric.is_relevant(metric.score(&[1.0, 0.0], &[1.0, 0.1]), threshold));
+        assert!(!metric.is_relevant(metric.score(&[1.0, 0.0], &[0.0, 1.0]), threshold));
+    }
+
+    #[test]
+    fn test_dot_product_relevance_is_a_minimum_score() {
+        let metric = SimilarityMetric::DotProduct;
+        assert!(metric.is_relevant(metric.score(&[0.6, 0.8], &[0.6, 0.8]), 0.5));
+        assert!(!metric.is_relevant(metric.score(&[0.6, 0.8], &[0.8, -0.6]), 0.5));
+    }
+
+    #[test]
+    fn test_euclidean_relevance_is_a_maximum_distance() {
+        let metric = SimilarityMetric::Euclidean;
+        let threshold = metric.default_threshold();
+        let near = metric.score(&[1.0, 0.0], &[0.9, 0.1]);
+        let far = metric.score(&[1.0, 0.0], &[-1.0, 0.0]);
+        assert!(metric.is_relevant(near, threshold));
+        assert!(!metric.is_relevant(far, threshold));
+        assert_eq!(metric.compare(near, far), Ordering::Less);
+    }
+
+    #[test]
+    fn test_search_modified_since_skips_older_notes() {
+        use crate::db::Artifact;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(dir.path().to_path_buf()).unwrap());
+        for (id, last_modified, vector) in [
+            ("old", 1_000, vec![1.0, 0.0]),
+            ("recent", 5_000, vec![0.6, 0.8]),
+            ("newest", 9_000, vec![0.0, 1.0]),
+        ] {
+            db.upsert_artifact(&Artifact {
+                id: id.to_string(),
+                path: format!("{}.md", id),
+                last_modified,
+                ..Default::default()
+            }).unwrap();
+            db.insert_embedding(&Embedding {
+                id: format!("{}#0", id),
+                artifact_id: id.to_string(),
+                chunk_index: 0,
+                content: String::new(),
+                embedding: vector,
+            }).unwrap();
+        }
+        let store = VectorStore::new(db);
+
+        // "old" is the best match but falls outside the window
+        let results = store.search_modified_since(&[1.0, 0.0], 10, 5_000).unwrap();
+
+        let ids: Vec<&str> = results.iter().map(|r| r.embedding.artifact_id.as_str()).collect();
+        assert_eq!(ids, vec!["recent", "newest"]);
+    }
+
+    #[test]
+    fn test_search_within_ids_is_confined_to_them() {
+        use crate::db::Artifact;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(dir.path().to_path_buf()).unwrap());
+        for (id, vector) in [("a", vec![1.0, 0.0]), ("b", vec![0.6, 0.8]), ("c", vec![0.0, 1.0])] {
+            db.upsert_artifact(&Artifact { id: id.to_string(), path: format!("{}.md", id), ..Default::default() })
+                .unwrap();
+            db.insert_embedding(&Embedding {
+                id: format!("{}#0", id),
+                artifact_id: id.to_string(),
+                chunk_index: 0,
+                content: String::new(),
+                embedding: vector,
+            }).unwrap();
+        }
+        let store = VectorStore::new(db);
+
+        // "a" is the best match overall but wasn't asked for
+        let ids = vec!["c".to_string(), "b".to_string()];
+        let results = store.search_within_ids(&[1.0, 0.0], &ids, 10).unwrap();
+
+        let ids: Vec<&str> = results.iter().map(|r| r.embedding.artifact_id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "c"]);
+        assert!(store.search_within_ids(&[1.0, 0.0], &[], 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_batched_search_matches_full_load() {
+        use crate::db::Artifact;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(dir.path().to_path_buf()).unwrap());
+        db.upsert_artifact(&Artifact { id: "a".to_string(), path: "a.md".to_string(), ..Default::default() }).unwrap();
+        for i in 0..50 {
+            let vector: Vec<f32> = (0..8).map(|d| ((i * 8 + d) as f32 * 0.731).sin()).collect();
+            db.insert_embedding(&Embedding {
+                id: format!("a#{}", i),
+                artifact_id: "a".to_string(),
+                chunk_index: i,
+                content: format!("chunk {}", i),
+                embedding: vector,
+            }).unwrap();
+        }
+        let query: Vec<f32> = (0..8).map(|d| (d as f32 * 1.3).cos()).collect();
+
+        for metric in [SimilarityMetric::Cosine, SimilarityMetric::DotProduct, SimilarityMetric::Euclidean] {
+            let store = VectorStore::with_metric(db.clone(), metric);
+            let full = rank_embeddings(db.get_all_embeddings().unwrap(), &query, 10, metric);
+            // Batches smaller than the index and the limit
+            let batched = store.search_batched(&query, 10, 7).unwrap();
+
+            let ids = |results: &[SearchResult]| results.iter().map(|r| r.embedding.id.clone()).collect::<Vec<_>>();
+            assert_eq!(ids(&batched), ids(&full), "{:?}", metric);
+            assert_eq!(ids(&store.search(&query, 10).unwrap()), ids(&full));
+        }
+    }
+
+    #[test]
+    fn test_overlapping_notes_compare_closer_than_unrelated() {
+        use crate::db::Artifact;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(dir.path().to_path_buf()).unwrap());
+        for (id, vectors) in [
+            ("rust", vec![vec![1.0, 0.1, 0.0], vec![0.8, 0.3, 0.0]]),
+            ("cargo", vec![vec![0.9, 0.2, 0.1], vec![0.1, 0.0, 1.0]]),
+            ("baking", vec![vec![0.0, 0.1, 1.0], vec![0.0, 1.0, 0.2]]),
+        ] {
+            db.upsert_artifact(&Artifact { id: id.to_string(), path: format!("{}.md", id), ..Default::default() }).unwrap();
+            for (i, vector) in vectors.into_iter().enumerate() {
+                db.insert_embedding(&Embedding {
+                    id: format!("{}#{}", id, i),
+                    artifact_id: id.to_string(),
+                    chunk_index: i as i32,
+                    content: format!("{} chunk {}", id, i),
+                    embedding: vector,
+                }).unwrap();
+            }
+        }
+        let store = VectorStore::new(db);
+
+        let overlapping = store.compare_artifacts("rust", "cargo").unwrap();
+        let unrelated = store.compare_artifacts("rust", "baking").unwrap();
+
+        assert!(overlapping.similarity > unrelated.similarity);
+        assert_eq!(overlapping.best_pair.content_a, "rust chunk 0");
+        assert_eq!(overlapping.best_pair.content_b, "cargo chunk 0");
+        assert!(matches!(store.compare_artifacts("rust", "missing"), Err(VectorError::NoEmbeddings)));
+    }
+
+    #[test]
+    fn test_disabled_source_is_excluded_from_search() {
+        use crate::db::Artifact;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(dir.path().to_path_buf()).unwrap());
+        for (id, path) in [("doc", "outline://doc-1"), ("note", "/vault/note.md")] {
+            db.upsert_artifact(&Artifact {
+                id: id.to_string(),
+                path: path.to_string(),
+                ..Default::default()
+            }).unwrap();
+            db.insert_embedding(&Embedding {
+                id: format!("{}#0", id),
+                artifact_id: id.to_string(),
+                chunk_index: 0,
+                content: String::new(),
+                embedding: vec![1.0, 0.0],
+            }).unwrap();
+        }
+        let settings = Settings {
+            enabled_sources: [("outline://".to_string(), false)].into_iter().collect(),
+            ..Settings::default()
+        };
+
+        let store = VectorStore::new(db.clone()).with_disabled_sources(disabled_sources(&settings));
+        let results = store.search(&[1.0, 0.0], 10).unwrap();
+
+        let ids: Vec<&str> = results.iter().map(|r| r.embedding.artifact_id.as_str()).collect();
+        assert_eq!(ids, vec!["note"]);
+        // The disabled source's data is untouched
+        assert_eq!(db.count_embeddings().unwrap(), 2);
+        assert_eq!(VectorStore::new(db).search(&[1.0, 0.0], 10).unwrap().len(), 2);
+    }
+
+    fn cache_hits(store: &VectorStore) -> usize {
+        store.cache.as_ref().map_or(0, |cache| cache.lock().unwrap().hits)
+    }
+
+    #[test]
+    fn test_search_cache_hits_until_embeddings_change() {
+        use crate::db::Artifact;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(dir.path().to_path_buf()).unwrap());
+        db.upsert_artifact(&Artifact { id: "a".to_string(), path: "a.md".to_string(), ..Default::default() })
+            .unwrap();
+        let embed = |id: &str, vector: Vec<f32>| Embedding {
+            id: id.to_string(),
+            artifact_id: "a".to_string(),
+            chunk_index: 0,
+            content: String::new(),
+            embedding: vector,
+        };
+        db.insert_embedding(&embed("a#0", vec![1.0, 0.0])).unwrap();
+        let store = VectorStore::new(db.clone()).with_cache_size(8);
+
+        assert_eq!(store.search(&[1.0, 0.0], 5).unwrap().len(), 1);
+        assert_eq!(cache_hits(&store), 0);
+        // Same query, up to float noise
+        assert_eq!(store.search(&[1.000_000_1, 0.0], 5).unwrap().len(), 1);
+        assert_eq!(cache_hits(&store), 1);
+
+        // New embeddings invalidate the cached results
+        db.insert_embedding(&embed("a#1", vec![0.8, 0.6])).unwrap();
+        assert_eq!(store.search(&[1.0, 0.0], 5).unwrap().len(), 2);
+        assert_eq!(cache_hits(&store), 1);
+
+        // Disabled cache never hits
+        let uncached = VectorStore::new(db);
+        uncached.search(&[1.0, 0.0], 5).unwrap();
+        uncached.search(&[1.0, 0.0], 5).unwrap();
+        assert_eq!(cache_hits(&uncached), 0);
+    }
+
+    #[test]
+    fn test_parse_metric_names() {
+        assert_eq!(SimilarityMetric::parse("dot_product"), SimilarityMetric::DotProduct);
+        assert_eq!(SimilarityMetric::parse("Euclidean"), SimilarityMetric::Euclidean);
+        assert_eq!(SimilarityMetric::parse("unknown"), SimilarityMetric::Cosine);
+    }
+}
@@ -1,8 +1,13 @@
+use crate::db::Settings;
+use crate::parser::is_canvas_path;
 use notify::{RecommendedWatcher, RecursiveMode};
 use notify_debouncer_mini::{new_debouncer, DebouncedEvent, Debouncer};
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Receiver};
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -22,14 +27,113 @@ pub enum FileEvent {
     Deleted(PathBuf),
 }
 
+impl FileEvent {
+    pub fn path(&self) -> &Path {
+        match self {
+            FileEvent::Created(p) | FileEvent::Modified(p) | FileEvent::Deleted(p) => p,
+        }
+    }
+}
+
+/// Shared switch that holds back file events while indexing is paused.
+/// Events arriving while paused are queued (latest per path) and handed
+/// back on resume so nothing is lost.
+#[derive(Clone, Default)]
+pub struct IndexingGate {
+    paused: Arc<AtomicBool>,
+    pending: Arc<Mutex<Vec<FileEvent>>>,
+}
+
+impl IndexingGate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Resume indexing, returning the events queued while paused
+    pub fn resume(&self) -> Vec<FileEvent> {
+        self.paused.store(false, Ordering::SeqCst);
+        self.pending
+            .lock()
+            .map(|mut pending| std::mem::take(&mut *pending))
+            .unwrap_or_default()
+    }
+
+    /// Pass events through when running; queue them and return nothing when paused
+    pub fn admit(&self, events: Vec<FileEvent>) -> Vec<FileEvent> {
+        if !self.is_paused() {
+            return events;
+        }
+
+        if let Ok(mut pending) = self.pending.lock() {
+            for event in events {
+                pending.retain(|e| e.path() != event.path());
+                pending.push(event);
+            }
+        }
+        Vec::new()
+    }
+}
+
+/// Collapses bursts of events for the same file (autosaving editors) into a
+/// single reindex. An event is held until its path has been quiet for the
+/// debounce interval; a newer event for the path replaces it and restarts
+/// the wait, so delete-then-recreate resolves to one reindex.
+pub struct EventCoalescer {
+    debounce: Duration,
+    pending: HashMap<PathBuf, (FileEvent, Instant)>,
+}
+
+impl EventCoalescer {
+    pub fn new(debounce: Duration) -> Self {
+        Self {
+            debounce,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Record events seen at `now`
+    pub fn push(&mut self, events: Vec<FileEvent>, now: Instant) {
+        for event in events {
+            self.pending.insert(event.path().to_path_buf(), (event, now));
+        }
+    }
+
+    /// Take the events whose path has been quiet for the debounce interval
+    pub fn ready(&mut self, now: Instant) -> Vec<FileEvent> {
+        let debounce = self.debounce;
+        let ready_paths: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(_, (_, seen))| now.duration_since(*seen) >= debounce)
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        ready_paths
+            .into_iter()
+            .filter_map(|path| self.pending.remove(&path).map(|(event, _)| event))
+            .collect()
+    }
+}
+
 pub struct FileWatcher {
     _watcher: Debouncer<RecommendedWatcher>,
     receiver: Receiver<Result<Vec<DebouncedEvent>, notify::Error>>,
     watched_path: PathBuf,
+    coalescer: EventCoalescer,
 }
 
 impl FileWatcher {
-    pub fn new(path: &Path) -> WatcherResult<Self> {
+    /// Watch `path`, holding back each file's events until it has been quiet
+    /// for the configured reindex debounce on top of the 500ms filesystem one
+    pub fn new(path: &Path, settings: &Settings) -> WatcherResult<Self> {
         let (tx, rx) = channel();
         
         let mut debouncer = new_debouncer(
@@ -43,10 +147,12 @@ impl FileWatcher {
             _watcher: debouncer,
             receiver: rx,
             watched_path: path.to_path_buf(),
+            coalescer: EventCoalescer::new(Duration::from_millis(settings.reindex_debounce_ms)),
         })
     }
 
-    pub fn try_recv_events(&self) -> Vec<FileEvent> {
+    /// Events for files that have settled, at most one per path
+    pub fn try_recv_events(&mut self) -> Vec<FileEvent> {
         let mut events = Vec::new();
         
         while let Ok(result) = self.receiver.try_recv() {
@@ -59,14 +165,16 @@ impl FileWatcher {
             }
         }
         
-        events
+        let now = Instant::now();
+        self.coalescer.push(events, now);
+        self.coalescer.ready(now)
     }
 
     fn process_event(&self, event: DebouncedEvent) -> Option<FileEvent> {
         let path = event.path;
         
-        // Only process markdown files
-        if !is_markdown_file(&path) {
+        // Only process files we know how to index
+        if !is_indexable_file(&path) {
             return None;
         }
         
@@ -90,32 +198,101 @@ fn is_markdown_file(path: &Path) -> bool {
         .unwrap_or(false)
 }
 
-/// Scan a directory for all markdown files
-pub fn scan_directory(path: &Path) -> Vec<PathBuf> {
+fn is_indexable_file(path: &Path) -> bool {
+    is_markdown_file(path) || is_canvas_path(path)
+}
+
+/// Directories deeper than this below the vault root are not scanned
+const MAX_SCAN_DEPTH: usize = 64;
+
+/// Options controlling how a vault directory is walked
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// Follow symlinked files and directories instead of skipping them
+    pub follow_symlinks: bool,
+    /// Hidden directories (e.g. `.notes`) scanned anyway; other dot-directories are skipped
+    pub allowed_hidden_dirs: Vec<String>,
+    /// Folder deleted notes are moved to (Obsidian's `.trash`); never scanned
+    pub trash_folder: String,
+}
+
+impl ScanOptions {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            follow_symlinks: settings.follow_symlinks,
+            allowed_hidden_dirs: settings.allowed_hidden_dirs.clone(),
+            trash_folder: settings.trash_folder.clone(),
+        }
+    }
+
+    /// The trash folder is always skipped; other hidden directories unless allowlisted by name
+    fn skips_dir(&self, name: &str) -> bool {
+        name == self.trash_folder
+            || (name.starts_with('.') && !self.allowed_hidden_dirs.iter().any(|allowed| allowed == name))
+    }
+
+    /// Whether a path relative to the vault root lies inside the trash folder
+    pub fn is_trashed(&self, relative: &Path) -> bool {
+        !self.trash_folder.is_empty()
+            && relative.components().any(|c| c.as_os_str() == self.trash_folder.as_str())
+    }
+}
+
+/// Scan a directory for all markdown and canvas files
+pub fn scan_directory(path: &Path, options: &ScanOptions) -> Vec<PathBuf> {
     let mut files = Vec::new();
-    
+    let mut visited = HashSet::new();
+    scan_directory_inner(path, options, 0, &mut visited, &mut files);
+    files
+}
+
+fn scan_directory_inner(
+    path: &Path,
+    options: &ScanOptions,
+    depth: usize,
+    visited: &mut HashSet<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) {
+    if depth > MAX_SCAN_DEPTH {
+        log::warn!("Not scanning {:?}: deeper than {} levels", path, MAX_SCAN_DEPTH);
+        return;
+    }
+
+    // Track real paths so symlink loops can't recurse forever
+    match std::fs::canonicalize(path) {
+        Ok(real_path) => {
+            if !visited.insert(real_path) {
+                return;
+            }
+        }
+        Err(_) => return,
+    }
+
     if let Ok(entries) = std::fs::read_dir(path) {
         for entry in entries.flatten() {
             let entry_path = entry.path();
+
+            let is_symlink = entry.file_type().map(|t| t.is_symlink()).unwrap_or(false);
+            if is_symlink && !options.follow_symlinks {
+                continue;
+            }
             
             if entry_path.is_dir() {
-                // Skip hidden directories
+                // Skip hidden directories that aren't allowlisted
                 if entry_path.file_name()
                     .and_then(|n| n.to_str())
-                    .map(|n| n.starts_with('.'))
+                    .map(|n| options.skips_dir(n))
                     .unwrap_or(false)
                 {
                     continue;
                 }
                 // Recursively scan subdirectories
-                files.extend(scan_directory(&entry_path));
-            } else if is_markdown_file(&entry_path) {
+                scan_directory_inner(&entry_path, options, depth + 1, visited, files);
+            } else if is_indexable_file(&entry_path) {
                 files.push(entry_path);
             }
         }
     }
-    
-    files
 }
 
 #[cfg(test)]
@@ -129,5 +306,91 @@ mod tests {
         assert!(!is_markdown_file(Path::new("test.txt")));
         assert!(!is_markdown_file(Path::new("test")));
     }
+
+    #[test]
+    fn test_indexing_gate_queues_while_paused() {
+        let gate = IndexingGate::new();
+        let note = PathBuf::from("vault/note.md");
+
+        gate.pause();
+        let admitted = gate.admit(vec![
+            FileEvent::Modified(note.clone()),
+            FileEvent::Modified(note.clone()),
+            FileEvent::Deleted(PathBuf::from("vault/old.md")),
+        ]);
+        assert!(admitted.is_empty());
+
+        let resumed = gate.resume();
+        assert_eq!(resumed.len(), 2);
+        assert_eq!(resumed[0].path(), note.as_path());
+        assert!(!gate.is_paused());
+
+        let admitted = gate.admit(vec![FileEvent::Modified(note)]);
+        assert_eq!(admitted.len(), 1);
+        assert!(gate.resume().is_empty());
+    }
+
+    #[test]
+    fn test_coalescer_schedules_one_reindex_per_burst() {
+        let mut coalescer = EventCoalescer::new(Duration::from_secs(2));
+        let note = PathBuf::from("vault/note.md");
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        // Autosave burst, including a delete-and-recreate save
+        coalescer.push(vec![FileEvent::Modified(note.clone())], at(0));
+        coalescer.push(vec![FileEvent::Modified(note.clone()), FileEvent::Deleted(note.clone())], at(500));
+        coalescer.push(vec![FileEvent::Created(note.clone())], at(1000));
+
+        // Still inside the window of the latest event
+        assert!(coalescer.ready(at(2500)).is_empty());
+
+        let ready = coalescer.ready(at(3000));
+        assert_eq!(ready.len(), 1);
+        assert!(matches!(&ready[0], FileEvent::Created(p) if p == &note));
+        assert!(coalescer.ready(at(10_000)).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_terminates_on_symlink_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("notes");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(nested.join("a.md"), "hello").unwrap();
+        // notes/loop -> vault root
+        std::os::unix::fs::symlink(dir.path(), nested.join("loop")).unwrap();
+
+        let follow = ScanOptions { follow_symlinks: true, ..Default::default() };
+        assert_eq!(scan_directory(dir.path(), &follow).len(), 1);
+
+        let ignore = ScanOptions { follow_symlinks: false, ..Default::default() };
+        assert_eq!(scan_directory(dir.path(), &ignore).len(), 1);
+    }
+
+    #[test]
+    fn test_hidden_directory_is_scanned_only_when_allowlisted() {
+        let dir = tempfile::tempdir().unwrap();
+        for folder in [".notes", ".obsidian"] {
+            std::fs::create_dir_all(dir.path().join(folder)).unwrap();
+            std::fs::write(dir.path().join(folder).join("a.md"), "hello").unwrap();
+        }
+        std::fs::write(dir.path().join("visible.md"), "hello").unwrap();
+
+        let default = ScanOptions::default();
+        assert_eq!(scan_directory(dir.path(), &default), vec![dir.path().join("visible.md")]);
+
+        let allowed = ScanOptions { allowed_hidden_dirs: vec![".notes".to_string()], ..Default::default() };
+        let mut files = scan_directory(dir.path(), &allowed);
+        files.sort();
+        assert_eq!(files, vec![dir.path().join(".notes").join("a.md"), dir.path().join("visible.md")]);
+    }
+
+    #[test]
+    fn test_is_indexable_file() {
+        assert!(is_indexable_file(Path::new("board.canvas")));
+        assert!(is_indexable_file(Path::new("note.md")));
+        assert!(!is_indexable_file(Path::new("data.json")));
+    }
 }
 
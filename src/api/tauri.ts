@@ -1,7 +1,35 @@
 import { invoke } from "@tauri-apps/api/tauri";
 import { listen } from "@tauri-apps/api/event";
-import { open } from "@tauri-apps/api/dialog";
-import { ChatMessage, Settings, SyncStatus, Artifact } from "../types";
+import { open, save } from "@tauri-apps/api/dialog";
+import {
+  ChatMessage,
+  AnswerStyle,
+  Settings,
+  SyncStatus,
+  Artifact,
+  TextEmbedding,
+  DimensionMismatch,
+  DiagnosticsReport,
+  FuzzyMatch,
+  WarmupReport,
+  SyncEstimate,
+  HashVerification,
+  IntegrityReport,
+  ArtifactMerge,
+  SourceUsage,
+  SearchHit,
+  QueryCoverage,
+  ChatReply,
+  NoteComparison,
+  CachePruneReport,
+  ModelComparison,
+  ArtifactDetails,
+  EmbeddingBenchmark,
+  EmbeddingModelInfo,
+  NoisyChunk,
+  EndpointCheck,
+  NoteHit,
+} from "../types";
 
 // Settings Commands
 export async function getSettings(): Promise<Settings> {
@@ -12,9 +40,78 @@ export async function saveSettings(settings: Settings): Promise<void> {
   return invoke("save_settings", { settings });
 }
 
+export async function resetSettings(keepVaultPaths = true): Promise<Settings> {
+  return invoke<Settings>("reset_settings", { keepVaultPaths });
+}
+
+export async function getDimensionMismatch(): Promise<DimensionMismatch | null> {
+  return invoke<DimensionMismatch | null>("get_dimension_mismatch");
+}
+
+export async function runDiagnostics(): Promise<DiagnosticsReport> {
+  return invoke<DiagnosticsReport>("run_diagnostics");
+}
+
+export async function validateEndpoint(url: string): Promise<EndpointCheck> {
+  return invoke<EndpointCheck>("validate_endpoint", { url });
+}
+
+export async function benchmarkEmbedding(sampleCount: number): Promise<EmbeddingBenchmark> {
+  return invoke<EmbeddingBenchmark>("benchmark_embedding", { sampleCount });
+}
+
+export async function getEmbeddingModelInfo(): Promise<EmbeddingModelInfo> {
+  return invoke<EmbeddingModelInfo>("get_embedding_model_info");
+}
+
+export async function checkIntegrity(repair: boolean): Promise<IntegrityReport> {
+  return invoke<IntegrityReport>("check_integrity", { repair });
+}
+
+export async function deduplicateArtifacts(): Promise<ArtifactMerge[]> {
+  return invoke<ArtifactMerge[]>("deduplicate_artifacts");
+}
+
 // Chat Commands
-export async function sendMessage(query: string): Promise<void> {
-  return invoke("send_message", { query });
+export async function sendMessage(query: string, style?: AnswerStyle): Promise<ChatReply> {
+  return invoke<ChatReply>("send_message", { query, style });
+}
+
+export async function previewPrompt(query: string, style?: AnswerStyle): Promise<string> {
+  return invoke<string>("preview_prompt", { query, style });
+}
+
+export async function searchRecent(
+  query: string,
+  sinceTimestamp: number,
+  limit: number,
+  explain = false
+): Promise<SearchHit[]> {
+  return invoke<SearchHit[]>("search_recent", { query, sinceTimestamp, limit, explain });
+}
+
+export async function searchWithinIds(
+  query: string,
+  artifactIds: string[],
+  limit: number
+): Promise<SearchHit[]> {
+  return invoke<SearchHit[]>("search_within_ids", { query, artifactIds, limit });
+}
+
+export async function groupedSearch(query: string, limit: number): Promise<NoteHit[]> {
+  return invoke<NoteHit[]>("grouped_search", { query, limit });
+}
+
+export async function compareNotes(idA: string, idB: string): Promise<NoteComparison> {
+  return invoke<NoteComparison>("compare_notes", { idA, idB });
+}
+
+export async function coverageReport(queries: string[]): Promise<QueryCoverage[]> {
+  return invoke<QueryCoverage[]>("coverage_report", { queries });
+}
+
+export async function summarizeArtifact(id: string, save: boolean): Promise<string> {
+  return invoke<string>("summarize_artifact", { id, save });
 }
 
 export async function getChatHistory(): Promise<ChatMessage[]> {
@@ -25,23 +122,112 @@ export async function clearChat(): Promise<void> {
   return invoke("clear_chat");
 }
 
+export async function exportChat(path: string): Promise<void> {
+  return invoke("export_chat", { path });
+}
+
+export async function warmup(): Promise<WarmupReport> {
+  return invoke<WarmupReport>("warmup");
+}
+
+export async function embedText(text: string): Promise<TextEmbedding> {
+  return invoke<TextEmbedding>("embed_text", { text });
+}
+
+export async function compareEmbeddingModels(
+  modelA: string,
+  modelB: string,
+  queries: string[],
+  sampleNotes?: number,
+  topK?: number
+): Promise<ModelComparison> {
+  return invoke<ModelComparison>("compare_embedding_models", { modelA, modelB, queries, sampleNotes, topK });
+}
+
 // Sync Commands
-export async function syncVault(vaultPath: string): Promise<SyncStatus> {
-  return invoke<SyncStatus>("sync_vault", { vaultPath });
+export async function syncVaults(vaultPaths: string[]): Promise<SyncStatus> {
+  return invoke<SyncStatus>("sync_vaults", { vaultPaths });
+}
+
+export async function estimateSync(vaultPath: string): Promise<SyncEstimate> {
+  return invoke<SyncEstimate>("estimate_sync", { vaultPath });
 }
 
 export async function getSyncStatus(): Promise<SyncStatus> {
   return invoke<SyncStatus>("get_sync_status");
 }
 
+export async function repairEmbeddings(): Promise<number> {
+  return invoke<number>("repair_embeddings");
+}
+
+export async function verifyHashes(repair: boolean): Promise<HashVerification> {
+  return invoke<HashVerification>("verify_hashes", { repair });
+}
+
+export async function pruneEmbeddingCache(): Promise<CachePruneReport> {
+  return invoke<CachePruneReport>("prune_embedding_cache");
+}
+
+export async function pauseIndexing(): Promise<void> {
+  return invoke("pause_indexing");
+}
+
+export async function resumeIndexing(): Promise<void> {
+  return invoke("resume_indexing");
+}
+
+export async function isIndexingPaused(): Promise<boolean> {
+  return invoke<boolean>("is_indexing_paused");
+}
+
 export async function getArtifacts(): Promise<Artifact[]> {
   return invoke<Artifact[]>("get_artifacts");
 }
 
+export async function getArtifact(id: string): Promise<ArtifactDetails> {
+  return invoke<ArtifactDetails>("get_artifact", { id });
+}
+
+export async function getArtifactsChangedSince(timestamp: number): Promise<ArtifactDetails[]> {
+  return invoke<ArtifactDetails[]>("get_artifacts_changed_since", { timestamp });
+}
+
+export async function fuzzyFindArtifacts(query: string, limit: number): Promise<FuzzyMatch[]> {
+  return invoke<FuzzyMatch[]>("fuzzy_find_artifacts", { query, limit });
+}
+
+export async function getTopSources(
+  limit: number,
+  since?: number
+): Promise<SourceUsage[]> {
+  return invoke<SourceUsage[]>("get_top_sources", { limit, since: since ?? null });
+}
+
+export async function rateMessage(messageId: number, helpful: boolean): Promise<void> {
+  return invoke("rate_message", { messageId, helpful });
+}
+
+export async function getNoisyChunks(limit: number): Promise<NoisyChunk[]> {
+  return invoke<NoisyChunk[]>("get_noisy_chunks", { limit });
+}
+
+export async function getTags(): Promise<[string, number][]> {
+  return invoke<[string, number][]>("get_tags");
+}
+
+export async function setArtifactPinned(id: string, pinned: boolean): Promise<void> {
+  return invoke("set_artifact_pinned", { id, pinned });
+}
+
 export async function deleteArtifact(id: string): Promise<void> {
   return invoke("delete_artifact", { id });
 }
 
+export async function deleteArtifactsByPrefix(prefix: string): Promise<number> {
+  return invoke<number>("delete_artifacts_by_prefix", { prefix });
+}
+
 // Outline Sync Commands
 export async function syncOutline(): Promise<SyncStatus> {
   return invoke<SyncStatus>("sync_outline");
@@ -57,18 +243,44 @@ export async function selectFolder(): Promise<string | null> {
   return selected as string | null;
 }
 
+export async function selectExportPath(): Promise<string | null> {
+  return save({
+    title: "Export Chat",
+    defaultPath: "Chat transcript.md",
+    filters: [{ name: "Markdown", extensions: ["md"] }],
+  });
+}
+
 // Event Listeners
 export type StreamChunkPayload = {
   content: string;
   done: boolean;
 };
 
+export type GenerationStatsPayload = {
+  timeToFirstTokenMs: number | null;
+  totalTimeMs: number;
+  promptTokens: number | null;
+  completionTokens: number | null;
+  tokensPerSecond: number | null;
+};
+
+export type GroundingWarningPayload = {
+  relevantChunks: number;
+};
+
 export type SyncProgressPayload = {
   processed: number;
   total: number;
   currentFile: string;
 };
 
+export type EmbeddingPercentPayload = {
+  percent: number;
+  embeddedChunks: number;
+  totalChunks: number;
+};
+
 export type OutlineSyncProgressPayload = {
   processed: number;
   total: number;
@@ -83,6 +295,22 @@ export function onStreamChunk(
   });
 }
 
+export function onGenerationStats(
+  callback: (payload: GenerationStatsPayload) => void
+): Promise<() => void> {
+  return listen<GenerationStatsPayload>("generation-stats", (event) => {
+    callback(event.payload);
+  });
+}
+
+export function onGroundingWarning(
+  callback: (payload: GroundingWarningPayload) => void
+): Promise<() => void> {
+  return listen<GroundingWarningPayload>("grounding-warning", (event) => {
+    callback(event.payload);
+  });
+}
+
 export function onSyncProgress(
   callback: (payload: SyncProgressPayload) => void
 ): Promise<() => void> {
@@ -91,6 +319,14 @@ export function onSyncProgress(
   });
 }
 
+export function onEmbeddingPercent(
+  callback: (payload: EmbeddingPercentPayload) => void
+): Promise<() => void> {
+  return listen<EmbeddingPercentPayload>("embedding-percent", (event) => {
+    callback(event.payload);
+  });
+}
+
 export function onSyncComplete(
   callback: (payload: SyncStatus) => void
 ): Promise<() => void> {
@@ -1,4 +1,4 @@
-import { onStreamChunk } from "@/api/tauri";
+import { exportChat, onStreamChunk, selectExportPath } from "@/api/tauri";
 import { Badge } from "@/components/ui/badge";
 import { Button } from "@/components/ui/button";
 import { Card, CardContent } from "@/components/ui/card";
@@ -11,11 +11,13 @@ import { ScrollArea } from "@/components/ui/scroll-area";
 import { Textarea } from "@/components/ui/textarea";
 import { cn } from "@/lib/utils";
 import { useChatStore } from "@/stores/chatStore";
-import { ChatMessage } from "@/types";
+import { ChatMessage, SourceCitation, isAppError } from "@/types";
+import { open } from "@tauri-apps/api/shell";
 import { invoke } from "@tauri-apps/api/tauri";
 import {
   Bot,
   ChevronDown,
+  Download,
   FileText,
   Loader2,
   Send,
@@ -107,7 +109,11 @@ export function Chat() {
       await invoke("send_message", { query: trimmedInput });
     } catch (error) {
       console.error("Failed to send message:", error);
-      toast.error("Failed to send message. Make sure Ollama is running.");
+      if (isAppError(error) && error.code === "provider_unreachable") {
+        toast.error("Failed to send message. Make sure Ollama is running.");
+      } else {
+        toast.error(isAppError(error) ? error.message : "Failed to send message");
+      }
       setStreaming(false);
     }
   };
@@ -123,6 +129,18 @@ export function Chat() {
     }
   };
 
+  const handleExport = async () => {
+    try {
+      const path = await selectExportPath();
+      if (!path) return;
+      await exportChat(path);
+      toast.success("Chat exported");
+    } catch (error) {
+      console.error("Failed to export chat:", error);
+      toast.error(isAppError(error) ? error.message : "Failed to export chat");
+    }
+  };
+
   const handleKeyDown = (e: React.KeyboardEvent<HTMLTextAreaElement>) => {
     if (e.key === "Enter" && !e.shiftKey) {
       e.preventDefault();
@@ -145,15 +163,26 @@ export function Chat() {
             </p>
           </div>
         </div>
-        <Button
-          variant="ghost"
-          size="sm"
-          onClick={handleClear}
-          disabled={messages.length === 0}
-        >
-          <Trash2 className="h-4 w-4" />
-          Clear
-        </Button>
+        <div className="flex items-center gap-1">
+          <Button
+            variant="ghost"
+            size="sm"
+            onClick={handleExport}
+            disabled={messages.length === 0}
+          >
+            <Download className="h-4 w-4" />
+            Export
+          </Button>
+          <Button
+            variant="ghost"
+            size="sm"
+            onClick={handleClear}
+            disabled={messages.length === 0}
+          >
+            <Trash2 className="h-4 w-4" />
+            Clear
+          </Button>
+        </div>
       </div>
 
       {/* Messages */}
@@ -316,14 +345,7 @@ function MessageContent({ content }: { content: string }) {
   );
 }
 
-interface Source {
-  path: string;
-  title: string;
-  chunk: string;
-  similarity: number;
-}
-
-function SourcesCitation({ sources }: { sources: Source[] }) {
+function SourcesCitation({ sources }: { sources: SourceCitation[] }) {
   return (
     <Collapsible className="w-full mt-2">
       <CollapsibleTrigger asChild>
@@ -339,9 +361,20 @@ function SourcesCitation({ sources }: { sources: Source[] }) {
             <Card key={i} className="py-2">
               <CardContent className="p-0 px-3">
                 <div className="flex items-center justify-between mb-1">
-                  <span className="text-xs font-medium truncate">
-                    {source.title || source.path}
-                  </span>
+                  {source.path.includes("://") ? (
+                    <span className="text-xs font-medium truncate">
+                      {source.title || source.path}
+                    </span>
+                  ) : (
+                    <button
+                      type="button"
+                      title={source.path}
+                      onClick={() => open(source.path).catch(console.error)}
+                      className="text-xs font-medium truncate hover:underline text-left"
+                    >
+                      {source.title || source.path}
+                    </button>
+                  )}
                   <Badge variant="secondary" className="text-xs">
                     {Math.round(source.similarity * 100)}%
                   </Badge>
@@ -1,4 +1,4 @@
-import { onOutlineSyncComplete, onOutlineSyncProgress, onSyncComplete, onSyncProgress, selectFolder, syncVault } from "@/api/tauri";
+import { onOutlineSyncComplete, onOutlineSyncProgress, onSyncComplete, onSyncProgress, selectFolder, syncVaults } from "@/api/tauri";
 import {
   AlertDialog,
   AlertDialogAction,
@@ -27,7 +27,7 @@ import { Separator } from "@/components/ui/separator";
 import { useArtifacts, useDeleteArtifact, useSyncOutline } from "@/queries/sync";
 import { useSettingsStore } from "@/stores/settingsStore";
 import { useSyncStore } from "@/stores/syncStore";
-import { Artifact, Settings as SettingsType } from "@/types";
+import { Artifact, Settings as SettingsType, isAppError } from "@/types";
 import { invoke } from "@tauri-apps/api/tauri";
 import {
   AlertCircle,
@@ -44,6 +44,7 @@ import {
   Save,
   Server,
   Trash2,
+  X,
 } from "lucide-react";
 import { useEffect, useState } from "react";
 import { toast } from "sonner";
@@ -148,8 +149,8 @@ export function Settings() {
   const handleSelectFolder = async () => {
     try {
       const selected = await selectFolder();
-      if (selected) {
-        setLocalSettings((prev) => ({ ...prev, vaultPath: selected }));
+      if (selected && !localSettings.vaultPaths.includes(selected)) {
+        setLocalSettings((prev) => ({ ...prev, vaultPaths: [...prev.vaultPaths, selected] }));
       }
     } catch (error) {
       console.error("Failed to select folder:", error);
@@ -157,6 +158,13 @@ export function Settings() {
     }
   };
 
+  const handleRemoveVault = (path: string) => {
+    setLocalSettings((prev) => ({
+      ...prev,
+      vaultPaths: prev.vaultPaths.filter((p) => p !== path),
+    }));
+  };
+
   const handleSave = async () => {
     setIsSaving(true);
     try {
@@ -165,25 +173,29 @@ export function Settings() {
       toast.success("Settings saved successfully!");
     } catch (error) {
       console.error("Failed to save settings:", error);
-      toast.error("Failed to save settings");
+      toast.error(isAppError(error) ? error.message : "Failed to save settings");
     } finally {
       setIsSaving(false);
     }
   };
 
   const handleSync = async () => {
-    if (!localSettings.vaultPath) {
-      toast.error("Please select a vault path first");
+    if (localSettings.vaultPaths.length === 0) {
+      toast.error("Please add a vault path first");
       return;
     }
 
     try {
       setStatus({ isRunning: true, error: null });
-      await syncVault(localSettings.vaultPath);
+      await syncVaults(localSettings.vaultPaths);
     } catch (error) {
       console.error("Failed to sync vault:", error);
-      toast.error("Failed to sync vault");
-      setStatus({ isRunning: false, error: String(error) });
+      let message = isAppError(error) ? error.message : "Failed to sync vault";
+      if (isAppError(error) && error.code === "provider_unreachable") {
+        message = "Failed to sync vault. Make sure Ollama is running.";
+      }
+      toast.error(message);
+      setStatus({ isRunning: false, error: message });
     }
   };
 
@@ -208,8 +220,16 @@ export function Settings() {
       await syncOutlineMutation.mutateAsync();
     } catch (error) {
       console.error("Failed to sync Outline:", error);
-      toast.error("Failed to sync Outline");
-      setOutlineSyncStatus((prev) => ({ ...prev, isRunning: false, error: String(error) }));
+      let message = isAppError(error) ? error.message : "Failed to sync Outline";
+      if (isAppError(error) && error.code === "outline_auth") {
+        message = "Outline rejected the API key. Check it and try again.";
+      } else if (isAppError(error) && error.code === "outline_unreachable") {
+        message = `Could not reach Outline at ${localSettings.outlineBaseUrl}`;
+      } else if (isAppError(error) && error.code === "provider_unreachable") {
+        message = "Failed to sync Outline. Make sure Ollama is running.";
+      }
+      toast.error(message);
+      setOutlineSyncStatus((prev) => ({ ...prev, isRunning: false, error: message }));
     }
   };
 
@@ -238,17 +258,29 @@ export function Settings() {
                 Knowledge Vault
               </CardTitle>
               <CardDescription>
-                Select your Obsidian vault or notes directory to index
+                Select your Obsidian vaults or notes directories to index
               </CardDescription>
             </CardHeader>
             <CardContent className="space-y-4">
               <div className="space-y-2">
-                <Label htmlFor="vault-path">Vault Path</Label>
+                <Label htmlFor="vault-path">Vault Paths</Label>
+                {localSettings.vaultPaths.map((path) => (
+                  <div key={path} className="flex gap-2">
+                    <Input value={path} readOnly className="flex-1" />
+                    <Button
+                      variant="outline"
+                      size="icon"
+                      onClick={() => handleRemoveVault(path)}
+                    >
+                      <X className="h-4 w-4" />
+                    </Button>
+                  </div>
+                ))}
                 <div className="flex gap-2">
                   <Input
                     id="vault-path"
-                    value={localSettings.vaultPath}
-                    placeholder="Select a folder..."
+                    value=""
+                    placeholder="Add a folder..."
                     readOnly
                     className="flex-1"
                   />
@@ -308,7 +340,7 @@ export function Settings() {
 
                 <Button
                   onClick={handleSync}
-                  disabled={status.isRunning || !localSettings.vaultPath}
+                  disabled={status.isRunning || localSettings.vaultPaths.length === 0}
                   className="w-full"
                   variant="outline"
                 >
@@ -395,6 +427,23 @@ export function Settings() {
                   The URL where your Ollama server is running
                 </p>
               </div>
+              <div className="space-y-2">
+                <Label htmlFor="embedding-endpoint">Embedding Endpoint</Label>
+                <Input
+                  id="embedding-endpoint"
+                  value={localSettings.embeddingEndpoint}
+                  onChange={(e) =>
+                    setLocalSettings((prev) => ({
+                      ...prev,
+                      embeddingEndpoint: e.target.value,
+                    }))
+                  }
+                  placeholder={localSettings.ollamaEndpoint}
+                />
+                <p className="text-xs text-muted-foreground">
+                  Server running the embedding model, if not the one above
+                </p>
+              </div>
             </CardContent>
           </Card>
 
@@ -1,5 +1,5 @@
 import { useQuery, useMutation, useQueryClient } from "@tanstack/react-query";
-import { getSyncStatus, syncVault, getArtifacts, deleteArtifact, syncOutline } from "../api/tauri";
+import { getSyncStatus, syncVaults, getArtifacts, deleteArtifact, syncOutline } from "../api/tauri";
 
 export const syncKeys = {
   all: ["sync"] as const,
@@ -25,11 +25,11 @@ export function useArtifacts() {
   });
 }
 
-export function useSyncVault() {
+export function useSyncVaults() {
   const queryClient = useQueryClient();
 
   return useMutation({
-    mutationFn: (vaultPath: string) => syncVault(vaultPath),
+    mutationFn: (vaultPaths: string[]) => syncVaults(vaultPaths),
     onSuccess: () => {
       queryClient.invalidateQueries({ queryKey: syncKeys.all });
     },
@@ -9,6 +9,7 @@ export interface ChatMessage {
 }
 
 export interface SourceCitation {
+  artifactId: string;
   path: string;
   title: string;
   chunk: string;
@@ -21,21 +22,83 @@ export interface Artifact {
   lastModified: number;
   contentHash: string;
   indexedAt: number;
+  title: string | null;
+  tags: string[];
+  pinned: boolean;
+  vault: string | null;
+  collectionId: string | null;
+  collection: string | null;
+}
+
+export interface ArtifactDetails {
+  artifact: Artifact;
+  chunkCount: number;
+}
+
+export interface FuzzyMatch {
+  artifact: Artifact;
+  score: number;
 }
 
 export interface Settings {
-  vaultPath: string;
+  vaultPaths: string[];
+  llmProvider: string;
   ollamaEndpoint: string;
+  embeddingEndpoint: string;
   ollamaModel: string;
   embeddingModel: string;
   outlineApiKey: string;
   outlineBaseUrl: string;
+  minWordCount: number;
+  snapChunkOverlap: boolean;
+  chunkStrategy: "words" | "tokens";
+  chunkMaxTokens: number;
+  embeddingDocumentPrefix: string;
+  embeddingQueryPrefix: string;
+  embeddingRequestFormat: "prompt" | "input" | "input_array";
+  embeddingResponseFormat: "embedding" | "embeddings" | "data";
+  embeddingMaxInputBytes: number;
+  maxFileSizeMb: number;
+  followSymlinks: boolean;
+  includeLinkUrls: boolean;
+  includeImageSources: boolean;
+  lowercaseTags: boolean;
+  indexAttachments: boolean;
+  retrievalBoosts: Record<string, number>;
+  similarityMetric: "cosine" | "dot_product" | "euclidean";
+  similarityThreshold: number | null;
+  subquerySimilarityFloor: number | null;
+  nearDuplicateThreshold: number;
+  crossSourceDedup: boolean;
+  sourcePriority: string[];
+  groundedAnswersOnly: boolean;
+  trackRetrieval: boolean;
+  maxTokens: number | null;
+  contextWindowTokens: number;
+  stopSequences: string[];
+  seed: number | null;
+  fuzzyKeywordFallback: boolean;
+  fuzzyMatchThreshold: number;
+  searchCacheSize: number;
+  enabledSources: Record<string, boolean>;
+  embeddingEncoding: "f32" | "int8";
+  reindexDebounceMs: number;
+  responseLanguage: string;
+  caseInsensitivePaths: boolean;
+  useChatContext: boolean;
+  allowedHiddenDirs: string[];
+  trashFolder: string;
+  quietUnchangedFiles: boolean;
+  maxQueryExpansions: number;
+  expansionMinEmbeddings: number;
 }
 
 export interface SyncStatus {
   isRunning: boolean;
   totalFiles: number;
   processedFiles: number;
+  skippedFiles: number;
+  resumedFiles: number;
   lastSyncAt: number | null;
   error: string | null;
 }
@@ -47,13 +110,256 @@ export interface EmbeddingChunk {
   content: string;
 }
 
+export interface TextEmbedding {
+  model: string;
+  dimension: number;
+  embedding: number[];
+}
+
+export interface DimensionMismatch {
+  model: string;
+  expectedDimension: number;
+  storedDimension: number;
+}
+
+export interface DiagnosticStep {
+  name: string;
+  passed: boolean;
+  message: string;
+}
+
+export interface DiagnosticsReport {
+  passed: boolean;
+  steps: DiagnosticStep[];
+}
+
+export interface EmbeddingBenchmark {
+  sampleCount: number;
+  totalMs: number;
+  p50Ms: number;
+  p95Ms: number;
+  embedsPerSecond: number;
+}
+
+export interface EmbeddingModelInfo {
+  model: string;
+  dimension: number;
+  norm: number;
+  normalized: boolean;
+}
+
+export interface EndpointCheck {
+  normalized: string | null;
+  wellFormed: boolean;
+  reachable: boolean;
+  message: string;
+}
+
+export interface WarmupStep {
+  name: string;
+  durationMs: number;
+  error: string | null;
+}
+
+export interface WarmupReport {
+  steps: WarmupStep[];
+  totalMs: number;
+}
+
+export interface SyncEstimate {
+  totalFiles: number;
+  skippedFiles: number;
+  totalChunks: number;
+  sampledChunks: number;
+  chunksPerSecond: number | null;
+  estimatedSeconds: number | null;
+}
+
+export interface HashMismatch {
+  path: string;
+  storedHash: string;
+  currentHash: string;
+}
+
+export interface HashVerification {
+  checked: number;
+  mismatches: HashMismatch[];
+  repaired: number;
+}
+
+export interface IntegrityReport {
+  integrityOk: boolean;
+  integrityErrors: string[];
+  orphanedEmbeddings: number;
+  invalidEmbeddings: number;
+  duplicateChunks: number;
+  repaired: boolean;
+}
+
+export interface ArtifactMerge {
+  keptPath: string;
+  removedPaths: string[];
+  removedEmbeddings: number;
+}
+
+export interface SourceUsage {
+  artifact: Artifact;
+  retrievals: number;
+}
+
+export interface NoisyChunk {
+  artifact: Artifact;
+  chunkIndex: number;
+  content: string;
+  retrievals: number;
+  upvotes: number;
+  downvotes: number;
+}
+
+export interface SearchHit {
+  artifactId: string;
+  path: string;
+  title: string | null;
+  chunkIndex: number;
+  content: string;
+  similarity: number;
+  matchedTerms?: string[];
+}
+
+export interface NoteHit {
+  artifactId: string;
+  path: string;
+  title: string | null;
+  chunkIndex: number;
+  snippet: string;
+  similarity: number;
+  matchingChunks: number;
+}
+
+export interface ChunkPair {
+  chunkIndexA: number;
+  contentA: string;
+  chunkIndexB: number;
+  contentB: string;
+  similarity: number;
+}
+
+export interface NoteComparison {
+  similarity: number;
+  bestPair: ChunkPair;
+}
+
+export type AnswerStyle = "concise" | "detailed" | "bullet";
+
+export interface ChatReply {
+  messageId: number;
+  content: string;
+  sources: SearchHit[];
+}
+
+export interface QueryCoverage {
+  query: string;
+  topScore: number | null;
+  bestMatch: SearchHit | null;
+  covered: boolean;
+}
+
+export interface QueryAgreement {
+  query: string;
+  topA: string[];
+  topB: string[];
+  agreement: number;
+}
+
+export interface ModelComparison {
+  modelA: string;
+  modelB: string;
+  sampledChunks: number;
+  queries: QueryAgreement[];
+  meanAgreement: number;
+}
+
+export interface CachePruneReport {
+  removedEntries: number;
+  reclaimedBytes: number;
+  skipped: boolean;
+}
+
+// Error returned by every command; `code` is stable, `message` is for display
+export interface AppError {
+  code:
+    | "provider_unreachable"
+    | "provider_error"
+    | "unknown_provider"
+    | "outline_auth"
+    | "outline_unreachable"
+    | "outline_error"
+    | "not_found"
+    | "no_content"
+    | "wrong_passphrase"
+    | "database"
+    | "file_too_large"
+    | "file_error"
+    | "invalid_input"
+    | "sync_running";
+  message: string;
+}
+
+export function isAppError(error: unknown): error is AppError {
+  return typeof error === "object" && error !== null && "code" in error && "message" in error;
+}
+
 // Default settings
 export const DEFAULT_SETTINGS: Settings = {
-  vaultPath: "",
+  vaultPaths: [],
+  llmProvider: "ollama",
   ollamaEndpoint: "http://localhost:11434",
+  embeddingEndpoint: "",
   ollamaModel: "llama3.2",
   embeddingModel: "nomic-embed-text",
   outlineApiKey: "",
   outlineBaseUrl: "https://app.getoutline.com/api",
+  minWordCount: 3,
+  snapChunkOverlap: true,
+  chunkStrategy: "words",
+  chunkMaxTokens: 512,
+  embeddingDocumentPrefix: "",
+  embeddingQueryPrefix: "",
+  embeddingRequestFormat: "prompt",
+  embeddingResponseFormat: "embedding",
+  embeddingMaxInputBytes: 8192,
+  maxFileSizeMb: 5,
+  followSymlinks: false,
+  includeLinkUrls: false,
+  includeImageSources: false,
+  lowercaseTags: false,
+  indexAttachments: false,
+  retrievalBoosts: {},
+  similarityMetric: "cosine",
+  similarityThreshold: null,
+  subquerySimilarityFloor: null,
+  nearDuplicateThreshold: 0.97,
+  crossSourceDedup: false,
+  sourcePriority: [],
+  groundedAnswersOnly: false,
+  trackRetrieval: false,
+  maxTokens: null,
+  contextWindowTokens: 8192,
+  stopSequences: [],
+  seed: null,
+  fuzzyKeywordFallback: false,
+  fuzzyMatchThreshold: 0.5,
+  searchCacheSize: 0,
+  enabledSources: {},
+  embeddingEncoding: "f32",
+  reindexDebounceMs: 2000,
+  responseLanguage: "off",
+  caseInsensitivePaths: false,
+  useChatContext: true,
+  allowedHiddenDirs: [],
+  trashFolder: ".trash",
+  quietUnchangedFiles: false,
+  maxQueryExpansions: 3,
+  expansionMinEmbeddings: 200,
 };
 